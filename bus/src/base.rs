@@ -0,0 +1,107 @@
+use std::{fmt, sync::Arc};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures_util::stream::BoxStream;
+
+#[derive(Debug)]
+pub enum BusError {
+    Nats(String),
+    Redis(::redis::RedisError),
+    /// Nobody is subscribed to the subject of the request.
+    NoResponders,
+    /// Request wasn't answered in time.
+    Timeout,
+}
+
+impl fmt::Display for BusError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BusError::Nats(err) => write!(f, "NATS error: {}", err),
+            BusError::Redis(err) => write!(f, "Redis error: {}", err),
+            BusError::NoResponders => write!(f, "No responders for the request"),
+            BusError::Timeout => write!(f, "Request timed out"),
+        }
+    }
+}
+
+impl std::error::Error for BusError {}
+
+impl From<::redis::RedisError> for BusError {
+    fn from(err: ::redis::RedisError) -> Self {
+        BusError::Redis(err)
+    }
+}
+
+pub type BusResult<T> = Result<T, BusError>;
+
+/// Confirms that the message was processed, transports
+/// with at-least-once delivery redeliver unconfirmed messages.
+#[async_trait]
+pub(crate) trait Acker: Send + Sync {
+    async fn ack(&self) -> BusResult<()>;
+}
+
+/// Message received from the bus.
+#[derive(Clone)]
+pub struct BusMessage {
+    pub subject: String,
+    pub payload: Bytes,
+    /// Subject the answer to the request goes to
+    pub reply: Option<String>,
+    pub(crate) acker: Option<Arc<dyn Acker>>,
+}
+
+impl BusMessage {
+    pub(crate) fn new(subject: String, payload: Bytes, reply: Option<String>) -> Self {
+        Self {
+            subject,
+            payload,
+            reply,
+            acker: None,
+        }
+    }
+
+    /// Marks the message as processed, it is a no-op for the transports
+    /// that don't redeliver messages.
+    pub async fn ack(&self) -> BusResult<()> {
+        match &self.acker {
+            Some(acker) => acker.ack().await,
+            None => Ok(()),
+        }
+    }
+}
+
+impl fmt::Debug for BusMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BusMessage")
+            .field("subject", &self.subject)
+            .field("payload", &self.payload)
+            .field("reply", &self.reply)
+            .finish()
+    }
+}
+
+/// Stream of the messages of the subscription, ends when the bus is gone.
+pub type Subscription = BoxStream<'static, BusMessage>;
+
+#[async_trait]
+pub trait MessageBus: Send + Sync {
+    async fn publish(&self, subject: &str, payload: Bytes) -> BusResult<()>;
+
+    /// Every message of the subject goes to a single subscriber of the group.
+    async fn queue_subscribe(&self, subject: &str, group: &str) -> BusResult<Subscription>;
+
+    /// Every subscriber gets every message of the subject
+    /// published after it subscribed, messages are not acked.
+    async fn subscribe(&self, subject: &str) -> BusResult<Subscription>;
+
+    /// Publishes the request and waits for the first answer.
+    async fn request(&self, subject: &str, payload: Bytes) -> BusResult<Bytes>;
+
+    /// Drops what the bus keeps for the subjects nobody subscribes to anymore,
+    /// most transports keep nothing.
+    async fn remove_subjects(&self, _subjects: &[String]) -> BusResult<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,227 @@
+use std::{sync::Arc, time::Duration};
+
+use async_nats::jetstream::{
+    self,
+    consumer::{pull, AckPolicy},
+    stream::RetentionPolicy,
+};
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures_util::StreamExt;
+
+use crate::{
+    base::{Acker, BusError, BusMessage, BusResult, MessageBus, Subscription},
+    nats::NatsBus,
+};
+
+/// Stream with the messages of all durable subjects.
+const STREAM_NAME: &str = "HCWC";
+/// Messages nobody consumed are dropped after this long.
+const STREAM_MAX_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+/// Message unacked for this long is delivered again.
+const ACK_WAIT: Duration = Duration::from_secs(30);
+/// Consumer nobody pulls from for this long is removed,
+/// so consumers of the stopped servers don't pile up.
+const CONSUMER_INACTIVE_THRESHOLD: Duration = Duration::from_secs(60 * 60);
+
+/// Checks the subject against the NATS pattern with `*` and `>` wildcards.
+fn subject_matches(pattern: &str, subject: &str) -> bool {
+    let mut tokens = subject.split('.');
+    for expected in pattern.split('.') {
+        match (expected, tokens.next()) {
+            (">", Some(_)) => return true,
+            ("*", Some(_)) => {}
+            (expected, Some(token)) if expected == token => {}
+            _ => return false,
+        }
+    }
+    tokens.next().is_none()
+}
+
+/// Durable consumer name can't have dots and wildcards of the subject.
+fn consumer_name(subject: &str, group: &str) -> String {
+    format!("{}_{}", group, subject)
+        .chars()
+        .map(|c| match c {
+            '.' | '*' | '>' | ' ' => '_',
+            c => c,
+        })
+        .collect()
+}
+
+/// Bus on top of NATS with JetStream for the durable subjects.
+///
+/// Messages of the durable subjects are kept in the stream until the consumer
+/// of the group acks them, unacked messages are delivered again. The rest of
+/// the subjects and the requests go through core NATS like in `NatsBus`.
+///
+/// Stream is a work queue, so every durable subject can have a single group only.
+pub struct JetStreamBus {
+    core: NatsBus,
+    context: jetstream::Context,
+    stream: jetstream::stream::Stream,
+    subjects: Vec<String>,
+}
+
+impl JetStreamBus {
+    /// Connects and creates the stream for the `subjects` if it isn't there yet,
+    /// subjects may have wildcards.
+    pub async fn connect(url: &str, subjects: &[&str]) -> BusResult<Self> {
+        let core = NatsBus::connect(url).await?;
+        let context = jetstream::new(core.client.clone());
+        let subjects: Vec<String> = subjects.iter().map(|subject| subject.to_string()).collect();
+        let stream = context
+            .get_or_create_stream(jetstream::stream::Config {
+                name: STREAM_NAME.to_string(),
+                subjects: subjects.clone(),
+                retention: RetentionPolicy::WorkQueue,
+                max_age: STREAM_MAX_AGE,
+                ..Default::default()
+            })
+            .await
+            .map_err(|err| BusError::Nats(err.to_string()))?;
+
+        Ok(Self {
+            core,
+            context,
+            stream,
+            subjects,
+        })
+    }
+
+    fn is_durable(&self, subject: &str) -> bool {
+        self.subjects
+            .iter()
+            .any(|pattern| subject_matches(pattern, subject))
+    }
+}
+
+#[async_trait]
+impl MessageBus for JetStreamBus {
+    async fn publish(&self, subject: &str, payload: Bytes) -> BusResult<()> {
+        if !self.is_durable(subject) {
+            return self.core.publish(subject, payload).await;
+        }
+
+        // Publish is done once the stream has stored the message.
+        self.context
+            .publish(subject.to_string(), payload)
+            .await
+            .map_err(|err| BusError::Nats(err.to_string()))?
+            .await
+            .map_err(|err| BusError::Nats(err.to_string()))?;
+        Ok(())
+    }
+
+    async fn queue_subscribe(&self, subject: &str, group: &str) -> BusResult<Subscription> {
+        if !self.is_durable(subject) {
+            return self.core.queue_subscribe(subject, group).await;
+        }
+
+        let name = consumer_name(subject, group);
+        let consumer = self
+            .stream
+            .get_or_create_consumer(
+                &name,
+                pull::Config {
+                    durable_name: Some(name.clone()),
+                    filter_subject: subject.to_string(),
+                    ack_policy: AckPolicy::Explicit,
+                    ack_wait: ACK_WAIT,
+                    inactive_threshold: CONSUMER_INACTIVE_THRESHOLD,
+                    ..Default::default()
+                },
+            )
+            .await
+            .map_err(|err| BusError::Nats(err.to_string()))?;
+        let messages = consumer
+            .messages()
+            .await
+            .map_err(|err| BusError::Nats(err.to_string()))?;
+
+        Ok(messages
+            .filter_map(|msg| async move {
+                match msg {
+                    Ok(msg) => {
+                        // Reply subject of the stream message is for the ack only.
+                        let (msg, acker) = msg.split();
+                        let mut msg = BusMessage::new(msg.subject.to_string(), msg.payload, None);
+                        msg.acker = Some(Arc::new(JetStreamAcker(acker)));
+                        Some(msg)
+                    }
+                    Err(err) => {
+                        println!("Cannot receive message from JetStream: {}", err);
+                        None
+                    }
+                }
+            })
+            .boxed())
+    }
+
+    /// Messages of the durable subjects reach core subscribers too.
+    async fn subscribe(&self, subject: &str) -> BusResult<Subscription> {
+        self.core.subscribe(subject).await
+    }
+
+    async fn request(&self, subject: &str, payload: Bytes) -> BusResult<Bytes> {
+        self.core.request(subject, payload).await
+    }
+}
+
+/// Acks a single message of the stream.
+struct JetStreamAcker(jetstream::message::Acker);
+
+#[async_trait]
+impl Acker for JetStreamAcker {
+    async fn ack(&self) -> BusResult<()> {
+        self.0
+            .ack()
+            .await
+            .map_err(|err| BusError::Nats(err.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exact_subject_matches() {
+        assert!(subject_matches("message.publish", "message.publish"));
+        assert!(!subject_matches("message.publish", "message.published"));
+        assert!(!subject_matches("message.publish", "message"));
+        assert!(!subject_matches("message", "message.publish"));
+    }
+
+    #[test]
+    fn star_matches_single_token() {
+        assert!(subject_matches("message.*.send", "message.node.send"));
+        assert!(!subject_matches("message.*.send", "message.send"));
+        assert!(!subject_matches("message.*.send", "message.a.b.send"));
+        assert!(!subject_matches("message.*", "message"));
+    }
+
+    #[test]
+    fn tail_matches_one_or_more_tokens() {
+        assert!(subject_matches("message.>", "message.publish"));
+        assert!(subject_matches("message.>", "message.node.send"));
+        assert!(!subject_matches("message.>", "message"));
+        assert!(!subject_matches("message.>", "room.publish"));
+    }
+
+    #[test]
+    fn consumer_name_has_no_subject_separators() {
+        assert_eq!(
+            consumer_name("message.publish", "workers"),
+            "workers_message_publish"
+        );
+        assert_eq!(
+            consumer_name("message.*.>", "my group"),
+            "my_group_message____"
+        );
+        assert_ne!(
+            consumer_name("message.publish", "workers"),
+            consumer_name("message.publish", "archive")
+        );
+    }
+}
@@ -0,0 +1,78 @@
+//! Transport between the servers and the workers.
+
+use std::{str::FromStr, sync::Arc};
+
+mod base;
+mod jetstream;
+mod memory;
+mod nats;
+mod streams;
+
+pub use base::{BusError, BusMessage, BusResult, MessageBus, Subscription};
+pub use jetstream::JetStreamBus;
+pub use memory::InProcessBus;
+pub use nats::NatsBus;
+pub use streams::RedisStreamsBus;
+
+/// Transport the bus runs on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BusBackend {
+    Nats,
+    /// NATS with the durable subjects kept in a JetStream stream
+    JetStream,
+    Redis,
+}
+
+impl FromStr for BusBackend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "nats" => Ok(BusBackend::Nats),
+            "jetstream" => Ok(BusBackend::JetStream),
+            "redis" => Ok(BusBackend::Redis),
+            "memory" => Err(
+                "memory bus is not shared between processes, expected nats, jetstream or redis"
+                    .to_string(),
+            ),
+            _ => Err(format!(
+                "unknown bus backend `{}`, expected nats, jetstream or redis",
+                s
+            )),
+        }
+    }
+}
+
+/// Subjects kept in the stream with `jetstream` backend: client messages
+/// going to the workers and routed messages going to the servers.
+const JETSTREAM_SUBJECTS: &[&str] = &["message.publish", "message.*.send"];
+
+#[derive(Debug, Clone)]
+pub struct BusConfig {
+    pub backend: BusBackend,
+    /// Address of the NATS server, used by `nats` and `jetstream` backends
+    pub nats_url: String,
+    /// URL of the redis server, used by `redis` backend
+    pub redis_url: String,
+}
+
+impl Default for BusConfig {
+    fn default() -> Self {
+        Self {
+            backend: BusBackend::Nats,
+            nats_url: "localhost".to_string(),
+            redis_url: "redis://127.0.0.1/".to_string(),
+        }
+    }
+}
+
+/// Builds bus selected in the config.
+pub async fn connect(config: &BusConfig) -> BusResult<Arc<dyn MessageBus>> {
+    match config.backend {
+        BusBackend::Nats => Ok(Arc::new(NatsBus::connect(&config.nats_url).await?)),
+        BusBackend::JetStream => Ok(Arc::new(
+            JetStreamBus::connect(&config.nats_url, JETSTREAM_SUBJECTS).await?,
+        )),
+        BusBackend::Redis => Ok(Arc::new(RedisStreamsBus::connect(&config.redis_url).await?)),
+    }
+}
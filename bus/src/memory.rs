@@ -0,0 +1,133 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures_util::StreamExt;
+use tokio::sync::mpsc::{self, UnboundedSender};
+use tokio_stream::wrappers::UnboundedReceiverStream;
+
+use crate::base::{BusError, BusMessage, BusResult, MessageBus, Subscription};
+
+/// How long the request waits for the answer, same as the NATS client default.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Default)]
+struct QueueGroup {
+    subscribers: Vec<UnboundedSender<BusMessage>>,
+    /// Subscriber that gets the next message
+    next: usize,
+}
+
+impl QueueGroup {
+    /// Sends the message to the next live subscriber, dropped subscribers are removed.
+    fn deliver(&mut self, msg: BusMessage) -> bool {
+        while !self.subscribers.is_empty() {
+            let index = self.next % self.subscribers.len();
+            if self.subscribers[index].send(msg.clone()).is_ok() {
+                self.next = index + 1;
+                return true;
+            }
+            self.subscribers.remove(index);
+        }
+        false
+    }
+}
+
+/// Bus inside a single process built on tokio channels,
+/// server and worker share it to run without a broker.
+#[derive(Default)]
+pub struct InProcessBus {
+    /// Queue groups of every subject
+    subjects: Mutex<HashMap<String, HashMap<String, QueueGroup>>>,
+    next_inbox: AtomicUsize,
+    next_subscriber: AtomicUsize,
+}
+
+impl InProcessBus {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns `false` if nobody got the message.
+    fn deliver(&self, msg: BusMessage) -> bool {
+        let mut subjects = self.subjects.lock().unwrap();
+        let Some(groups) = subjects.get_mut(&msg.subject) else {
+            return false;
+        };
+
+        let mut delivered = false;
+        for group in groups.values_mut() {
+            delivered |= group.deliver(msg.clone());
+        }
+        groups.retain(|_, group| !group.subscribers.is_empty());
+        if groups.is_empty() {
+            subjects.remove(&msg.subject);
+        }
+        delivered
+    }
+
+    fn add_subscriber(&self, subject: String, group: String) -> Subscription {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        self.subjects
+            .lock()
+            .unwrap()
+            .entry(subject)
+            .or_default()
+            .entry(group)
+            .or_default()
+            .subscribers
+            .push(sender);
+        UnboundedReceiverStream::new(receiver).boxed()
+    }
+}
+
+#[async_trait]
+impl MessageBus for InProcessBus {
+    async fn publish(&self, subject: &str, payload: Bytes) -> BusResult<()> {
+        // Like core NATS, message without subscribers is dropped.
+        self.deliver(BusMessage::new(subject.to_string(), payload, None));
+        Ok(())
+    }
+
+    async fn queue_subscribe(&self, subject: &str, group: &str) -> BusResult<Subscription> {
+        Ok(self.add_subscriber(subject.to_string(), group.to_string()))
+    }
+
+    async fn subscribe(&self, subject: &str) -> BusResult<Subscription> {
+        // Every subscriber is a group of its own.
+        let group = format!(
+            "_SUB.{}",
+            self.next_subscriber.fetch_add(1, Ordering::Relaxed)
+        );
+        Ok(self.add_subscriber(subject.to_string(), group))
+    }
+
+    async fn request(&self, subject: &str, payload: Bytes) -> BusResult<Bytes> {
+        let inbox = format!("_INBOX.{}", self.next_inbox.fetch_add(1, Ordering::Relaxed));
+        let mut replies = self.add_subscriber(inbox.clone(), String::new());
+
+        let delivered = self.deliver(BusMessage::new(
+            subject.to_string(),
+            payload,
+            Some(inbox.clone()),
+        ));
+        if !delivered {
+            self.subjects.lock().unwrap().remove(&inbox);
+            return Err(BusError::NoResponders);
+        }
+
+        let reply = tokio::time::timeout(REQUEST_TIMEOUT, replies.next()).await;
+        self.subjects.lock().unwrap().remove(&inbox);
+        match reply {
+            Ok(Some(msg)) => Ok(msg.payload),
+            _ => Err(BusError::Timeout),
+        }
+    }
+}
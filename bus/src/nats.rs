@@ -0,0 +1,76 @@
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures_util::StreamExt;
+
+use crate::base::{BusError, BusMessage, BusResult, MessageBus, Subscription};
+
+#[derive(Clone)]
+pub struct NatsBus {
+    pub(crate) client: async_nats::Client,
+}
+
+impl NatsBus {
+    pub async fn connect(url: &str) -> BusResult<Self> {
+        let client = async_nats::connect(url)
+            .await
+            .map_err(|err| BusError::Nats(err.to_string()))?;
+        Ok(Self { client })
+    }
+}
+
+#[async_trait]
+impl MessageBus for NatsBus {
+    async fn publish(&self, subject: &str, payload: Bytes) -> BusResult<()> {
+        self.client
+            .publish(subject.to_string(), payload)
+            .await
+            .map_err(|err| BusError::Nats(err.to_string()))
+    }
+
+    async fn queue_subscribe(&self, subject: &str, group: &str) -> BusResult<Subscription> {
+        let subscriber = self
+            .client
+            .queue_subscribe(subject.to_string(), group.to_string())
+            .await
+            .map_err(|err| BusError::Nats(err.to_string()))?;
+
+        Ok(subscriber
+            .map(|msg| {
+                BusMessage::new(
+                    msg.subject.to_string(),
+                    msg.payload,
+                    msg.reply.map(|reply| reply.to_string()),
+                )
+            })
+            .boxed())
+    }
+
+    async fn subscribe(&self, subject: &str) -> BusResult<Subscription> {
+        let subscriber = self
+            .client
+            .subscribe(subject.to_string())
+            .await
+            .map_err(|err| BusError::Nats(err.to_string()))?;
+
+        Ok(subscriber
+            .map(|msg| {
+                BusMessage::new(
+                    msg.subject.to_string(),
+                    msg.payload,
+                    msg.reply.map(|reply| reply.to_string()),
+                )
+            })
+            .boxed())
+    }
+
+    async fn request(&self, subject: &str, payload: Bytes) -> BusResult<Bytes> {
+        match self.client.request(subject.to_string(), payload).await {
+            Ok(msg) => Ok(msg.payload),
+            Err(err) => match err.kind() {
+                async_nats::RequestErrorKind::NoResponders => Err(BusError::NoResponders),
+                async_nats::RequestErrorKind::TimedOut => Err(BusError::Timeout),
+                async_nats::RequestErrorKind::Other => Err(BusError::Nats(err.to_string())),
+            },
+        }
+    }
+}
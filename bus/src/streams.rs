@@ -0,0 +1,376 @@
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures_util::StreamExt;
+use redis::{
+    aio::MultiplexedConnection,
+    streams::{
+        StreamAutoClaimOptions, StreamAutoClaimReply, StreamId, StreamInfoConsumersReply,
+        StreamMaxlen, StreamRangeReply, StreamReadOptions, StreamReadReply,
+    },
+    AsyncCommands,
+};
+use tokio::sync::mpsc::{self, UnboundedSender};
+use tokio_stream::wrappers::UnboundedReceiverStream;
+
+use crate::base::{Acker, BusError, BusMessage, BusResult, MessageBus, Subscription};
+
+/// Streams keep only about this many newest entries.
+///
+/// Trim doesn't look at the consumer groups, an entry that is still pending
+/// is dropped too and never delivered again. Backlog this long means that
+/// the consumers are down, such entries are logged when they are claimed.
+const STREAM_MAX_LEN: usize = 100_000;
+/// How long a single read waits for new entries.
+const READ_BLOCK: Duration = Duration::from_secs(1);
+/// Entries taken by a single read or claim.
+const READ_COUNT: usize = 100;
+/// Entry unacked for this long is taken over from the consumer that read it.
+const CLAIM_MIN_IDLE: Duration = Duration::from_secs(60);
+/// How often the consumer looks for entries abandoned by crashed consumers.
+const CLAIM_INTERVAL: Duration = Duration::from_secs(30);
+/// Consumer that holds no entries and got none for this long is removed
+/// from the group, so consumers of the stopped processes don't pile up.
+const CONSUMER_IDLE_LIMIT: Duration = Duration::from_secs(60 * 60);
+/// How long the request waits for the answer, same as the NATS client default.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Reply subjects of the requests, the stream of a reply that
+/// comes after the request gave up expires on its own.
+const INBOX_PREFIX: &str = "_INBOX.";
+
+const PAYLOAD_FIELD: &str = "payload";
+const REPLY_FIELD: &str = "reply";
+
+/// Every subject is a stream, every queue group is a consumer group of it.
+fn stream_key(subject: &str) -> String {
+    format!("hcwc.stream.{}", subject)
+}
+
+/// Bus on top of redis streams for deployments without NATS.
+///
+/// Messages stay in the stream until the consumer acks them, messages
+/// of a crashed consumer are claimed by another consumer of the group.
+pub struct RedisStreamsBus {
+    client: redis::Client,
+    connection: MultiplexedConnection,
+}
+
+impl RedisStreamsBus {
+    pub async fn connect(url: &str) -> BusResult<Self> {
+        let client = redis::Client::open(url)?;
+        let connection = client.get_multiplexed_async_connection().await?;
+        Ok(Self { client, connection })
+    }
+
+    async fn add(&self, subject: &str, payload: Bytes, reply: Option<&str>) -> BusResult<()> {
+        let mut connection = self.connection.clone();
+        let mut fields: Vec<(&str, &[u8])> = vec![(PAYLOAD_FIELD, &payload)];
+        if let Some(reply) = reply {
+            fields.push((REPLY_FIELD, reply.as_bytes()));
+        }
+        let key = stream_key(subject);
+        let mut pipe = redis::pipe();
+        pipe.xadd_maxlen(&key, StreamMaxlen::Approx(STREAM_MAX_LEN), "*", &fields)
+            .ignore();
+        if subject.starts_with(INBOX_PREFIX) {
+            pipe.pexpire(&key, REQUEST_TIMEOUT.as_millis() as i64)
+                .ignore();
+        }
+        pipe.query_async::<()>(&mut connection).await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl MessageBus for RedisStreamsBus {
+    async fn publish(&self, subject: &str, payload: Bytes) -> BusResult<()> {
+        self.add(subject, payload, None).await
+    }
+
+    async fn queue_subscribe(&self, subject: &str, group: &str) -> BusResult<Subscription> {
+        let key = stream_key(subject);
+        let mut connection = self.connection.clone();
+        let created = connection
+            .xgroup_create_mkstream::<_, _, _, ()>(&key, group, "0")
+            .await;
+        match created {
+            Ok(()) => {}
+            // Group is already there, another consumer created it.
+            Err(err) if err.code() == Some("BUSYGROUP") => {}
+            Err(err) => return Err(err.into()),
+        }
+
+        // Blocking reads hold the connection, every consumer gets its own one.
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let consumer = Consumer {
+            subject: subject.to_string(),
+            key,
+            group: group.to_string(),
+            name: uuid::Uuid::new_v4().to_string(),
+            reader: self.client.get_multiplexed_async_connection().await?,
+            acker: connection,
+            sender,
+        };
+        tokio::spawn(consumer.run());
+
+        Ok(UnboundedReceiverStream::new(receiver).boxed())
+    }
+
+    async fn subscribe(&self, subject: &str) -> BusResult<Subscription> {
+        let key = stream_key(subject);
+        let mut reader = self.client.get_multiplexed_async_connection().await?;
+        // Reads start after the newest entry, no consumer group is left behind.
+        let newest = reader
+            .xrevrange_count::<_, _, _, _, StreamRangeReply>(&key, "+", "-", 1)
+            .await?;
+        let last_id = newest
+            .ids
+            .into_iter()
+            .next()
+            .map_or_else(|| "0-0".to_string(), |entry| entry.id);
+
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let follower = Follower {
+            subject: subject.to_string(),
+            key,
+            last_id,
+            reader,
+            sender,
+        };
+        tokio::spawn(follower.run());
+
+        Ok(UnboundedReceiverStream::new(receiver).boxed())
+    }
+
+    async fn request(&self, subject: &str, payload: Bytes) -> BusResult<Bytes> {
+        let inbox = format!("{}{}", INBOX_PREFIX, uuid::Uuid::new_v4());
+        let inbox_key = stream_key(&inbox);
+        self.add(subject, payload, Some(&inbox)).await?;
+
+        let mut reader = self.client.get_multiplexed_async_connection().await?;
+        let reply = reader
+            .xread_options::<_, _, Option<StreamReadReply>>(
+                &[&inbox_key],
+                &["0"],
+                &StreamReadOptions::default()
+                    .block(REQUEST_TIMEOUT.as_millis() as usize)
+                    .count(1),
+            )
+            .await;
+
+        let mut connection = self.connection.clone();
+        connection.del::<_, ()>(&inbox_key).await?;
+
+        reply?
+            .into_iter()
+            .flat_map(|reply| reply.keys)
+            .flat_map(|key| key.ids)
+            .find_map(|entry| entry.get::<Vec<u8>>(PAYLOAD_FIELD))
+            .map(Bytes::from)
+            .ok_or(BusError::Timeout)
+    }
+
+    async fn remove_subjects(&self, subjects: &[String]) -> BusResult<()> {
+        let keys: Vec<String> = subjects.iter().map(|subject| stream_key(subject)).collect();
+        let mut connection = self.connection.clone();
+        connection.del::<_, ()>(keys).await?;
+        Ok(())
+    }
+}
+
+/// Acks a single entry of the stream.
+struct StreamAcker {
+    connection: MultiplexedConnection,
+    key: String,
+    group: String,
+    id: String,
+}
+
+#[async_trait]
+impl Acker for StreamAcker {
+    async fn ack(&self) -> BusResult<()> {
+        let mut connection = self.connection.clone();
+        connection
+            .xack::<_, _, _, ()>(&self.key, &self.group, &[&self.id])
+            .await?;
+        Ok(())
+    }
+}
+
+/// Reads the stream as a member of the consumer group and passes
+/// entries to the subscription until it is dropped.
+struct Consumer {
+    subject: String,
+    key: String,
+    group: String,
+    name: String,
+    reader: MultiplexedConnection,
+    acker: MultiplexedConnection,
+    sender: UnboundedSender<BusMessage>,
+}
+
+impl Consumer {
+    async fn run(mut self) {
+        // Entries left by crashed consumers are claimed right away.
+        let mut last_claim: Option<Instant> = None;
+
+        while !self.sender.is_closed() {
+            if last_claim.is_none_or(|claimed| claimed.elapsed() >= CLAIM_INTERVAL) {
+                if let Err(err) = self.claim().await {
+                    println!("Cannot claim entries of {}: {}", self.key, err);
+                }
+                if let Err(err) = self.remove_idle_consumers().await {
+                    println!("Cannot remove idle consumers of {}: {}", self.key, err);
+                }
+                last_claim = Some(Instant::now());
+            }
+
+            let options = StreamReadOptions::default()
+                .group(&self.group, &self.name)
+                .block(READ_BLOCK.as_millis() as usize)
+                .count(READ_COUNT);
+            let reply = self
+                .reader
+                .xread_options::<_, _, Option<StreamReadReply>>(&[&self.key], &[">"], &options)
+                .await;
+            match reply {
+                Ok(Some(reply)) => {
+                    for entry in reply.keys.into_iter().flat_map(|key| key.ids) {
+                        self.forward(entry);
+                    }
+                }
+                Ok(None) => {}
+                Err(err) => {
+                    println!("Cannot read {}: {}", self.key, err);
+                    tokio::time::sleep(READ_BLOCK).await;
+                }
+            }
+        }
+    }
+
+    /// Takes over entries that other consumers read but never acked.
+    async fn claim(&mut self) -> BusResult<()> {
+        let mut start = "0-0".to_string();
+        loop {
+            let reply = self
+                .acker
+                .xautoclaim_options::<_, _, _, _, _, StreamAutoClaimReply>(
+                    &self.key,
+                    &self.group,
+                    &self.name,
+                    CLAIM_MIN_IDLE.as_millis() as usize,
+                    &start,
+                    StreamAutoClaimOptions::default().count(READ_COUNT),
+                )
+                .await?;
+            for entry in reply.claimed {
+                self.forward(entry);
+            }
+            if !reply.deleted_ids.is_empty() {
+                println!(
+                    "Entries of {} were trimmed before they were acked: {:?}",
+                    self.key, reply.deleted_ids
+                );
+            }
+            // Whole pending list was scanned.
+            if reply.next_stream_id == "0-0" {
+                return Ok(());
+            }
+            start = reply.next_stream_id;
+        }
+    }
+
+    /// Removes consumers of the group that crashed or were dropped.
+    ///
+    /// Consumer with pending entries is kept, removing it would drop them.
+    /// Its entries are claimed first, it is removed by the next sweep.
+    async fn remove_idle_consumers(&mut self) -> BusResult<()> {
+        let reply = self
+            .acker
+            .xinfo_consumers::<_, _, StreamInfoConsumersReply>(&self.key, &self.group)
+            .await?;
+        for consumer in reply.consumers {
+            if consumer.name == self.name
+                || consumer.pending > 0
+                || consumer.idle < CONSUMER_IDLE_LIMIT.as_millis() as usize
+            {
+                continue;
+            }
+            self.acker
+                .xgroup_delconsumer::<_, _, _, ()>(&self.key, &self.group, &consumer.name)
+                .await?;
+        }
+        Ok(())
+    }
+
+    fn forward(&self, entry: StreamId) {
+        let Some(payload) = entry.get::<Vec<u8>>(PAYLOAD_FIELD) else {
+            return;
+        };
+        let mut msg = BusMessage::new(
+            self.subject.clone(),
+            Bytes::from(payload),
+            entry.get::<String>(REPLY_FIELD),
+        );
+        msg.acker = Some(Arc::new(StreamAcker {
+            connection: self.acker.clone(),
+            key: self.key.clone(),
+            group: self.group.clone(),
+            id: entry.id,
+        }));
+        let _ = self.sender.send(msg);
+    }
+}
+
+/// Reads every new entry of the stream without a consumer group
+/// and passes it to the subscription until it is dropped.
+struct Follower {
+    subject: String,
+    key: String,
+    last_id: String,
+    reader: MultiplexedConnection,
+    sender: UnboundedSender<BusMessage>,
+}
+
+impl Follower {
+    async fn run(mut self) {
+        while !self.sender.is_closed() {
+            let options = StreamReadOptions::default()
+                .block(READ_BLOCK.as_millis() as usize)
+                .count(READ_COUNT);
+            let reply = self
+                .reader
+                .xread_options::<_, _, Option<StreamReadReply>>(
+                    &[&self.key],
+                    &[&self.last_id],
+                    &options,
+                )
+                .await;
+            match reply {
+                Ok(Some(reply)) => {
+                    for entry in reply.keys.into_iter().flat_map(|key| key.ids) {
+                        self.last_id.clone_from(&entry.id);
+                        let Some(payload) = entry.get::<Vec<u8>>(PAYLOAD_FIELD) else {
+                            continue;
+                        };
+                        let _ = self.sender.send(BusMessage::new(
+                            self.subject.clone(),
+                            Bytes::from(payload),
+                            entry.get::<String>(REPLY_FIELD),
+                        ));
+                    }
+                }
+                Ok(None) => {}
+                Err(err) => {
+                    println!("Cannot read {}: {}", self.key, err);
+                    tokio::time::sleep(READ_BLOCK).await;
+                }
+            }
+        }
+    }
+}
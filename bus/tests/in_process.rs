@@ -0,0 +1,98 @@
+//! Delivery semantics of the in-process bus, the same as of core NATS.
+
+use std::time::Duration;
+
+use bytes::Bytes;
+use futures_util::StreamExt;
+use hcwc_bus::{BusError, InProcessBus, MessageBus, Subscription};
+
+async fn next_payload(subscription: &mut Subscription) -> Option<Bytes> {
+    tokio::time::timeout(Duration::from_millis(100), subscription.next())
+        .await
+        .ok()
+        .flatten()
+        .map(|msg| msg.payload)
+}
+
+#[tokio::test]
+async fn queue_group_members_take_turns() {
+    let bus = InProcessBus::new();
+    let mut first = bus.queue_subscribe("subject", "group").await.unwrap();
+    let mut second = bus.queue_subscribe("subject", "group").await.unwrap();
+
+    for payload in ["1", "2", "3", "4"] {
+        bus.publish("subject", Bytes::from(payload)).await.unwrap();
+    }
+
+    assert_eq!(next_payload(&mut first).await, Some(Bytes::from("1")));
+    assert_eq!(next_payload(&mut first).await, Some(Bytes::from("3")));
+    assert_eq!(next_payload(&mut first).await, None);
+    assert_eq!(next_payload(&mut second).await, Some(Bytes::from("2")));
+    assert_eq!(next_payload(&mut second).await, Some(Bytes::from("4")));
+    assert_eq!(next_payload(&mut second).await, None);
+}
+
+#[tokio::test]
+async fn every_group_gets_every_message() {
+    let bus = InProcessBus::new();
+    let mut first = bus.queue_subscribe("subject", "first").await.unwrap();
+    let mut second = bus.queue_subscribe("subject", "second").await.unwrap();
+    let mut other = bus.queue_subscribe("other", "first").await.unwrap();
+
+    bus.publish("subject", Bytes::from("1")).await.unwrap();
+
+    assert_eq!(next_payload(&mut first).await, Some(Bytes::from("1")));
+    assert_eq!(next_payload(&mut second).await, Some(Bytes::from("1")));
+    assert_eq!(next_payload(&mut other).await, None);
+}
+
+#[tokio::test]
+async fn dropped_subscriber_leaves_the_group() {
+    let bus = InProcessBus::new();
+    let first = bus.queue_subscribe("subject", "group").await.unwrap();
+    let mut second = bus.queue_subscribe("subject", "group").await.unwrap();
+    drop(first);
+
+    bus.publish("subject", Bytes::from("1")).await.unwrap();
+    bus.publish("subject", Bytes::from("2")).await.unwrap();
+
+    assert_eq!(next_payload(&mut second).await, Some(Bytes::from("1")));
+    assert_eq!(next_payload(&mut second).await, Some(Bytes::from("2")));
+}
+
+#[tokio::test]
+async fn request_gets_the_reply() {
+    let bus = std::sync::Arc::new(InProcessBus::new());
+    let mut responder = bus.queue_subscribe("echo", "group").await.unwrap();
+    let replier = bus.clone();
+    tokio::spawn(async move {
+        while let Some(msg) = responder.next().await {
+            let reply = msg.reply.expect("request has no reply subject");
+            replier.publish(&reply, msg.payload).await.unwrap();
+        }
+    });
+
+    let reply = bus.request("echo", Bytes::from("ping")).await.unwrap();
+    assert_eq!(reply, Bytes::from("ping"));
+}
+
+#[tokio::test]
+async fn request_without_responders_fails() {
+    let bus = InProcessBus::new();
+    let res = bus.request("nobody", Bytes::from("ping")).await;
+    assert!(matches!(res, Err(BusError::NoResponders)));
+}
+
+#[tokio::test]
+async fn every_subscriber_gets_every_message() {
+    let bus = InProcessBus::new();
+    let mut first = bus.subscribe("subject").await.unwrap();
+    let mut second = bus.subscribe("subject").await.unwrap();
+    let mut member = bus.queue_subscribe("subject", "group").await.unwrap();
+
+    bus.publish("subject", Bytes::from("1")).await.unwrap();
+
+    assert_eq!(next_payload(&mut first).await, Some(Bytes::from("1")));
+    assert_eq!(next_payload(&mut second).await, Some(Bytes::from("1")));
+    assert_eq!(next_payload(&mut member).await, Some(Bytes::from("1")));
+}
@@ -0,0 +1,43 @@
+//! Redelivery of the durable subjects, needs `nats-server -js`:
+//!
+//! HCWC_NATS_URL=localhost cargo test -p hcwc-bus --test jetstream -- --ignored
+
+use std::time::Duration;
+
+use bytes::Bytes;
+use futures_util::StreamExt;
+use hcwc_bus::{BusMessage, JetStreamBus, MessageBus, Subscription};
+
+/// Longer than the message stays unacked before it is delivered again.
+const PAST_ACK_WAIT: Duration = Duration::from_secs(35);
+
+async fn next(subscription: &mut Subscription, timeout: Duration) -> Option<BusMessage> {
+    tokio::time::timeout(timeout, subscription.next())
+        .await
+        .ok()
+        .flatten()
+}
+
+#[tokio::test]
+#[ignore = "needs nats-server with JetStream, waits for the ack deadline"]
+async fn unacked_message_is_delivered_again() {
+    let url = std::env::var("HCWC_NATS_URL").unwrap_or_else(|_| "localhost".to_string());
+    let bus = JetStreamBus::connect(&url, &["message.publish", "message.*.send"])
+        .await
+        .unwrap();
+    // Subject of a server of its own, no other consumer filters it.
+    let subject = format!("message.{}.send", uuid::Uuid::new_v4());
+    let mut subscription = bus.queue_subscribe(&subject, "group").await.unwrap();
+    bus.publish(&subject, Bytes::from("1")).await.unwrap();
+
+    // Consumer crashed before the ack.
+    let first = next(&mut subscription, Duration::from_secs(5))
+        .await
+        .unwrap();
+    assert_eq!(first.payload, Bytes::from("1"));
+
+    let again = next(&mut subscription, PAST_ACK_WAIT).await.unwrap();
+    assert_eq!(again.payload, Bytes::from("1"));
+    again.ack().await.unwrap();
+    assert!(next(&mut subscription, PAST_ACK_WAIT).await.is_none());
+}
@@ -0,0 +1,50 @@
+//! Recovery of the entries a crashed consumer left pending, needs redis:
+//!
+//! HCWC_REDIS_URL=redis://127.0.0.1/ cargo test -p hcwc-bus --test redis_streams -- --ignored
+
+use std::time::Duration;
+
+use bytes::Bytes;
+use futures_util::StreamExt;
+use hcwc_bus::{MessageBus, RedisStreamsBus, Subscription};
+
+/// Longer than the entry has to stay unacked before another consumer claims it.
+const PAST_CLAIM_IDLE: Duration = Duration::from_secs(61);
+
+async fn connect() -> RedisStreamsBus {
+    let url = std::env::var("HCWC_REDIS_URL").unwrap_or_else(|_| "redis://127.0.0.1/".to_string());
+    RedisStreamsBus::connect(&url).await.unwrap()
+}
+
+async fn next_payload(subscription: &mut Subscription) -> Option<Bytes> {
+    tokio::time::timeout(Duration::from_secs(5), subscription.next())
+        .await
+        .ok()
+        .flatten()
+        .map(|msg| msg.payload)
+}
+
+#[tokio::test]
+#[ignore = "needs redis, waits for the entry to become claimable"]
+async fn unacked_entry_is_claimed_by_another_consumer() {
+    let bus = connect().await;
+    let subject = format!("test.{}", uuid::Uuid::new_v4());
+
+    // Consumer reads the entry and crashes before the ack.
+    let mut crashed = bus.queue_subscribe(&subject, "group").await.unwrap();
+    bus.publish(&subject, Bytes::from("1")).await.unwrap();
+    assert_eq!(next_payload(&mut crashed).await, Some(Bytes::from("1")));
+    drop(crashed);
+
+    tokio::time::sleep(PAST_CLAIM_IDLE).await;
+    let mut survivor = bus.queue_subscribe(&subject, "group").await.unwrap();
+    let msg = tokio::time::timeout(Duration::from_secs(5), survivor.next())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(msg.payload, Bytes::from("1"));
+    msg.ack().await.unwrap();
+    assert_eq!(next_payload(&mut survivor).await, None);
+
+    bus.remove_subjects(&[subject]).await.unwrap();
+}
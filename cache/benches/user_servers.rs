@@ -0,0 +1,114 @@
+//! Latency of the recipient lookup with many connected users.
+//!
+//! ```text
+//! cargo bench -p hcwc-cache --bench user_servers
+//! HCWC_CACHE_BACKEND=redis HCWC_REDIS_URL=redis://127.0.0.1/ cargo bench -p hcwc-cache
+//! ```
+//!
+//! In-memory cache is used unless `HCWC_CACHE_BACKEND` is set,
+//! `HCWC_BENCH_USERS` changes the number of connected users.
+
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use hcwc_cache::{CacheConfig, CacheDB, MemoryCache};
+
+const DEFAULT_USERS: usize = 1_000_000;
+const SERVERS: usize = 16;
+const LOOKUPS: usize = 10_000;
+/// Members of the room in the pipelined lookups.
+const ROOM_SIZE: usize = 100;
+/// Users registered concurrently while the cache is filled.
+const FILL_BATCH: usize = 1_000;
+const PRESENCE_TTL: Duration = Duration::from_secs(600);
+
+/// Xorshift, recipients of the lookups are spread over all users.
+struct Users(u64);
+
+impl Users {
+    fn next(&mut self, users: usize) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 % users as u64) as usize
+    }
+}
+
+fn report(name: &str, mut latencies: Vec<Duration>) {
+    latencies.sort();
+    let total: Duration = latencies.iter().sum();
+    let percentile = |p: usize| latencies[(latencies.len() * p / 100).min(latencies.len() - 1)];
+    println!(
+        "{:<24} mean {:>10.2?}  p50 {:>10.2?}  p99 {:>10.2?}  max {:>10.2?}",
+        name,
+        total / latencies.len() as u32,
+        percentile(50),
+        percentile(99),
+        latencies[latencies.len() - 1],
+    );
+}
+
+async fn fill(cache: &dyn CacheDB, users: usize) {
+    let servers: Vec<String> = (0..SERVERS)
+        .map(|n| format!("bench-server-{}", n))
+        .collect();
+    for batch in (0..users).collect::<Vec<usize>>().chunks(FILL_BATCH) {
+        let adds = batch
+            .iter()
+            .map(|user_id| cache.add_new_user(*user_id, &servers[user_id % SERVERS], PRESENCE_TTL));
+        for res in futures_util::future::join_all(adds).await {
+            res.expect("Cannot add user to the cache");
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    let users = std::env::var("HCWC_BENCH_USERS")
+        .ok()
+        .and_then(|users| users.parse::<usize>().ok())
+        .unwrap_or(DEFAULT_USERS);
+    let cache: Arc<dyn CacheDB> = match std::env::var("HCWC_CACHE_BACKEND") {
+        Ok(backend) => {
+            let mut config = CacheConfig {
+                backend: backend.parse().expect("Bad cache backend"),
+                ..CacheConfig::default()
+            };
+            if let Ok(url) = std::env::var("HCWC_REDIS_URL") {
+                config.redis_url = url;
+            }
+            hcwc_cache::connect(&config)
+                .await
+                .expect("Cannot connect to the cache")
+        }
+        Err(_) => Arc::new(MemoryCache::new()),
+    };
+
+    let started = Instant::now();
+    fill(cache.as_ref(), users).await;
+    println!("{} users connected in {:.2?}", users, started.elapsed());
+
+    let mut recipients = Users(0x2545_f491_4f6c_dd1d);
+
+    let mut latencies = Vec::with_capacity(LOOKUPS);
+    for _ in 0..LOOKUPS {
+        let recipient = recipients.next(users);
+        let started = Instant::now();
+        let servers = cache.user_servers(recipient).await.unwrap();
+        latencies.push(started.elapsed());
+        assert_eq!(servers.len(), 1);
+    }
+    report("user_servers", latencies);
+
+    let mut latencies = Vec::with_capacity(LOOKUPS / ROOM_SIZE);
+    for _ in 0..LOOKUPS / ROOM_SIZE {
+        let members: Vec<usize> = (0..ROOM_SIZE).map(|_| recipients.next(users)).collect();
+        let started = Instant::now();
+        let servers = cache.users_servers(&members).await.unwrap();
+        latencies.push(started.elapsed());
+        assert_eq!(servers.len(), ROOM_SIZE);
+    }
+    report(&format!("users_servers x{}", ROOM_SIZE), latencies);
+}
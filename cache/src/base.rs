@@ -0,0 +1,164 @@
+use std::{fmt, time::Duration};
+
+use async_trait::async_trait;
+
+use hcwc_protocol::{
+    mq_messages::{ClientMessage, DeadLetter, DeliveryStatus, SentMessage},
+    pending::PendingLimits,
+};
+
+#[derive(Debug)]
+pub enum CacheError {
+    Redis(::redis::RedisError),
+    /// Another thread panicked while holding the cache.
+    Poisoned,
+}
+
+impl fmt::Display for CacheError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CacheError::Redis(err) => write!(f, "Redis error: {}", err),
+            CacheError::Poisoned => write!(f, "Cache is poisoned"),
+        }
+    }
+}
+
+impl std::error::Error for CacheError {}
+
+impl From<::redis::RedisError> for CacheError {
+    fn from(err: ::redis::RedisError) -> Self {
+        CacheError::Redis(err)
+    }
+}
+
+pub type CacheResult<T> = Result<T, CacheError>;
+
+/// State shared by the servers and the workers.
+#[async_trait]
+pub trait CacheDB: Send + Sync {
+    /// Registers running server.
+    async fn add_server(&self, server_uuid: &str) -> CacheResult<()>;
+
+    /// Server is alive for `ttl` more, it stays registered while it refreshes itself.
+    async fn refresh_server(&self, server_uuid: &str, ttl: Duration) -> CacheResult<()>;
+
+    /// Registered servers that didn't refresh themselves in time.
+    async fn stale_servers(&self) -> CacheResult<Vec<String>>;
+
+    /// Removes server and its state, users stop pointing to it.
+    async fn remove_server(&self, server_uuid: &str) -> CacheResult<()>;
+
+    /// Marks that the server holds a session of the user, the mark expires
+    /// after `ttl` unless it is added again.
+    ///
+    /// Marks of all the servers of the user share the expiry, a server
+    /// that stops refreshing stays marked while another one refreshes.
+    /// Such server has crashed, `remove_server` of the reaper unmarks it.
+    async fn add_new_user(
+        &self,
+        user_id: usize,
+        server_uuid: &str,
+        ttl: Duration,
+    ) -> CacheResult<()>;
+
+    /// Server doesn't hold sessions of the user anymore,
+    /// returns `false` if it wasn't marked.
+    async fn remove_user(&self, user_id: usize, server_uuid: &str) -> CacheResult<bool>;
+
+    /// User has a session on any server.
+    async fn is_user_exist(&self, user_id: usize) -> CacheResult<bool>;
+
+    /// Remembers when the user went offline.
+    async fn set_last_seen(&self, user_id: usize, timestamp: u64) -> CacheResult<()>;
+
+    /// Unix timestamp in milliseconds the user went offline at.
+    async fn last_seen(&self, user_id: usize) -> CacheResult<Option<u64>>;
+
+    /// Next sequence number of the messages from `sender` to `recipient`, starts at 1.
+    async fn next_sequence(&self, sender: usize, recipient: usize) -> CacheResult<u64>;
+
+    /// Claims the client message id of the sender for `window`,
+    /// returns outcome of the earlier message if the id is already claimed.
+    async fn claim_sent_message(
+        &self,
+        sender: usize,
+        client_message_id: &str,
+        sent: SentMessage,
+        window: Duration,
+    ) -> CacheResult<Option<SentMessage>>;
+
+    /// Updates outcome of the claimed message, the status
+    /// is kept if it can't become the new one.
+    async fn update_sent_message(
+        &self,
+        sender: usize,
+        client_message_id: &str,
+        sent: SentMessage,
+        window: Duration,
+    ) -> CacheResult<()>;
+
+    /// Records the receipt of the message for `window`, returns `false`
+    /// if the message already has a receipt with the status or a later one.
+    async fn record_receipt(
+        &self,
+        message_id: u64,
+        status: DeliveryStatus,
+        window: Duration,
+    ) -> CacheResult<bool>;
+
+    /// Outcome of the message with the client message id, if it was sent within the window.
+    async fn sent_message(
+        &self,
+        sender: usize,
+        client_message_id: &str,
+    ) -> CacheResult<Option<SentMessage>>;
+
+    /// Servers holding sessions of the user.
+    async fn user_servers(&self, user_id: usize) -> CacheResult<Vec<String>>;
+
+    /// Servers holding sessions of every user, in the order of `user_ids`.
+    async fn users_servers(&self, user_ids: &[usize]) -> CacheResult<Vec<Vec<String>>>;
+
+    /// Puts the message into the pending queue of the offline recipient,
+    /// queue keeps only the newest `limits.max_len` messages.
+    async fn queue_message(
+        &self,
+        message: &ClientMessage,
+        limits: PendingLimits,
+    ) -> CacheResult<()>;
+
+    /// Takes all messages from the pending queue of the user, oldest first.
+    async fn take_pending_messages(&self, user_id: usize) -> CacheResult<Vec<ClientMessage>>;
+
+    /// Creates room with the owner as its only member and
+    /// the invited users, returns its id.
+    async fn create_room(&self, owner: usize, invited: &[usize]) -> CacheResult<usize>;
+
+    /// Room has at least one member.
+    async fn room_exists(&self, room_id: usize) -> CacheResult<bool>;
+
+    /// User can join the room then.
+    async fn invite_room_member(&self, room_id: usize, user_id: usize) -> CacheResult<()>;
+
+    /// Moves the user from the invites of the room to its members,
+    /// returns `false` if the user is not invited.
+    async fn join_room(&self, room_id: usize, user_id: usize) -> CacheResult<bool>;
+
+    /// Returns `false` if the user wasn't a member. Invites
+    /// are dropped together with the last member.
+    async fn remove_room_member(&self, room_id: usize, user_id: usize) -> CacheResult<bool>;
+
+    /// Members of the room, empty if there is no such room.
+    async fn room_members(&self, room_id: usize) -> CacheResult<Vec<usize>>;
+
+    async fn is_room_member(&self, room_id: usize, user_id: usize) -> CacheResult<bool>;
+
+    /// Keeps the message the worker gave up on, returns id assigned to it.
+    async fn add_dead_letter(&self, letter: DeadLetter) -> CacheResult<u64>;
+
+    /// All dead letters ordered by id.
+    async fn dead_letters(&self) -> CacheResult<Vec<DeadLetter>>;
+
+    /// Removes the dead letter and returns it.
+    async fn take_dead_letter(&self, id: u64) -> CacheResult<Option<DeadLetter>>;
+}
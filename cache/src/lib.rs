@@ -0,0 +1,55 @@
+//! Shared state of the chat: running servers, servers holding sessions
+//! of every user, pending queues of offline users and room members.
+
+use std::{str::FromStr, sync::Arc};
+
+mod base;
+mod memory;
+mod redis;
+
+pub use base::{CacheDB, CacheError, CacheResult};
+pub use memory::MemoryCache;
+pub use redis::RedisCache;
+
+/// Storage the cache runs on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheBackend {
+    Redis,
+}
+
+impl FromStr for CacheBackend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "redis" => Ok(CacheBackend::Redis),
+            "memory" => {
+                Err("memory cache is not shared between processes, expected redis".to_string())
+            }
+            _ => Err(format!("unknown cache backend `{}`, expected redis", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CacheConfig {
+    pub backend: CacheBackend,
+    /// URL of the redis server, used by `redis` backend
+    pub redis_url: String,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            backend: CacheBackend::Redis,
+            redis_url: "redis://127.0.0.1/".to_string(),
+        }
+    }
+}
+
+/// Builds cache selected in the config.
+pub async fn connect(config: &CacheConfig) -> CacheResult<Arc<dyn CacheDB>> {
+    match config.backend {
+        CacheBackend::Redis => Ok(Arc::new(RedisCache::connect(&config.redis_url).await?)),
+    }
+}
@@ -0,0 +1,409 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+
+use hcwc_protocol::{
+    mq_messages::{ClientMessage, DeadLetter, DeliveryStatus, SentMessage},
+    pending::PendingLimits,
+};
+
+use crate::base::{CacheDB, CacheError, CacheResult};
+
+struct PendingQueue {
+    expires_at: Instant,
+    messages: VecDeque<ClientMessage>,
+}
+
+/// Servers holding sessions of the user, they expire together
+/// like the set of the user in redis.
+struct Presence {
+    servers: HashSet<String>,
+    expires_at: Instant,
+}
+
+#[derive(Default)]
+struct State {
+    /// Running servers and the time their liveness expires at
+    servers: HashMap<String, Option<Instant>>,
+    users: HashMap<usize, Presence>,
+    last_seen: HashMap<usize, u64>,
+    /// Last sequence number of the messages from sender to recipient
+    sequences: HashMap<(usize, usize), u64>,
+    /// Outcomes of the messages with client message ids and the time they expire at
+    sent: HashMap<(usize, String), (SentMessage, Instant)>,
+    /// Status of the newest receipt of the message and the time it expires at
+    receipts: HashMap<u64, (DeliveryStatus, Instant)>,
+    pending: HashMap<usize, PendingQueue>,
+    next_room_id: usize,
+    rooms: HashMap<usize, HashSet<usize>>,
+    room_invites: HashMap<usize, HashSet<usize>>,
+    next_dead_letter_id: u64,
+    dead_letters: BTreeMap<u64, DeadLetter>,
+}
+
+/// Cache kept in memory of the process, for tests and single node deployments.
+#[derive(Default)]
+pub struct MemoryCache {
+    state: Mutex<State>,
+}
+
+impl State {
+    /// Outcome of the message unless it has expired.
+    fn sent(&mut self, sender: usize, client_message_id: &str) -> Option<&mut SentMessage> {
+        let key = (sender, client_message_id.to_string());
+        if self
+            .sent
+            .get(&key)
+            .is_some_and(|(_, expires_at)| *expires_at < Instant::now())
+        {
+            self.sent.remove(&key);
+        }
+        self.sent.get_mut(&key).map(|(sent, _)| sent)
+    }
+
+    /// Presence of the user unless it has expired.
+    fn presence(&mut self, user_id: usize) -> Option<&mut Presence> {
+        if self
+            .users
+            .get(&user_id)
+            .is_some_and(|presence| presence.expires_at < Instant::now())
+        {
+            self.users.remove(&user_id);
+        }
+        self.users.get_mut(&user_id)
+    }
+}
+
+impl MemoryCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn with_state<T>(&self, f: impl FnOnce(&mut State) -> T) -> CacheResult<T> {
+        let mut state = self.state.lock().map_err(|_| CacheError::Poisoned)?;
+        Ok(f(&mut state))
+    }
+}
+
+#[async_trait]
+impl CacheDB for MemoryCache {
+    async fn add_server(&self, server_uuid: &str) -> CacheResult<()> {
+        self.with_state(|state| {
+            state.servers.entry(server_uuid.to_string()).or_default();
+        })
+    }
+
+    async fn refresh_server(&self, server_uuid: &str, ttl: Duration) -> CacheResult<()> {
+        self.with_state(|state| {
+            state
+                .servers
+                .insert(server_uuid.to_string(), Some(Instant::now() + ttl));
+        })
+    }
+
+    async fn stale_servers(&self) -> CacheResult<Vec<String>> {
+        self.with_state(|state| {
+            let now = Instant::now();
+            state
+                .servers
+                .iter()
+                .filter(|(_, expires_at)| expires_at.is_none_or(|expires_at| expires_at < now))
+                .map(|(server, _)| server.clone())
+                .collect()
+        })
+    }
+
+    async fn remove_server(&self, server_uuid: &str) -> CacheResult<()> {
+        self.with_state(|state| {
+            state.servers.remove(server_uuid);
+            state.users.retain(|_, presence| {
+                presence.servers.remove(server_uuid);
+                !presence.servers.is_empty()
+            });
+        })
+    }
+
+    async fn add_new_user(
+        &self,
+        user_id: usize,
+        server_uuid: &str,
+        ttl: Duration,
+    ) -> CacheResult<()> {
+        self.with_state(|state| {
+            let expires_at = Instant::now() + ttl;
+            match state.presence(user_id) {
+                Some(presence) => {
+                    presence.servers.insert(server_uuid.to_string());
+                    presence.expires_at = expires_at;
+                }
+                None => {
+                    state.users.insert(
+                        user_id,
+                        Presence {
+                            servers: HashSet::from([server_uuid.to_string()]),
+                            expires_at,
+                        },
+                    );
+                }
+            }
+        })
+    }
+
+    async fn remove_user(&self, user_id: usize, server_uuid: &str) -> CacheResult<bool> {
+        self.with_state(|state| {
+            let Some(presence) = state.presence(user_id) else {
+                return false;
+            };
+            let removed = presence.servers.remove(server_uuid);
+            if presence.servers.is_empty() {
+                state.users.remove(&user_id);
+            }
+            removed
+        })
+    }
+
+    async fn is_user_exist(&self, user_id: usize) -> CacheResult<bool> {
+        self.with_state(|state| state.presence(user_id).is_some())
+    }
+
+    async fn set_last_seen(&self, user_id: usize, timestamp: u64) -> CacheResult<()> {
+        self.with_state(|state| {
+            state.last_seen.insert(user_id, timestamp);
+        })
+    }
+
+    async fn last_seen(&self, user_id: usize) -> CacheResult<Option<u64>> {
+        self.with_state(|state| state.last_seen.get(&user_id).copied())
+    }
+
+    async fn next_sequence(&self, sender: usize, recipient: usize) -> CacheResult<u64> {
+        self.with_state(|state| {
+            let seq = state.sequences.entry((sender, recipient)).or_default();
+            *seq += 1;
+            *seq
+        })
+    }
+
+    async fn claim_sent_message(
+        &self,
+        sender: usize,
+        client_message_id: &str,
+        sent: SentMessage,
+        window: Duration,
+    ) -> CacheResult<Option<SentMessage>> {
+        self.with_state(|state| {
+            if let Some(earlier) = state.sent(sender, client_message_id) {
+                return Some(*earlier);
+            }
+            state.sent.insert(
+                (sender, client_message_id.to_string()),
+                (sent, Instant::now() + window),
+            );
+            None
+        })
+    }
+
+    async fn update_sent_message(
+        &self,
+        sender: usize,
+        client_message_id: &str,
+        sent: SentMessage,
+        window: Duration,
+    ) -> CacheResult<()> {
+        self.with_state(|state| {
+            if state
+                .sent(sender, client_message_id)
+                .is_some_and(|earlier| earlier.status.can_become(sent.status))
+            {
+                state.sent.insert(
+                    (sender, client_message_id.to_string()),
+                    (sent, Instant::now() + window),
+                );
+            }
+        })
+    }
+
+    async fn record_receipt(
+        &self,
+        message_id: u64,
+        status: DeliveryStatus,
+        window: Duration,
+    ) -> CacheResult<bool> {
+        self.with_state(|state| {
+            let now = Instant::now();
+            let recorded = match state.receipts.get(&message_id) {
+                Some((earlier, expires_at)) if *expires_at >= now => status.is_ahead_of(*earlier),
+                _ => status != DeliveryStatus::Failed,
+            };
+            if recorded {
+                state.receipts.insert(message_id, (status, now + window));
+            }
+            recorded
+        })
+    }
+
+    async fn sent_message(
+        &self,
+        sender: usize,
+        client_message_id: &str,
+    ) -> CacheResult<Option<SentMessage>> {
+        self.with_state(|state| state.sent(sender, client_message_id).copied())
+    }
+
+    async fn user_servers(&self, user_id: usize) -> CacheResult<Vec<String>> {
+        self.with_state(|state| {
+            state
+                .presence(user_id)
+                .map(|presence| presence.servers.iter().cloned().collect())
+                .unwrap_or_default()
+        })
+    }
+
+    async fn users_servers(&self, user_ids: &[usize]) -> CacheResult<Vec<Vec<String>>> {
+        self.with_state(|state| {
+            user_ids
+                .iter()
+                .map(|user_id| {
+                    state
+                        .presence(*user_id)
+                        .map(|presence| presence.servers.iter().cloned().collect())
+                        .unwrap_or_default()
+                })
+                .collect()
+        })
+    }
+
+    async fn queue_message(
+        &self,
+        message: &ClientMessage,
+        limits: PendingLimits,
+    ) -> CacheResult<()> {
+        let message = message.clone();
+        self.with_state(|state| {
+            let now = Instant::now();
+            let queue = state
+                .pending
+                .entry(message.recipient)
+                .or_insert_with(|| PendingQueue {
+                    expires_at: now,
+                    messages: VecDeque::new(),
+                });
+            if queue.expires_at < now {
+                queue.messages.clear();
+            }
+
+            queue.messages.push_back(message);
+            while queue.messages.len() > limits.max_len {
+                queue.messages.pop_front();
+            }
+            queue.expires_at = now + limits.ttl;
+        })
+    }
+
+    async fn take_pending_messages(&self, user_id: usize) -> CacheResult<Vec<ClientMessage>> {
+        self.with_state(|state| match state.pending.remove(&user_id) {
+            Some(queue) if queue.expires_at >= Instant::now() => queue.messages.into(),
+            _ => vec![],
+        })
+    }
+
+    async fn create_room(&self, owner: usize, invited: &[usize]) -> CacheResult<usize> {
+        self.with_state(|state| {
+            state.next_room_id += 1;
+            state
+                .rooms
+                .insert(state.next_room_id, HashSet::from([owner]));
+            if !invited.is_empty() {
+                state
+                    .room_invites
+                    .insert(state.next_room_id, invited.iter().copied().collect());
+            }
+            state.next_room_id
+        })
+    }
+
+    async fn room_exists(&self, room_id: usize) -> CacheResult<bool> {
+        self.with_state(|state| state.rooms.contains_key(&room_id))
+    }
+
+    async fn invite_room_member(&self, room_id: usize, user_id: usize) -> CacheResult<()> {
+        self.with_state(|state| {
+            state
+                .room_invites
+                .entry(room_id)
+                .or_default()
+                .insert(user_id);
+        })
+    }
+
+    async fn join_room(&self, room_id: usize, user_id: usize) -> CacheResult<bool> {
+        self.with_state(|state| {
+            let Some(invites) = state.room_invites.get_mut(&room_id) else {
+                return false;
+            };
+            if !invites.remove(&user_id) {
+                return false;
+            }
+            if invites.is_empty() {
+                state.room_invites.remove(&room_id);
+            }
+            state.rooms.entry(room_id).or_default().insert(user_id);
+            true
+        })
+    }
+
+    async fn remove_room_member(&self, room_id: usize, user_id: usize) -> CacheResult<bool> {
+        self.with_state(|state| {
+            let Some(members) = state.rooms.get_mut(&room_id) else {
+                return false;
+            };
+            let removed = members.remove(&user_id);
+            // Like redis, room without members is gone.
+            if members.is_empty() {
+                state.rooms.remove(&room_id);
+                state.room_invites.remove(&room_id);
+            }
+            removed
+        })
+    }
+
+    async fn room_members(&self, room_id: usize) -> CacheResult<Vec<usize>> {
+        self.with_state(|state| {
+            state
+                .rooms
+                .get(&room_id)
+                .map(|members| members.iter().copied().collect())
+                .unwrap_or_default()
+        })
+    }
+
+    async fn is_room_member(&self, room_id: usize, user_id: usize) -> CacheResult<bool> {
+        self.with_state(|state| {
+            state
+                .rooms
+                .get(&room_id)
+                .is_some_and(|members| members.contains(&user_id))
+        })
+    }
+
+    async fn add_dead_letter(&self, mut letter: DeadLetter) -> CacheResult<u64> {
+        self.with_state(|state| {
+            state.next_dead_letter_id += 1;
+            letter.id = state.next_dead_letter_id;
+            state.dead_letters.insert(letter.id, letter);
+            state.next_dead_letter_id
+        })
+    }
+
+    async fn dead_letters(&self) -> CacheResult<Vec<DeadLetter>> {
+        self.with_state(|state| state.dead_letters.values().cloned().collect())
+    }
+
+    async fn take_dead_letter(&self, id: u64) -> CacheResult<Option<DeadLetter>> {
+        self.with_state(|state| state.dead_letters.remove(&id))
+    }
+}
@@ -0,0 +1,449 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use redis::{aio::MultiplexedConnection, AsyncCommands, ExistenceCheck, SetExpiry, SetOptions};
+
+use hcwc_protocol::{
+    keys,
+    mq_messages::{ClientMessage, DeadLetter, DeliveryStatus, SentMessage},
+    pending::PendingLimits,
+};
+
+use crate::base::{CacheDB, CacheResult};
+
+/// Sets the outcome of the claimed message if its status is one of `ARGV[3..]`.
+const UPDATE_SENT_SCRIPT: &str = r"
+local earlier = redis.call('GET', KEYS[1])
+if not earlier then
+    return 0
+end
+local status = cjson.decode(earlier).status
+for i = 3, #ARGV do
+    if ARGV[i] == status then
+        redis.call('SET', KEYS[1], ARGV[1], 'EX', ARGV[2])
+        return 1
+    end
+end
+return 0
+";
+
+/// Sets the receipt status if there is none or the earlier one is in `ARGV[3..]`.
+const RECORD_RECEIPT_SCRIPT: &str = r"
+local earlier = redis.call('GET', KEYS[1])
+if earlier then
+    local behind = false
+    for i = 3, #ARGV do
+        if ARGV[i] == earlier then
+            behind = true
+        end
+    end
+    if not behind then
+        return 0
+    end
+end
+redis.call('SET', KEYS[1], ARGV[1], 'EX', ARGV[2])
+return 1
+";
+
+/// Name of the status as it is serialized.
+fn status_name(status: DeliveryStatus) -> String {
+    serde_json::to_value(status)
+        .unwrap()
+        .as_str()
+        .unwrap()
+        .to_string()
+}
+
+/// Cache shared by all servers and workers.
+#[derive(Clone)]
+pub struct RedisCache {
+    connection: MultiplexedConnection,
+}
+
+impl RedisCache {
+    pub async fn connect(url: &str) -> CacheResult<Self> {
+        let client = redis::Client::open(url)?;
+        let connection = client.get_multiplexed_async_connection().await?;
+        Ok(Self { connection })
+    }
+}
+
+#[async_trait]
+impl CacheDB for RedisCache {
+    async fn add_server(&self, server_uuid: &str) -> CacheResult<()> {
+        let mut connection = self.connection.clone();
+        connection
+            .sadd::<&str, &str, ()>(keys::SERVERS_KEY, server_uuid)
+            .await?;
+        Ok(())
+    }
+
+    async fn refresh_server(&self, server_uuid: &str, ttl: Duration) -> CacheResult<()> {
+        let mut connection = self.connection.clone();
+        redis::pipe()
+            .atomic()
+            .sadd(keys::SERVERS_KEY, server_uuid)
+            .ignore()
+            .set_ex(keys::server_key(server_uuid), 1, ttl.as_secs().max(1))
+            .ignore()
+            .query_async::<()>(&mut connection)
+            .await?;
+        Ok(())
+    }
+
+    async fn stale_servers(&self) -> CacheResult<Vec<String>> {
+        let mut connection = self.connection.clone();
+        let servers = connection
+            .smembers::<&str, Vec<String>>(keys::SERVERS_KEY)
+            .await?;
+
+        let mut pipe = redis::pipe();
+        for server in &servers {
+            pipe.exists(keys::server_key(server));
+        }
+        let alive: Vec<bool> = pipe.query_async(&mut connection).await?;
+
+        Ok(servers
+            .into_iter()
+            .zip(alive)
+            .filter_map(|(server, alive)| (!alive).then_some(server))
+            .collect())
+    }
+
+    async fn remove_server(&self, server_uuid: &str) -> CacheResult<()> {
+        let mut connection = self.connection.clone();
+        let users_key = keys::server_users_key(server_uuid);
+        let users = connection.smembers::<&str, Vec<usize>>(&users_key).await?;
+
+        let mut pipe = redis::pipe();
+        pipe.atomic();
+        for user_id in users {
+            pipe.srem(keys::user_key(user_id), server_uuid).ignore();
+        }
+        pipe.del(&users_key)
+            .ignore()
+            .srem(keys::SERVERS_KEY, server_uuid)
+            .ignore()
+            .del(keys::server_key(server_uuid))
+            .ignore()
+            .query_async::<()>(&mut connection)
+            .await?;
+        Ok(())
+    }
+
+    async fn add_new_user(
+        &self,
+        user_id: usize,
+        server_uuid: &str,
+        ttl: Duration,
+    ) -> CacheResult<()> {
+        let mut connection = self.connection.clone();
+        let key = keys::user_key(user_id);
+        redis::pipe()
+            .atomic()
+            .sadd(&key, server_uuid)
+            .ignore()
+            .expire(&key, ttl.as_secs().max(1) as i64)
+            .ignore()
+            .sadd(keys::server_users_key(server_uuid), user_id)
+            .ignore()
+            .query_async::<()>(&mut connection)
+            .await?;
+        Ok(())
+    }
+
+    async fn remove_user(&self, user_id: usize, server_uuid: &str) -> CacheResult<bool> {
+        let mut connection = self.connection.clone();
+        let (removed,): (usize,) = redis::pipe()
+            .atomic()
+            .srem(keys::user_key(user_id), server_uuid)
+            .srem(keys::server_users_key(server_uuid), user_id)
+            .ignore()
+            .query_async(&mut connection)
+            .await?;
+        Ok(removed > 0)
+    }
+
+    async fn is_user_exist(&self, user_id: usize) -> CacheResult<bool> {
+        let mut connection = self.connection.clone();
+        Ok(connection
+            .exists::<String, bool>(keys::user_key(user_id))
+            .await?)
+    }
+
+    async fn set_last_seen(&self, user_id: usize, timestamp: u64) -> CacheResult<()> {
+        let mut connection = self.connection.clone();
+        connection
+            .set::<String, u64, ()>(keys::last_seen_key(user_id), timestamp)
+            .await?;
+        Ok(())
+    }
+
+    async fn last_seen(&self, user_id: usize) -> CacheResult<Option<u64>> {
+        let mut connection = self.connection.clone();
+        Ok(connection
+            .get::<String, Option<u64>>(keys::last_seen_key(user_id))
+            .await?)
+    }
+
+    async fn next_sequence(&self, sender: usize, recipient: usize) -> CacheResult<u64> {
+        let mut connection = self.connection.clone();
+        Ok(connection
+            .incr::<String, u64, u64>(keys::sequence_key(sender, recipient), 1)
+            .await?)
+    }
+
+    async fn claim_sent_message(
+        &self,
+        sender: usize,
+        client_message_id: &str,
+        sent: SentMessage,
+        window: Duration,
+    ) -> CacheResult<Option<SentMessage>> {
+        let mut connection = self.connection.clone();
+        let key = keys::sent_message_key(sender, client_message_id);
+        let options = SetOptions::default()
+            .conditional_set(ExistenceCheck::NX)
+            .with_expiration(SetExpiry::EX(window.as_secs()));
+        let claimed = connection
+            .set_options::<&str, String, Option<String>>(
+                &key,
+                serde_json::to_string(&sent).unwrap(),
+                options,
+            )
+            .await?;
+        if claimed.is_some() {
+            return Ok(None);
+        }
+
+        let earlier = connection.get::<&str, Option<String>>(&key).await?;
+        Ok(earlier.and_then(|sent| serde_json::from_str::<SentMessage>(&sent).ok()))
+    }
+
+    async fn update_sent_message(
+        &self,
+        sender: usize,
+        client_message_id: &str,
+        sent: SentMessage,
+        window: Duration,
+    ) -> CacheResult<()> {
+        let mut connection = self.connection.clone();
+        let earlier = DeliveryStatus::ALL
+            .into_iter()
+            .filter(|earlier| earlier.can_become(sent.status))
+            .map(status_name);
+        redis::Script::new(UPDATE_SENT_SCRIPT)
+            .key(keys::sent_message_key(sender, client_message_id))
+            .arg(serde_json::to_string(&sent).unwrap())
+            .arg(window.as_secs())
+            .arg(earlier.collect::<Vec<_>>())
+            .invoke_async::<()>(&mut connection)
+            .await?;
+        Ok(())
+    }
+
+    async fn record_receipt(
+        &self,
+        message_id: u64,
+        status: DeliveryStatus,
+        window: Duration,
+    ) -> CacheResult<bool> {
+        if status == DeliveryStatus::Failed {
+            return Ok(false);
+        }
+        let mut connection = self.connection.clone();
+        let behind = DeliveryStatus::ALL
+            .into_iter()
+            .filter(|earlier| status.is_ahead_of(*earlier))
+            .map(status_name);
+        Ok(redis::Script::new(RECORD_RECEIPT_SCRIPT)
+            .key(keys::receipt_key(message_id))
+            .arg(status_name(status))
+            .arg(window.as_secs())
+            .arg(behind.collect::<Vec<_>>())
+            .invoke_async::<bool>(&mut connection)
+            .await?)
+    }
+
+    async fn sent_message(
+        &self,
+        sender: usize,
+        client_message_id: &str,
+    ) -> CacheResult<Option<SentMessage>> {
+        let mut connection = self.connection.clone();
+        let sent = connection
+            .get::<String, Option<String>>(keys::sent_message_key(sender, client_message_id))
+            .await?;
+        Ok(sent.and_then(|sent| serde_json::from_str::<SentMessage>(&sent).ok()))
+    }
+
+    async fn user_servers(&self, user_id: usize) -> CacheResult<Vec<String>> {
+        let mut connection = self.connection.clone();
+        Ok(connection
+            .smembers::<String, Vec<String>>(keys::user_key(user_id))
+            .await?)
+    }
+
+    async fn users_servers(&self, user_ids: &[usize]) -> CacheResult<Vec<Vec<String>>> {
+        if user_ids.is_empty() {
+            return Ok(vec![]);
+        }
+
+        // One round trip for all users.
+        let mut connection = self.connection.clone();
+        let mut pipe = redis::pipe();
+        for user_id in user_ids {
+            pipe.smembers(keys::user_key(*user_id));
+        }
+        Ok(pipe.query_async(&mut connection).await?)
+    }
+
+    async fn queue_message(
+        &self,
+        message: &ClientMessage,
+        limits: PendingLimits,
+    ) -> CacheResult<()> {
+        let mut connection = self.connection.clone();
+        let key = keys::pending_key(message.recipient);
+        redis::pipe()
+            .atomic()
+            .rpush(&key, serde_json::to_string(message).unwrap())
+            .ignore()
+            .ltrim(&key, -(limits.max_len as isize), -1)
+            .ignore()
+            .expire(&key, limits.ttl.as_secs() as i64)
+            .ignore()
+            .query_async::<()>(&mut connection)
+            .await?;
+        Ok(())
+    }
+
+    async fn take_pending_messages(&self, user_id: usize) -> CacheResult<Vec<ClientMessage>> {
+        let mut connection = self.connection.clone();
+        let key = keys::pending_key(user_id);
+        let (messages,): (Vec<String>,) = redis::pipe()
+            .atomic()
+            .lrange(&key, 0, -1)
+            .del(&key)
+            .ignore()
+            .query_async(&mut connection)
+            .await?;
+
+        Ok(messages
+            .iter()
+            .filter_map(|message| serde_json::from_str::<ClientMessage>(message).ok())
+            .collect())
+    }
+
+    async fn create_room(&self, owner: usize, invited: &[usize]) -> CacheResult<usize> {
+        let mut connection = self.connection.clone();
+        let room_id = connection
+            .incr::<&str, usize, usize>(keys::ROOM_ID_KEY, 1)
+            .await?;
+        let mut pipe = redis::pipe();
+        pipe.atomic().sadd(keys::room_key(room_id), owner).ignore();
+        if !invited.is_empty() {
+            pipe.sadd(keys::room_invites_key(room_id), invited).ignore();
+        }
+        pipe.query_async::<()>(&mut connection).await?;
+        Ok(room_id)
+    }
+
+    async fn room_exists(&self, room_id: usize) -> CacheResult<bool> {
+        let mut connection = self.connection.clone();
+        Ok(connection
+            .exists::<String, bool>(keys::room_key(room_id))
+            .await?)
+    }
+
+    async fn invite_room_member(&self, room_id: usize, user_id: usize) -> CacheResult<()> {
+        let mut connection = self.connection.clone();
+        connection
+            .sadd::<String, usize, ()>(keys::room_invites_key(room_id), user_id)
+            .await?;
+        Ok(())
+    }
+
+    async fn join_room(&self, room_id: usize, user_id: usize) -> CacheResult<bool> {
+        let mut connection = self.connection.clone();
+        Ok(connection
+            .smove::<String, String, usize, bool>(
+                keys::room_invites_key(room_id),
+                keys::room_key(room_id),
+                user_id,
+            )
+            .await?)
+    }
+
+    async fn remove_room_member(&self, room_id: usize, user_id: usize) -> CacheResult<bool> {
+        let mut connection = self.connection.clone();
+        let (removed, left): (usize, usize) = redis::pipe()
+            .atomic()
+            .srem(keys::room_key(room_id), user_id)
+            .scard(keys::room_key(room_id))
+            .query_async(&mut connection)
+            .await?;
+        // Invites don't bring back the room its last member left.
+        if left == 0 {
+            connection
+                .del::<String, ()>(keys::room_invites_key(room_id))
+                .await?;
+        }
+        Ok(removed > 0)
+    }
+
+    async fn room_members(&self, room_id: usize) -> CacheResult<Vec<usize>> {
+        let mut connection = self.connection.clone();
+        Ok(connection
+            .smembers::<String, Vec<usize>>(keys::room_key(room_id))
+            .await?)
+    }
+
+    async fn is_room_member(&self, room_id: usize, user_id: usize) -> CacheResult<bool> {
+        let mut connection = self.connection.clone();
+        Ok(connection
+            .sismember::<String, usize, bool>(keys::room_key(room_id), user_id)
+            .await?)
+    }
+
+    async fn add_dead_letter(&self, mut letter: DeadLetter) -> CacheResult<u64> {
+        let mut connection = self.connection.clone();
+        letter.id = connection
+            .incr::<&str, u64, u64>(keys::DEADLETTER_ID_KEY, 1)
+            .await?;
+        connection
+            .hset::<&str, u64, String, ()>(
+                keys::DEADLETTERS_KEY,
+                letter.id,
+                serde_json::to_string(&letter).unwrap(),
+            )
+            .await?;
+        Ok(letter.id)
+    }
+
+    async fn dead_letters(&self) -> CacheResult<Vec<DeadLetter>> {
+        let mut connection = self.connection.clone();
+        let letters = connection
+            .hvals::<&str, Vec<String>>(keys::DEADLETTERS_KEY)
+            .await?;
+
+        let mut letters: Vec<DeadLetter> = letters
+            .iter()
+            .filter_map(|letter| serde_json::from_str::<DeadLetter>(letter).ok())
+            .collect();
+        letters.sort_by_key(|letter| letter.id);
+        Ok(letters)
+    }
+
+    async fn take_dead_letter(&self, id: u64) -> CacheResult<Option<DeadLetter>> {
+        let mut connection = self.connection.clone();
+        let (letter,): (Option<String>,) = redis::pipe()
+            .atomic()
+            .hget(keys::DEADLETTERS_KEY, id)
+            .hdel(keys::DEADLETTERS_KEY, id)
+            .ignore()
+            .query_async(&mut connection)
+            .await?;
+        Ok(letter.and_then(|letter| serde_json::from_str::<DeadLetter>(&letter).ok()))
+    }
+}
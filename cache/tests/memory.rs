@@ -0,0 +1,243 @@
+//! Expiry, claims and queues of the in-memory cache, the same as redis has them.
+
+use std::time::Duration;
+
+use hcwc_cache::{CacheDB, MemoryCache};
+use hcwc_protocol::{
+    mq_messages::{ClientMessage, DeadLetter, DeliveryStatus, SentMessage},
+    pending::PendingLimits,
+};
+
+const SHORT: Duration = Duration::from_millis(50);
+const LONG: Duration = Duration::from_secs(60);
+
+fn sent(seq: u64, status: DeliveryStatus) -> SentMessage {
+    SentMessage {
+        message_id: None,
+        seq: Some(seq),
+        status,
+    }
+}
+
+fn message(recipient: usize, text: &str) -> ClientMessage {
+    ClientMessage {
+        id: 1,
+        session_id: 0,
+        server_uuid: String::new(),
+        request_id: None,
+        msg: text.to_string(),
+        recipient,
+        message_id: None,
+        timestamp: None,
+        seq: None,
+        client_message_id: None,
+    }
+}
+
+fn letter(subject: &str) -> DeadLetter {
+    DeadLetter {
+        id: 0,
+        subject: subject.to_string(),
+        payload: "{}".to_string(),
+        reason: "down".to_string(),
+        attempts: 5,
+        failed_at: 0,
+    }
+}
+
+fn texts(messages: &[ClientMessage]) -> Vec<&str> {
+    messages
+        .iter()
+        .map(|message| message.msg.as_str())
+        .collect()
+}
+
+#[tokio::test]
+async fn presence_expires_unless_refreshed() {
+    let cache = MemoryCache::new();
+    cache.add_new_user(1, "a", SHORT).await.unwrap();
+    cache.add_new_user(2, "a", LONG).await.unwrap();
+    assert!(cache.is_user_exist(1).await.unwrap());
+
+    tokio::time::sleep(SHORT * 2).await;
+    assert!(!cache.is_user_exist(1).await.unwrap());
+    assert!(cache.user_servers(1).await.unwrap().is_empty());
+    assert_eq!(cache.user_servers(2).await.unwrap(), vec!["a".to_string()]);
+}
+
+#[tokio::test]
+async fn presence_of_all_servers_expires_together() {
+    let cache = MemoryCache::new();
+    cache.add_new_user(1, "a", SHORT).await.unwrap();
+    tokio::time::sleep(SHORT / 2).await;
+    // Refresh from another server keeps the first one marked too.
+    cache.add_new_user(1, "b", SHORT * 4).await.unwrap();
+    tokio::time::sleep(SHORT).await;
+
+    let mut servers = cache.user_servers(1).await.unwrap();
+    servers.sort();
+    assert_eq!(servers, vec!["a".to_string(), "b".to_string()]);
+
+    tokio::time::sleep(SHORT * 4).await;
+    assert!(!cache.is_user_exist(1).await.unwrap());
+}
+
+#[tokio::test]
+async fn claim_sets_only_new_id() {
+    let cache = MemoryCache::new();
+    let claimed = cache
+        .claim_sent_message(1, "a", sent(1, DeliveryStatus::Accepted), LONG)
+        .await
+        .unwrap();
+    assert!(claimed.is_none());
+
+    // Second claim gets the first outcome and changes nothing.
+    let earlier = cache
+        .claim_sent_message(1, "a", sent(2, DeliveryStatus::Accepted), LONG)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(earlier.seq, Some(1));
+    // Ids are per sender.
+    assert!(cache
+        .claim_sent_message(2, "a", sent(1, DeliveryStatus::Accepted), LONG)
+        .await
+        .unwrap()
+        .is_none());
+}
+
+#[tokio::test]
+async fn update_sets_only_claimed_id() {
+    let cache = MemoryCache::new();
+    cache
+        .update_sent_message(1, "a", sent(1, DeliveryStatus::Routed), LONG)
+        .await
+        .unwrap();
+    assert!(cache.sent_message(1, "a").await.unwrap().is_none());
+
+    cache
+        .claim_sent_message(1, "a", sent(1, DeliveryStatus::Accepted), LONG)
+        .await
+        .unwrap();
+    cache
+        .update_sent_message(1, "a", sent(1, DeliveryStatus::Routed), LONG)
+        .await
+        .unwrap();
+    let outcome = cache.sent_message(1, "a").await.unwrap().unwrap();
+    assert_eq!(outcome.status, DeliveryStatus::Routed);
+
+    // Status doesn't move back.
+    cache
+        .update_sent_message(1, "a", sent(1, DeliveryStatus::Accepted), LONG)
+        .await
+        .unwrap();
+    let outcome = cache.sent_message(1, "a").await.unwrap().unwrap();
+    assert_eq!(outcome.status, DeliveryStatus::Routed);
+}
+
+#[tokio::test]
+async fn claim_expires_after_window() {
+    let cache = MemoryCache::new();
+    cache
+        .claim_sent_message(1, "a", sent(1, DeliveryStatus::Accepted), SHORT)
+        .await
+        .unwrap();
+    tokio::time::sleep(SHORT * 2).await;
+
+    assert!(cache.sent_message(1, "a").await.unwrap().is_none());
+    assert!(cache
+        .claim_sent_message(1, "a", sent(2, DeliveryStatus::Accepted), LONG)
+        .await
+        .unwrap()
+        .is_none());
+}
+
+#[tokio::test]
+async fn receipt_is_recorded_once() {
+    let cache = MemoryCache::new();
+    assert!(cache
+        .record_receipt(1, DeliveryStatus::Delivered, LONG)
+        .await
+        .unwrap());
+    assert!(!cache
+        .record_receipt(1, DeliveryStatus::Delivered, LONG)
+        .await
+        .unwrap());
+    assert!(cache
+        .record_receipt(1, DeliveryStatus::Read, LONG)
+        .await
+        .unwrap());
+    assert!(!cache
+        .record_receipt(1, DeliveryStatus::Delivered, LONG)
+        .await
+        .unwrap());
+}
+
+#[tokio::test]
+async fn pending_queue_keeps_newest_messages() {
+    let cache = MemoryCache::new();
+    let limits = PendingLimits {
+        ttl: LONG,
+        max_len: 2,
+    };
+    for text in ["one", "two", "three"] {
+        cache
+            .queue_message(&message(1, text), limits)
+            .await
+            .unwrap();
+    }
+    cache
+        .queue_message(&message(2, "other"), limits)
+        .await
+        .unwrap();
+
+    let pending = cache.take_pending_messages(1).await.unwrap();
+    assert_eq!(texts(&pending), vec!["two", "three"]);
+    // Queue is taken once.
+    assert!(cache.take_pending_messages(1).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn pending_queue_expires() {
+    let cache = MemoryCache::new();
+    let limits = PendingLimits {
+        ttl: SHORT,
+        max_len: 10,
+    };
+    cache
+        .queue_message(&message(1, "old"), limits)
+        .await
+        .unwrap();
+    tokio::time::sleep(SHORT * 2).await;
+    assert!(cache.take_pending_messages(1).await.unwrap().is_empty());
+
+    // Expired queue doesn't come back with the next message.
+    cache
+        .queue_message(&message(1, "old"), limits)
+        .await
+        .unwrap();
+    tokio::time::sleep(SHORT * 2).await;
+    cache
+        .queue_message(&message(1, "new"), limits)
+        .await
+        .unwrap();
+    let pending = cache.take_pending_messages(1).await.unwrap();
+    assert_eq!(texts(&pending), vec!["new"]);
+}
+
+#[tokio::test]
+async fn dead_letters_are_taken_by_id() {
+    let cache = MemoryCache::new();
+    let first = cache.add_dead_letter(letter("a")).await.unwrap();
+    let second = cache.add_dead_letter(letter("b")).await.unwrap();
+    assert!(first < second);
+
+    let letters = cache.dead_letters().await.unwrap();
+    let ids: Vec<u64> = letters.iter().map(|letter| letter.id).collect();
+    assert_eq!(ids, vec![first, second]);
+
+    let taken = cache.take_dead_letter(first).await.unwrap().unwrap();
+    assert_eq!((taken.id, taken.subject.as_str()), (first, "a"));
+    assert!(cache.take_dead_letter(first).await.unwrap().is_none());
+    assert_eq!(cache.dead_letters().await.unwrap().len(), 1);
+}
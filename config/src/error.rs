@@ -0,0 +1,36 @@
+use std::{fmt, path::PathBuf};
+
+#[derive(Debug)]
+pub enum ConfigError {
+    /// Config file can't be read.
+    Read(PathBuf, std::io::Error),
+    /// Config file is not valid TOML or has unknown settings.
+    Parse(PathBuf, toml::de::Error),
+    /// Setting has a bad value, `key` is its name in the config file.
+    Invalid { key: &'static str, message: String },
+}
+
+impl ConfigError {
+    pub fn invalid(key: &'static str, message: impl Into<String>) -> Self {
+        ConfigError::Invalid {
+            key,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Read(path, err) => {
+                write!(f, "Cannot read config file {}: {}", path.display(), err)
+            }
+            ConfigError::Parse(path, err) => {
+                write!(f, "Bad config file {}: {}", path.display(), err)
+            }
+            ConfigError::Invalid { key, message } => write!(f, "Bad {}: {}", key, message),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
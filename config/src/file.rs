@@ -0,0 +1,71 @@
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::ConfigError;
+
+/// Contents of the config file, every setting is optional.
+///
+/// Server and worker share the file, each of them reads its own sections.
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConfigFile {
+    /// Queue group the servers and the workers consume their subjects in
+    pub queue_group: Option<String>,
+    /// URL of the redis server for the cache and the redis bus
+    pub redis_url: Option<String>,
+    pub bus: BusSection,
+    pub cache: CacheSection,
+    pub pending: PendingSection,
+    pub server: ServerSection,
+    pub history: HistorySection,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct BusSection {
+    pub backend: Option<String>,
+    pub nats_url: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct CacheSection {
+    pub backend: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct PendingSection {
+    /// Seconds
+    pub ttl: Option<u64>,
+    pub max_len: Option<usize>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerSection {
+    pub host: Option<String>,
+    pub port: Option<u16>,
+    pub workers: Option<usize>,
+    /// Seconds
+    pub heartbeat_interval: Option<u64>,
+    /// Seconds
+    pub client_timeout: Option<u64>,
+    pub auth_secret: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct HistorySection {
+    pub backend: Option<String>,
+    pub path: Option<String>,
+}
+
+impl ConfigFile {
+    pub fn read(path: &Path) -> Result<Self, ConfigError> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|err| ConfigError::Read(path.to_path_buf(), err))?;
+        toml::from_str(&contents).map_err(|err| ConfigError::Parse(path.to_path_buf(), err))
+    }
+}
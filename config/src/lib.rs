@@ -0,0 +1,178 @@
+//! Settings of the server and the worker.
+//!
+//! Every setting is taken from the first place it is set in: command line flag,
+//! `HCWC_*` environment variable, TOML file given with `--config` or `HCWC_CONFIG`.
+//! Unset settings have defaults, `example.toml` lists all of them.
+
+use std::{path::PathBuf, str::FromStr, time::Duration};
+
+use clap::Args;
+use hcwc_bus::{BusBackend, BusConfig};
+use hcwc_cache::{CacheBackend, CacheConfig};
+use hcwc_protocol::pending::PendingLimits;
+
+mod error;
+mod file;
+
+pub use error::ConfigError;
+pub use file::{
+    BusSection, CacheSection, ConfigFile, HistorySection, PendingSection, ServerSection,
+};
+
+pub type ConfigResult<T> = Result<T, ConfigError>;
+
+const DEFAULT_QUEUE_GROUP: &str = "my_group";
+
+/// Flags of the settings both the server and the worker have.
+#[derive(Args, Debug, Clone)]
+pub struct CommonArgs {
+    /// TOML config file
+    #[arg(long, env = "HCWC_CONFIG")]
+    pub config: Option<PathBuf>,
+    /// Message bus: nats, jetstream or redis
+    #[arg(long, env = "HCWC_BUS_BACKEND")]
+    pub bus_backend: Option<String>,
+    /// Address of the NATS server
+    #[arg(long, env = "HCWC_NATS_URL")]
+    pub nats_url: Option<String>,
+    /// URL of the redis server for the cache and the redis bus
+    #[arg(long, env = "HCWC_REDIS_URL")]
+    pub redis_url: Option<String>,
+    /// Cache: redis
+    #[arg(long, env = "HCWC_CACHE_BACKEND")]
+    pub cache_backend: Option<String>,
+    /// Queue group the subjects are consumed in
+    #[arg(long, env = "HCWC_QUEUE_GROUP")]
+    pub queue_group: Option<String>,
+    /// Seconds the pending queue of an offline user is kept
+    #[arg(long, env = "HCWC_PENDING_TTL")]
+    pub pending_ttl: Option<u64>,
+    /// Most messages in the pending queue of an offline user
+    #[arg(long, env = "HCWC_PENDING_MAX_LEN")]
+    pub pending_max_len: Option<usize>,
+}
+
+impl CommonArgs {
+    /// Reads the config file, there are no settings from the file without it.
+    pub fn read_file(&self) -> ConfigResult<ConfigFile> {
+        match &self.config {
+            Some(path) => ConfigFile::read(path),
+            None => Ok(ConfigFile::default()),
+        }
+    }
+}
+
+/// Settings both the server and the worker have.
+#[derive(Debug, Clone)]
+pub struct CommonConfig {
+    pub bus: BusConfig,
+    pub cache: CacheConfig,
+    pub pending: PendingLimits,
+    pub queue_group: String,
+}
+
+impl CommonConfig {
+    pub fn resolve(args: &CommonArgs, file: &ConfigFile) -> ConfigResult<Self> {
+        let bus_defaults = BusConfig::default();
+        let cache_defaults = CacheConfig::default();
+        let pending_defaults = PendingLimits::default();
+
+        let redis_url = pick(&args.redis_url, &file.redis_url).unwrap_or(cache_defaults.redis_url);
+        let bus = BusConfig {
+            backend: parse(
+                "bus.backend",
+                pick(&args.bus_backend, &file.bus.backend),
+                bus_defaults.backend,
+            )?,
+            nats_url: pick(&args.nats_url, &file.bus.nats_url).unwrap_or(bus_defaults.nats_url),
+            redis_url: redis_url.clone(),
+        };
+        let cache = CacheConfig {
+            backend: parse(
+                "cache.backend",
+                pick(&args.cache_backend, &file.cache.backend),
+                cache_defaults.backend,
+            )?,
+            redis_url,
+        };
+        let pending = PendingLimits {
+            ttl: pick(&args.pending_ttl, &file.pending.ttl)
+                .map(Duration::from_secs)
+                .unwrap_or(pending_defaults.ttl),
+            max_len: pick(&args.pending_max_len, &file.pending.max_len)
+                .unwrap_or(pending_defaults.max_len),
+        };
+        let queue_group = pick(&args.queue_group, &file.queue_group)
+            .unwrap_or_else(|| DEFAULT_QUEUE_GROUP.to_string());
+
+        if matches!(bus.backend, BusBackend::Nats | BusBackend::JetStream) {
+            require_non_empty("bus.nats_url", &bus.nats_url)?;
+        }
+        if bus.backend == BusBackend::Redis || cache.backend == CacheBackend::Redis {
+            check_redis_url("redis_url", &cache.redis_url)?;
+        }
+        require_positive("pending.ttl", pending.ttl.as_secs())?;
+        require_positive("pending.max_len", pending.max_len as u64)?;
+        require_non_empty("queue_group", &queue_group)?;
+        if queue_group.contains(char::is_whitespace) {
+            return Err(ConfigError::invalid(
+                "queue_group",
+                "must not contain whitespace",
+            ));
+        }
+
+        Ok(Self {
+            bus,
+            cache,
+            pending,
+            queue_group,
+        })
+    }
+}
+
+/// Value from the flag or the environment variable, otherwise from the file.
+pub fn pick<T: Clone>(arg: &Option<T>, file: &Option<T>) -> Option<T> {
+    arg.clone().or_else(|| file.clone())
+}
+
+/// Parses the backend of the setting `key`, `default` if it is not set.
+pub fn parse<T: FromStr<Err = String>>(
+    key: &'static str,
+    value: Option<String>,
+    default: T,
+) -> ConfigResult<T> {
+    match value {
+        Some(value) => value
+            .parse()
+            .map_err(|message| ConfigError::invalid(key, message)),
+        None => Ok(default),
+    }
+}
+
+pub fn require_non_empty(key: &'static str, value: &str) -> ConfigResult<()> {
+    if value.trim().is_empty() {
+        return Err(ConfigError::invalid(key, "must not be empty"));
+    }
+    Ok(())
+}
+
+pub fn require_positive(key: &'static str, value: u64) -> ConfigResult<()> {
+    if value == 0 {
+        return Err(ConfigError::invalid(key, "must be greater than 0"));
+    }
+    Ok(())
+}
+
+fn check_redis_url(key: &'static str, url: &str) -> ConfigResult<()> {
+    let schemes = ["redis://", "rediss://", "redis+unix://", "unix://"];
+    if !schemes.iter().any(|scheme| url.starts_with(scheme)) {
+        return Err(ConfigError::invalid(
+            key,
+            format!(
+                "`{}` is not a redis URL, expected redis://, rediss:// or unix://",
+                url
+            ),
+        ));
+    }
+    Ok(())
+}
@@ -0,0 +1,70 @@
+//! Redis key naming.
+
+/// Set with uuids of all running servers.
+pub const SERVERS_KEY: &str = "hcwc_servers";
+
+/// Set with the servers holding sessions of the user, it expires
+/// unless the sessions refresh it.
+pub fn user_key(user_id: usize) -> String {
+    format!("hcwc.user.{}", user_id)
+}
+
+/// Liveness key of a single server, it expires unless the server refreshes it.
+pub fn server_key(server_uuid: &str) -> String {
+    format!("hcwc.server.{}", server_uuid)
+}
+
+/// Set with ids of the users that have a session on the server.
+pub fn server_users_key(server_uuid: &str) -> String {
+    format!("hcwc.server.{}.users", server_uuid)
+}
+
+/// List with messages for the user that is offline.
+pub fn pending_key(user_id: usize) -> String {
+    format!("hcwc.pending.{}", user_id)
+}
+
+/// Id of the conversation between two users, it is the same for both of them.
+pub fn conversation_id(user_id: usize, peer: usize) -> String {
+    format!("{}:{}", user_id.min(peer), user_id.max(peer))
+}
+
+/// Counter the ids of new rooms are taken from.
+pub const ROOM_ID_KEY: &str = "hcwc_room_id";
+
+/// Set with ids of the room members, redis drops it when the last member leaves.
+pub fn room_key(room_id: usize) -> String {
+    format!("hcwc.room.{}", room_id)
+}
+
+/// Set with ids of the users invited to the room who haven't joined it yet.
+pub fn room_invites_key(room_id: usize) -> String {
+    format!("hcwc.room.{}.invites", room_id)
+}
+
+/// Unix timestamp in milliseconds the user was last seen online at.
+pub fn last_seen_key(user_id: usize) -> String {
+    format!("hcwc.last_seen.{}", user_id)
+}
+
+/// Counter the sequence numbers of the messages from `sender`
+/// to `recipient` are taken from.
+pub fn sequence_key(sender: usize, recipient: usize) -> String {
+    format!("hcwc.seq.{}.{}", sender, recipient)
+}
+
+/// Status of the newest receipt of the message routed to its sender.
+pub fn receipt_key(message_id: u64) -> String {
+    format!("hcwc.receipt.{}", message_id)
+}
+
+/// Outcome of the message the sender sent with the client message id.
+pub fn sent_message_key(sender: usize, client_message_id: &str) -> String {
+    format!("hcwc.sent.{}.{}", sender, client_message_id)
+}
+
+/// Counter the ids of dead letters are taken from.
+pub const DEADLETTER_ID_KEY: &str = "hcwc_deadletter_id";
+
+/// Hash with dead letters by their ids.
+pub const DEADLETTERS_KEY: &str = "hcwc_deadletters";
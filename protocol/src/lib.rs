@@ -0,0 +1,12 @@
+//! Wire types shared by the hcwc server and worker.
+//!
+//! Everything that crosses a process boundary lives here: JSON-RPC
+//! requests and responses exchanged with websocket clients, the envelopes
+//! sent over NATS and the names of the keys stored in Redis.
+
+pub mod keys;
+pub mod mq_messages;
+pub mod notifications;
+pub mod pending;
+pub mod requests;
+pub mod responses;
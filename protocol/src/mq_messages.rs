@@ -0,0 +1,325 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::requests::RequestId;
+use crate::responses::{HistoryPage, JRPCError, UserPresence};
+
+/// Subject the servers publish client messages to, workers consume it.
+pub const PUBLISH_SUBJECT: &str = "message.publish";
+
+/// Subject a worker publishes routed messages to, only the server
+/// with `server_uuid` consumes it.
+pub fn send_subject(server_uuid: &str) -> String {
+    format!("message.{}.send", server_uuid)
+}
+
+/// Subject the servers send history queries to, workers reply to them.
+pub const HISTORY_SUBJECT: &str = "history.fetch";
+
+/// Subject with delivery statuses for the senders connected
+/// to the server with `server_uuid`.
+pub fn status_subject(server_uuid: &str) -> String {
+    format!("message.{}.status", server_uuid)
+}
+
+/// Subject the servers publish receipts of the recipients to, workers consume it.
+pub const RECEIPT_SUBJECT: &str = "receipt.publish";
+
+/// Subject a worker publishes receipts to, only the server
+/// with `server_uuid` consumes it.
+pub fn receipt_subject(server_uuid: &str) -> String {
+    format!("message.{}.receipt", server_uuid)
+}
+
+/// Subject the servers publish room messages to, workers consume it.
+pub const ROOM_PUBLISH_SUBJECT: &str = "room.publish";
+
+/// Subject a worker publishes room messages to, only the server
+/// with `server_uuid` consumes it.
+pub fn room_send_subject(server_uuid: &str) -> String {
+    format!("message.{}.room", server_uuid)
+}
+
+/// Subject with presence changes, every server consumes all of them.
+pub const PRESENCE_SUBJECT: &str = "presence.changed";
+
+/// Subject with messages the workers gave up on, see `DeadLetter`.
+pub const DEADLETTER_SUBJECT: &str = "message.deadletter";
+
+/// Subject the servers publish typing events to, workers consume it.
+///
+/// Typing events have their own subjects, so they never wait
+/// behind client messages.
+pub const TYPING_PUBLISH_SUBJECT: &str = "typing.publish";
+
+/// Subject a worker publishes typing events to, only the server
+/// with `server_uuid` consumes it.
+pub fn typing_send_subject(server_uuid: &str) -> String {
+    format!("typing.{}.send", server_uuid)
+}
+
+/// Subjects of a single server, they go away with the server.
+pub fn server_subjects(server_uuid: &str) -> Vec<String> {
+    vec![
+        send_subject(server_uuid),
+        status_subject(server_uuid),
+        receipt_subject(server_uuid),
+        room_send_subject(server_uuid),
+        typing_send_subject(server_uuid),
+    ]
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "actix", derive(actix::Message))]
+#[cfg_attr(
+    feature = "actix",
+    rtype(result = "Result<crate::responses::SendMessageResult, crate::responses::JRPCError>")
+)]
+pub struct ClientMessage {
+    /// Id of the sender
+    pub id: usize,
+    /// Session of the sender the message was sent from
+    pub session_id: usize,
+    /// Server holding the sender's session, filled in by the chat server
+    pub server_uuid: String,
+    /// Id of the JSON-RPC request the message was sent with
+    pub request_id: Option<RequestId>,
+    /// Peer message
+    pub msg: String,
+    /// Recipient
+    pub recipient: usize,
+    /// Id assigned by the history store, set by the worker
+    #[serde(default)]
+    pub message_id: Option<u64>,
+    /// Unix timestamp in milliseconds assigned by the history store, set by the worker
+    #[serde(default)]
+    pub timestamp: Option<u64>,
+    /// Number of the message among the messages from the sender
+    /// to the recipient, set by the chat server
+    #[serde(default)]
+    pub seq: Option<u64>,
+    /// Key the client gave the message, retries with the same key
+    /// are not delivered again
+    #[serde(default)]
+    pub client_message_id: Option<String>,
+}
+
+/// Message to every member of the room.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "actix", derive(actix::Message))]
+#[cfg_attr(
+    feature = "actix",
+    rtype(result = "Result<crate::responses::SendRoomMessageResult, crate::responses::JRPCError>")
+)]
+pub struct RoomMessage {
+    /// Id of the sender
+    pub id: usize,
+    /// Server holding the sender's session, filled in by the chat server
+    pub server_uuid: String,
+    pub room_id: usize,
+    pub msg: String,
+    /// Unix timestamp in milliseconds, set by the worker
+    #[serde(default)]
+    pub timestamp: Option<u64>,
+}
+
+/// Room message routed to a single server, `members` are the members
+/// of the room that have a session on that server.
+#[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "actix", derive(actix::Message))]
+#[cfg_attr(feature = "actix", rtype(result = "()"))]
+pub struct RoutedRoomMessage {
+    pub members: Vec<usize>,
+    pub message: RoomMessage,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum DeliveryStatus {
+    /// Server published the message, it waits for the worker.
+    Accepted,
+    /// Worker stored the message and sent it to the recipient's servers.
+    Routed,
+    /// Recipient is offline, message waits in the pending queue.
+    Queued,
+    /// Message was written to the recipient's socket.
+    Delivered,
+    /// Recipient marked the message as read.
+    Read,
+    /// Worker gave up on the message, it waits in the dead letters.
+    Failed,
+}
+
+impl DeliveryStatus {
+    pub const ALL: [DeliveryStatus; 6] = [
+        DeliveryStatus::Accepted,
+        DeliveryStatus::Routed,
+        DeliveryStatus::Queued,
+        DeliveryStatus::Delivered,
+        DeliveryStatus::Read,
+        DeliveryStatus::Failed,
+    ];
+
+    /// How far the message got, `Failed` is not on the way.
+    fn progress(self) -> u8 {
+        match self {
+            DeliveryStatus::Accepted | DeliveryStatus::Failed => 0,
+            DeliveryStatus::Routed | DeliveryStatus::Queued => 1,
+            DeliveryStatus::Delivered => 2,
+            DeliveryStatus::Read => 3,
+        }
+    }
+
+    /// Status of the message only moves forward: a read message is never
+    /// delivered again. Only accepted message fails, failed message
+    /// is accepted again when it is retried.
+    pub fn can_become(self, next: DeliveryStatus) -> bool {
+        match (self, next) {
+            (DeliveryStatus::Failed, _) => true,
+            (DeliveryStatus::Accepted, DeliveryStatus::Failed) => true,
+            (_, DeliveryStatus::Failed) => false,
+            _ => self.progress() <= next.progress(),
+        }
+    }
+
+    /// Receipt with the status tells the sender something new,
+    /// every later receipt with the same status is a duplicate.
+    pub fn is_ahead_of(self, earlier: DeliveryStatus) -> bool {
+        self != DeliveryStatus::Failed && self.progress() > earlier.progress()
+    }
+}
+
+/// Retries of the message with the same client message id
+/// are recognised for this long.
+pub const DEDUP_WINDOW: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Outcome of the message with the client message id,
+/// retries of the message get it instead of a second delivery.
+///
+/// Server claims the id with `Accepted` status before it takes the sequence
+/// number, the worker updates the status once the message is routed.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub struct SentMessage {
+    /// Id assigned by the history store
+    pub message_id: Option<u64>,
+    pub seq: Option<u64>,
+    pub status: DeliveryStatus,
+}
+
+/// Status of the message, goes back to the server of the sender.
+#[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "actix", derive(actix::Message))]
+#[cfg_attr(feature = "actix", rtype(result = "()"))]
+pub struct MessageStatus {
+    /// Id of the sender
+    pub id: usize,
+    /// Session of the sender the message was sent from
+    pub session_id: usize,
+    /// Id of the JSON-RPC request the message was sent with
+    pub request_id: Option<RequestId>,
+    /// Id assigned by the history store
+    pub message_id: Option<u64>,
+    pub recipient: usize,
+    pub status: DeliveryStatus,
+}
+
+impl MessageStatus {
+    pub fn new(message: &ClientMessage, status: DeliveryStatus) -> Self {
+        Self {
+            id: message.id,
+            session_id: message.session_id,
+            request_id: message.request_id.clone(),
+            message_id: message.message_id,
+            recipient: message.recipient,
+            status,
+        }
+    }
+}
+
+/// Delivery or read receipt of the recipient, the worker finds
+/// the sender of the message and routes the receipt to it.
+#[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "actix", derive(actix::Message))]
+#[cfg_attr(feature = "actix", rtype(result = "()"))]
+pub struct Receipt {
+    pub message_id: u64,
+    /// User that got or read the message
+    pub recipient: usize,
+    pub status: DeliveryStatus,
+}
+
+/// Receipt routed to the servers of the sender.
+#[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "actix", derive(actix::Message))]
+#[cfg_attr(feature = "actix", rtype(result = "()"))]
+pub struct RoutedReceipt {
+    pub message_id: u64,
+    pub sender: usize,
+    pub recipient: usize,
+    pub status: DeliveryStatus,
+}
+
+/// User came online or went offline on any server.
+#[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "actix", derive(actix::Message))]
+#[cfg_attr(feature = "actix", rtype(result = "()"))]
+pub struct PresenceChanged {
+    pub presence: UserPresence,
+}
+
+/// Sender started or stopped typing to the recipient.
+///
+/// Typing events are not stored in the history, not queued
+/// for offline recipients and get no delivery statuses.
+#[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "actix", derive(actix::Message))]
+#[cfg_attr(feature = "actix", rtype(result = "()"))]
+pub struct TypingEvent {
+    pub sender: usize,
+    pub recipient: usize,
+    pub typing: bool,
+}
+
+/// Message the worker failed to process after all retries.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DeadLetter {
+    /// Id assigned by the cache, replay takes the letter by it
+    pub id: u64,
+    /// Subject the message was received from, replay publishes it there again
+    pub subject: String,
+    /// Message as it was received
+    pub payload: String,
+    /// Error of the last attempt
+    pub reason: String,
+    pub attempts: u32,
+    /// Unix timestamp in milliseconds
+    pub failed_at: u64,
+}
+
+/// Query for a page of the conversation between `user_id` and `peer`.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct HistoryQuery {
+    pub user_id: usize,
+    pub peer: usize,
+    /// Only messages with smaller ids
+    pub before: Option<u64>,
+    /// Only messages with bigger ids
+    pub after: Option<u64>,
+    pub limit: usize,
+    /// Only messages from `peer` with sequence numbers in the range,
+    /// cursors are ignored then
+    #[serde(default)]
+    pub sequence: Option<SequenceRange>,
+}
+
+/// Inclusive range of sequence numbers.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub struct SequenceRange {
+    pub from: u64,
+    pub to: u64,
+}
+
+/// Reply of the worker to the `HistoryQuery`.
+pub type HistoryReply = Result<HistoryPage, JRPCError>;
@@ -0,0 +1,110 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::mq_messages::DeliveryStatus;
+use crate::requests::RequestId;
+
+/// Sent right after the session is registered in the chat server.
+pub const CONNECTED: &str = "connected";
+/// New message for the client.
+pub const MESSAGE: &str = "message";
+/// Messages from the sender are missing, they can be
+/// fetched with `fill_gap`.
+pub const MESSAGE_GAP: &str = "message_gap";
+/// Status of the message the client has sent.
+pub const MESSAGE_STATUS: &str = "message_status";
+/// Watched user came online or went offline, params are `UserPresence`.
+pub const PRESENCE: &str = "presence";
+/// User started or stopped typing to the client.
+pub const TYPING: &str = "typing";
+/// New message in the room the client is a member of.
+pub const ROOM_MESSAGE: &str = "room_message";
+
+/// Member of the params with the number of the event in the session, events
+/// with it are replayed after resume until the client acknowledges them.
+pub const EVENT_SEQ: &str = "event_seq";
+
+/// Server initiated push, notifications never carry an id.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "actix", derive(actix::Message))]
+#[cfg_attr(feature = "actix", rtype(result = "()"))]
+pub struct JRPCNotification {
+    pub jsonrpc: String,
+    pub method: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub params: Option<Value>,
+}
+
+impl JRPCNotification {
+    pub fn new(method: &str, params: impl Serialize) -> Self {
+        Self {
+            jsonrpc: "2.0".into(),
+            method: method.into(),
+            params: Some(serde_json::to_value(params).unwrap()),
+        }
+    }
+
+    /// Numbers the event of the session, params that are not
+    /// an object can't carry the number.
+    pub fn with_event_seq(mut self, seq: u64) -> Self {
+        let params = self.params.get_or_insert_with(|| Value::Object(Map::new()));
+        if let Value::Object(params) = params {
+            params.insert(EVENT_SEQ.to_string(), seq.into());
+        }
+        self
+    }
+
+    /// Number of the event in the session.
+    pub fn event_seq(&self) -> Option<u64> {
+        self.params.as_ref()?.get(EVENT_SEQ)?.as_u64()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ChatMessageParams {
+    /// Id of the message in the history
+    pub id: Option<u64>,
+    pub sender: usize,
+    pub message: String,
+    /// Unix timestamp in milliseconds
+    pub timestamp: Option<u64>,
+    /// Number of the message among the messages from the sender to the client,
+    /// messages the client sends to the sender are numbered on their own
+    #[serde(default)]
+    pub seq: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct MessageGapParams {
+    pub sender: usize,
+    /// First missing sequence number
+    pub from_seq: u64,
+    /// Last missing sequence number
+    pub to_seq: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct MessageStatusParams {
+    /// Id of the `send_message` request, only the session
+    /// that sent the message gets it
+    pub request_id: Option<RequestId>,
+    /// Id of the message in the history
+    pub message_id: Option<u64>,
+    pub recipient: usize,
+    pub status: DeliveryStatus,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RoomMessageParams {
+    pub room_id: usize,
+    pub sender: usize,
+    pub message: String,
+    /// Unix timestamp in milliseconds
+    pub timestamp: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TypingParams {
+    pub sender: usize,
+    pub typing: bool,
+}
@@ -0,0 +1,21 @@
+//! Limits of the queue with messages for offline users.
+
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy)]
+pub struct PendingLimits {
+    /// Queue is dropped if the user doesn't come back in time,
+    /// every new message extends it.
+    pub ttl: Duration,
+    /// Oldest messages are dropped when the queue is full.
+    pub max_len: usize,
+}
+
+impl Default for PendingLimits {
+    fn default() -> Self {
+        Self {
+            ttl: Duration::from_secs(7 * 24 * 60 * 60),
+            max_len: 1000,
+        }
+    }
+}
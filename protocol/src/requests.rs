@@ -0,0 +1,138 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Id of the request, the response echoes it back as it was sent.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+#[serde(untagged)]
+pub enum RequestId {
+    Number(i64),
+    String(String),
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct JRPCRequest {
+    pub jsonrpc: String,
+    pub method: String,
+    pub params: Option<Value>,
+    pub id: Option<RequestId>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct JRPCJoinRequestParams {
+    pub recipient: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct JRPCMessageRequestParams {
+    pub message: String,
+    pub recipient: usize,
+    /// Key of the message chosen by the client, resending the message
+    /// with the same key doesn't deliver it twice
+    #[serde(default)]
+    pub client_message_id: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct JRPCAuthRequestParams {
+    pub token: String,
+    /// Resumes the session that was dropped
+    #[serde(default)]
+    pub resume: Option<ResumeParams>,
+}
+
+/// Session to resume, events after `last_seq` are replayed.
+///
+/// Session can be resumed only on the server that created it,
+/// elsewhere the client gets a new session with `resume_failed`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct ResumeParams {
+    /// Token from the `connected` notification
+    pub token: String,
+    /// Last event the client has acknowledged, 0 if none
+    #[serde(default)]
+    pub last_seq: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct JRPCHistoryRequestParams {
+    /// The other user of the conversation
+    pub peer: usize,
+    /// Cursor, only messages older than the message with this id
+    pub before: Option<u64>,
+    /// Cursor, only messages newer than the message with this id
+    pub after: Option<u64>,
+    /// Page size, 50 if omitted
+    pub limit: Option<usize>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct JRPCFillGapRequestParams {
+    /// Sender of the missing messages
+    pub peer: usize,
+    /// First missing sequence number
+    pub from_seq: u64,
+    /// Last missing sequence number
+    pub to_seq: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct JRPCMarkReadRequestParams {
+    /// Id of the received message
+    pub message_id: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct JRPCCreateRoomRequestParams {
+    /// Users invited to the room, its creator is its first member
+    #[serde(default)]
+    pub members: Vec<usize>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct JRPCRoomRequestParams {
+    pub room_id: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct JRPCRoomInviteRequestParams {
+    pub room_id: usize,
+    /// User who can join the room then
+    pub user_id: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct JRPCRoomMessageRequestParams {
+    pub message: String,
+    pub room_id: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct JRPCPresenceRequestParams {
+    /// Users to watch or look up, at most 256 in one request
+    pub user_ids: Vec<usize>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct JRPCTypingRequestParams {
+    pub recipient: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct JRPCAckEventsRequestParams {
+    /// Events up to this one are received by the client
+    pub seq: u64,
+}
@@ -0,0 +1,402 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::mq_messages::DeliveryStatus;
+use crate::requests::RequestId;
+
+pub trait ResponseResult {
+    fn result(&self) -> Option<serde_json::Value>;
+}
+
+pub trait ResponseError {
+    fn error(&self) -> Option<serde_json::Value>;
+}
+
+pub trait JRPCErrorData {
+    fn data(&self) -> Option<serde_json::Value>;
+}
+
+/// Invalid JSON was received.
+pub const PARSE_ERROR: i64 = -32700;
+/// The JSON sent is not a valid request object.
+pub const INVALID_REQUEST: i64 = -32600;
+/// The method does not exist or is not available.
+pub const METHOD_NOT_FOUND: i64 = -32601;
+/// Invalid method parameters.
+pub const INVALID_PARAMS: i64 = -32602;
+/// Internal JSON-RPC error.
+pub const INTERNAL_ERROR: i64 = -32603;
+
+/// Recipient of a request is not connected to any server.
+pub const RECIPIENT_NOT_FOUND: i64 = -32001;
+/// Cache (redis) cannot be reached.
+pub const CACHE_UNAVAILABLE: i64 = -32002;
+/// Message broker (nats) cannot be reached.
+pub const BROKER_UNAVAILABLE: i64 = -32003;
+/// Connection is not authenticated or token is invalid.
+pub const UNAUTHORIZED: i64 = -32004;
+/// History store cannot be reached.
+pub const HISTORY_UNAVAILABLE: i64 = -32005;
+/// Room does not exist or has no members left.
+pub const ROOM_NOT_FOUND: i64 = -32006;
+/// User is not a member of the room.
+pub const NOT_ROOM_MEMBER: i64 = -32007;
+/// User is not invited to the room it joins.
+pub const NOT_ROOM_INVITED: i64 = -32008;
+
+impl JRPCErrorData for String {
+    fn data(&self) -> Option<serde_json::Value> {
+        Some(Value::String(self.clone()))
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct JRPCError {
+    pub code: i64,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<Value>,
+}
+
+impl JRPCError {
+    pub fn new(code: i64, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            data: None,
+        }
+    }
+
+    pub fn with_data(mut self, data: impl JRPCErrorData) -> Self {
+        self.data = data.data();
+        self
+    }
+
+    pub fn parse_error() -> Self {
+        Self::new(PARSE_ERROR, "Parse error")
+    }
+
+    pub fn invalid_request() -> Self {
+        Self::new(INVALID_REQUEST, "Invalid Request")
+    }
+
+    pub fn method_not_found() -> Self {
+        Self::new(METHOD_NOT_FOUND, "Method not found")
+    }
+
+    pub fn invalid_params() -> Self {
+        Self::new(INVALID_PARAMS, "Invalid params")
+    }
+
+    pub fn internal_error() -> Self {
+        Self::new(INTERNAL_ERROR, "Internal error")
+    }
+
+    pub fn recipient_not_found() -> Self {
+        Self::new(RECIPIENT_NOT_FOUND, "Recipient doesn't exist")
+    }
+
+    pub fn cache_unavailable() -> Self {
+        Self::new(CACHE_UNAVAILABLE, "Cannot connect to the cache")
+    }
+
+    pub fn broker_unavailable() -> Self {
+        Self::new(BROKER_UNAVAILABLE, "Cannot publish to the message broker")
+    }
+
+    pub fn unauthorized() -> Self {
+        Self::new(UNAUTHORIZED, "Unauthorized")
+    }
+
+    pub fn history_unavailable() -> Self {
+        Self::new(HISTORY_UNAVAILABLE, "Cannot read message history")
+    }
+
+    pub fn room_not_found() -> Self {
+        Self::new(ROOM_NOT_FOUND, "Room doesn't exist")
+    }
+
+    pub fn not_room_member() -> Self {
+        Self::new(NOT_ROOM_MEMBER, "User is not a member of the room")
+    }
+
+    pub fn not_room_invited() -> Self {
+        Self::new(NOT_ROOM_INVITED, "User is not invited to the room")
+    }
+}
+
+impl ResponseError for JRPCError {
+    fn error(&self) -> Option<serde_json::Value> {
+        Some(serde_json::to_value(self).unwrap())
+    }
+}
+
+impl ResponseResult for Value {
+    fn result(&self) -> Option<serde_json::Value> {
+        Some(self.clone())
+    }
+}
+
+impl ResponseResult for () {
+    fn result(&self) -> Option<serde_json::Value> {
+        None
+    }
+}
+
+impl ResponseError for () {
+    fn error(&self) -> Option<serde_json::Value> {
+        None
+    }
+}
+
+impl<T: ResponseResult> ResponseResult for Option<T> {
+    fn result(&self) -> Option<serde_json::Value> {
+        match self {
+            Some(result) => result.result(),
+            None => None,
+        }
+    }
+}
+
+impl<T: ResponseError> ResponseError for Option<T> {
+    fn error(&self) -> Option<serde_json::Value> {
+        match self {
+            Some(error) => error.error(),
+            None => None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct JRPCResponse {
+    pub jsonrpc: String,
+    pub id: Option<RequestId>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<Value>,
+}
+
+impl JRPCResponse {
+    pub fn new(
+        id: Option<RequestId>,
+        result: Option<impl ResponseResult>,
+        error: Option<impl ResponseError>,
+    ) -> Self {
+        Self {
+            jsonrpc: "2.0".into(),
+            id,
+            result: result.result(),
+            error: error.error(),
+        }
+    }
+
+    pub fn from_error(id: Option<RequestId>, error: JRPCError) -> Self {
+        Self::new(id, None::<()>, Some(error))
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct SendMessageResult {
+    pub recipient: usize,
+    /// Id of the message in the history, known if the message is a retry
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message_id: Option<u64>,
+    pub status: DeliveryStatus,
+    /// Number of the message among the messages to the recipient
+    pub seq: u64,
+}
+
+impl ResponseResult for SendMessageResult {
+    fn result(&self) -> Option<Value> {
+        Some(serde_json::to_value(self).unwrap())
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct ConnectResult {
+    pub id: usize,
+    pub session_id: usize,
+    /// Token that resumes this session after reconnect
+    pub resume_token: String,
+    /// Existing session is resumed, missed events follow
+    pub resumed: bool,
+    /// Client asked to resume a session this server doesn't have, sessions live
+    /// on the server that created them. Missed events are not replayed,
+    /// the client fetches them from the history.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub resume_failed: bool,
+}
+
+impl ResponseResult for ConnectResult {
+    fn result(&self) -> Option<Value> {
+        Some(serde_json::to_value(self).unwrap())
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct AuthResult {
+    pub user_id: usize,
+}
+
+impl ResponseResult for AuthResult {
+    fn result(&self) -> Option<Value> {
+        Some(serde_json::to_value(self).unwrap())
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct JoinResult {
+    pub joined_user: usize,
+}
+
+impl ResponseResult for JoinResult {
+    fn result(&self) -> Option<Value> {
+        Some(serde_json::to_value(self).unwrap())
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct HistoryMessage {
+    pub id: u64,
+    pub sender: usize,
+    pub recipient: usize,
+    pub message: String,
+    /// Unix timestamp in milliseconds
+    pub timestamp: u64,
+    /// Number of the message among the messages from the sender to the recipient.
+    ///
+    /// Every direction of the conversation is numbered on its own, messages
+    /// of both users have the same numbers. Conversation is ordered by `id`.
+    #[serde(default)]
+    pub seq: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct HistoryPage {
+    /// Messages ordered from the oldest to the newest
+    pub messages: Vec<HistoryMessage>,
+    /// There are more messages past the page in the requested direction
+    pub has_more: bool,
+}
+
+impl ResponseResult for HistoryPage {
+    fn result(&self) -> Option<Value> {
+        Some(serde_json::to_value(self).unwrap())
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct MarkReadResult {
+    pub message_id: u64,
+}
+
+impl ResponseResult for MarkReadResult {
+    fn result(&self) -> Option<Value> {
+        Some(serde_json::to_value(self).unwrap())
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct RoomResult {
+    pub room_id: usize,
+}
+
+impl ResponseResult for RoomResult {
+    fn result(&self) -> Option<Value> {
+        Some(serde_json::to_value(self).unwrap())
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct RoomMembersResult {
+    pub room_id: usize,
+    pub members: Vec<usize>,
+}
+
+impl ResponseResult for RoomMembersResult {
+    fn result(&self) -> Option<Value> {
+        Some(serde_json::to_value(self).unwrap())
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct SendRoomMessageResult {
+    pub room_id: usize,
+    pub status: DeliveryStatus,
+}
+
+impl ResponseResult for SendRoomMessageResult {
+    fn result(&self) -> Option<Value> {
+        Some(serde_json::to_value(self).unwrap())
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct UserPresence {
+    pub user_id: usize,
+    pub online: bool,
+    /// Unix timestamp in milliseconds the user went offline at,
+    /// `None` if it was never seen
+    pub last_seen: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct PresenceResult {
+    pub users: Vec<UserPresence>,
+}
+
+impl ResponseResult for PresenceResult {
+    fn result(&self) -> Option<Value> {
+        Some(serde_json::to_value(self).unwrap())
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct UnsubscribePresenceResult {
+    pub user_ids: Vec<usize>,
+}
+
+impl ResponseResult for UnsubscribePresenceResult {
+    fn result(&self) -> Option<Value> {
+        Some(serde_json::to_value(self).unwrap())
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct TypingResult {
+    pub recipient: usize,
+    pub typing: bool,
+}
+
+impl ResponseResult for TypingResult {
+    fn result(&self) -> Option<Value> {
+        Some(serde_json::to_value(self).unwrap())
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct AckEventsResult {
+    pub seq: u64,
+}
+
+impl ResponseResult for AckEventsResult {
+    fn result(&self) -> Option<Value> {
+        Some(serde_json::to_value(self).unwrap())
+    }
+}
@@ -0,0 +1,42 @@
+//! Which status of a message can follow which.
+
+use hcwc_protocol::mq_messages::DeliveryStatus::{self, *};
+
+#[test]
+fn status_only_moves_forward() {
+    let order = [Accepted, Routed, Delivered, Read];
+    for (i, earlier) in order.iter().enumerate() {
+        for (j, next) in order.iter().enumerate() {
+            assert_eq!(
+                earlier.can_become(*next),
+                i <= j,
+                "{:?} -> {:?}",
+                earlier,
+                next
+            );
+        }
+    }
+    assert!(Queued.can_become(Routed));
+    assert!(Routed.can_become(Queued));
+}
+
+#[test]
+fn only_accepted_message_fails() {
+    assert!(Accepted.can_become(Failed));
+    for status in [Routed, Queued, Delivered, Read] {
+        assert!(!status.can_become(Failed), "{:?} -> Failed", status);
+    }
+    // Retry of the failed message.
+    for status in DeliveryStatus::ALL {
+        assert!(Failed.can_become(status), "Failed -> {:?}", status);
+    }
+}
+
+#[test]
+fn repeated_receipt_is_not_ahead() {
+    assert!(Delivered.is_ahead_of(Routed));
+    assert!(Read.is_ahead_of(Delivered));
+    assert!(!Delivered.is_ahead_of(Delivered));
+    assert!(!Delivered.is_ahead_of(Read));
+    assert!(!Failed.is_ahead_of(Accepted));
+}
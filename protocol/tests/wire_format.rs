@@ -0,0 +1,349 @@
+//! Formats of the JSON-RPC messages, the bus envelopes and the redis keys.
+//!
+//! Other processes read them, any change here breaks mixed deployments.
+
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{json, Value};
+
+use hcwc_protocol::{
+    keys,
+    mq_messages::{
+        self, ClientMessage, DeadLetter, DeliveryStatus, HistoryQuery, MessageStatus,
+        PresenceChanged, Receipt, RoomMessage, RoutedReceipt, RoutedRoomMessage, SentMessage,
+        SequenceRange, TypingEvent,
+    },
+    notifications::{self, ChatMessageParams, JRPCNotification},
+    requests::{JRPCRequest, RequestId},
+    responses::{JRPCError, JRPCResponse, SendMessageResult, UserPresence},
+};
+
+/// Serializes the value to `expected` and reads `expected` back to the same value.
+fn round_trip<T: Serialize + DeserializeOwned>(value: &T, expected: Value) {
+    assert_eq!(serde_json::to_value(value).unwrap(), expected);
+    let parsed: T = serde_json::from_value(expected.clone()).unwrap();
+    assert_eq!(serde_json::to_value(parsed).unwrap(), expected);
+}
+
+fn client_message() -> ClientMessage {
+    ClientMessage {
+        id: 1,
+        session_id: 2,
+        server_uuid: "server".to_string(),
+        request_id: Some(RequestId::Number(3)),
+        msg: "hello".to_string(),
+        recipient: 4,
+        message_id: Some(5),
+        timestamp: Some(6),
+        seq: Some(7),
+        client_message_id: Some("client-1".to_string()),
+    }
+}
+
+#[test]
+fn request() {
+    let request = JRPCRequest {
+        jsonrpc: "2.0".to_string(),
+        method: "send_message".to_string(),
+        params: Some(json!({"recipient": 2, "message": "hi"})),
+        id: Some(RequestId::Number(1)),
+    };
+    round_trip(
+        &request,
+        json!({
+            "jsonrpc": "2.0",
+            "method": "send_message",
+            "params": {"recipient": 2, "message": "hi"},
+            "id": 1,
+        }),
+    );
+}
+
+#[test]
+fn request_ids() {
+    round_trip(&RequestId::Number(-1), json!(-1));
+    round_trip(&RequestId::String("a1".to_string()), json!("a1"));
+
+    let request: JRPCRequest =
+        serde_json::from_value(json!({"jsonrpc": "2.0", "method": "ping", "id": null})).unwrap();
+    assert_eq!(request.id, None);
+    let request: JRPCRequest =
+        serde_json::from_value(json!({"jsonrpc": "2.0", "method": "ping"})).unwrap();
+    assert_eq!(request.id, None);
+}
+
+#[test]
+fn response() {
+    let result = SendMessageResult {
+        recipient: 2,
+        message_id: None,
+        status: DeliveryStatus::Accepted,
+        seq: 3,
+    };
+    round_trip(
+        &JRPCResponse::new(
+            Some(RequestId::String("a1".to_string())),
+            Some(result),
+            None::<()>,
+        ),
+        json!({
+            "jsonrpc": "2.0",
+            "id": "a1",
+            "result": {"recipient": 2, "status": "accepted", "seq": 3},
+        }),
+    );
+}
+
+#[test]
+fn error_response() {
+    round_trip(
+        &JRPCResponse::from_error(None, JRPCError::parse_error()),
+        json!({
+            "jsonrpc": "2.0",
+            "id": null,
+            "error": {"code": -32700, "message": "Parse error"},
+        }),
+    );
+    round_trip(
+        &JRPCError::invalid_params().with_data("recipient is missing".to_string()),
+        json!({
+            "code": -32602,
+            "message": "Invalid params",
+            "data": "recipient is missing",
+        }),
+    );
+}
+
+#[test]
+fn notification() {
+    let notification = JRPCNotification::new(
+        notifications::MESSAGE,
+        ChatMessageParams {
+            id: Some(1),
+            sender: 2,
+            message: "hi".to_string(),
+            timestamp: Some(3),
+            seq: Some(4),
+        },
+    )
+    .with_event_seq(5);
+    assert_eq!(notification.event_seq(), Some(5));
+    round_trip(
+        &notification,
+        json!({
+            "jsonrpc": "2.0",
+            "method": "message",
+            "params": {
+                "id": 1,
+                "sender": 2,
+                "message": "hi",
+                "timestamp": 3,
+                "seq": 4,
+                "event_seq": 5,
+            },
+        }),
+    );
+}
+
+#[test]
+fn client_message_envelope() {
+    round_trip(
+        &client_message(),
+        json!({
+            "id": 1,
+            "session_id": 2,
+            "server_uuid": "server",
+            "request_id": 3,
+            "msg": "hello",
+            "recipient": 4,
+            "message_id": 5,
+            "timestamp": 6,
+            "seq": 7,
+            "client_message_id": "client-1",
+        }),
+    );
+
+    // Envelopes from older servers have no optional fields.
+    let message: ClientMessage = serde_json::from_value(json!({
+        "id": 1,
+        "session_id": 2,
+        "server_uuid": "server",
+        "request_id": null,
+        "msg": "hello",
+        "recipient": 4,
+    }))
+    .unwrap();
+    assert_eq!(message.seq, None);
+    assert_eq!(message.client_message_id, None);
+}
+
+#[test]
+fn status_envelopes() {
+    round_trip(
+        &MessageStatus::new(&client_message(), DeliveryStatus::Routed),
+        json!({
+            "id": 1,
+            "session_id": 2,
+            "request_id": 3,
+            "message_id": 5,
+            "recipient": 4,
+            "status": "routed",
+        }),
+    );
+    round_trip(
+        &SentMessage {
+            message_id: Some(5),
+            seq: Some(7),
+            status: DeliveryStatus::Failed,
+        },
+        json!({"message_id": 5, "seq": 7, "status": "failed"}),
+    );
+}
+
+#[test]
+fn receipt_envelopes() {
+    round_trip(
+        &Receipt {
+            message_id: 1,
+            recipient: 2,
+            status: DeliveryStatus::Delivered,
+        },
+        json!({"message_id": 1, "recipient": 2, "status": "delivered"}),
+    );
+    round_trip(
+        &RoutedReceipt {
+            message_id: 1,
+            sender: 3,
+            recipient: 2,
+            status: DeliveryStatus::Read,
+        },
+        json!({"message_id": 1, "sender": 3, "recipient": 2, "status": "read"}),
+    );
+}
+
+#[test]
+fn room_envelopes() {
+    round_trip(
+        &RoutedRoomMessage {
+            members: vec![2, 3],
+            message: RoomMessage {
+                id: 1,
+                server_uuid: "server".to_string(),
+                room_id: 4,
+                msg: "hi".to_string(),
+                timestamp: Some(5),
+            },
+        },
+        json!({
+            "members": [2, 3],
+            "message": {
+                "id": 1,
+                "server_uuid": "server",
+                "room_id": 4,
+                "msg": "hi",
+                "timestamp": 5,
+            },
+        }),
+    );
+}
+
+#[test]
+fn presence_and_typing_envelopes() {
+    round_trip(
+        &PresenceChanged {
+            presence: UserPresence {
+                user_id: 1,
+                online: false,
+                last_seen: Some(2),
+            },
+        },
+        json!({"presence": {"user_id": 1, "online": false, "last_seen": 2}}),
+    );
+    round_trip(
+        &TypingEvent {
+            sender: 1,
+            recipient: 2,
+            typing: true,
+        },
+        json!({"sender": 1, "recipient": 2, "typing": true}),
+    );
+}
+
+#[test]
+fn history_query_envelope() {
+    round_trip(
+        &HistoryQuery {
+            user_id: 1,
+            peer: 2,
+            before: Some(3),
+            after: None,
+            limit: 50,
+            sequence: Some(SequenceRange { from: 4, to: 5 }),
+        },
+        json!({
+            "user_id": 1,
+            "peer": 2,
+            "before": 3,
+            "after": null,
+            "limit": 50,
+            "sequence": {"from": 4, "to": 5},
+        }),
+    );
+}
+
+#[test]
+fn dead_letter_envelope() {
+    round_trip(
+        &DeadLetter {
+            id: 1,
+            subject: "message.publish".to_string(),
+            payload: "{}".to_string(),
+            reason: "Cache error".to_string(),
+            attempts: 5,
+            failed_at: 2,
+        },
+        json!({
+            "id": 1,
+            "subject": "message.publish",
+            "payload": "{}",
+            "reason": "Cache error",
+            "attempts": 5,
+            "failed_at": 2,
+        }),
+    );
+}
+
+#[test]
+fn redis_keys() {
+    assert_eq!(keys::SERVERS_KEY, "hcwc_servers");
+    assert_eq!(keys::user_key(42), "hcwc.user.42");
+    assert_eq!(keys::server_key("abc"), "hcwc.server.abc");
+    assert_eq!(keys::server_users_key("abc"), "hcwc.server.abc.users");
+    assert_eq!(keys::pending_key(42), "hcwc.pending.42");
+    assert_eq!(keys::ROOM_ID_KEY, "hcwc_room_id");
+    assert_eq!(keys::room_key(7), "hcwc.room.7");
+    assert_eq!(keys::last_seen_key(42), "hcwc.last_seen.42");
+    assert_eq!(keys::sequence_key(1, 2), "hcwc.seq.1.2");
+    assert_eq!(keys::sent_message_key(1, "a1"), "hcwc.sent.1.a1");
+    assert_eq!(keys::DEADLETTER_ID_KEY, "hcwc_deadletter_id");
+    assert_eq!(keys::DEADLETTERS_KEY, "hcwc_deadletters");
+}
+
+#[test]
+fn conversation_id_is_the_same_for_both_users() {
+    assert_eq!(keys::conversation_id(2, 1), "1:2");
+    assert_eq!(keys::conversation_id(1, 2), "1:2");
+}
+
+#[test]
+fn server_subjects() {
+    assert_eq!(
+        mq_messages::server_subjects("abc"),
+        [
+            "message.abc.send",
+            "message.abc.status",
+            "message.abc.receipt",
+            "message.abc.room",
+            "typing.abc.send",
+        ]
+    );
+}
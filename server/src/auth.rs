@@ -0,0 +1,122 @@
+use std::fmt;
+
+use jsonwebtoken::{decode, Algorithm, DecodingKey, Validation};
+use serde::Deserialize;
+
+#[derive(Debug)]
+pub enum AuthError {
+    /// Token is malformed, expired or signed with another secret.
+    InvalidToken(jsonwebtoken::errors::Error),
+    /// Subject of the token is not a valid user id.
+    InvalidSubject(String),
+}
+
+impl fmt::Display for AuthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuthError::InvalidToken(err) => write!(f, "Invalid token: {}", err),
+            AuthError::InvalidSubject(sub) => write!(f, "Invalid token subject: {}", sub),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct Claims {
+    /// Stable id of the user
+    sub: String,
+}
+
+/// Verifies HMAC (HS256) signed JWTs issued to the clients.
+pub struct TokenVerifier {
+    key: DecodingKey,
+    validation: Validation,
+}
+
+impl TokenVerifier {
+    pub fn new(secret: &[u8]) -> Self {
+        Self {
+            key: DecodingKey::from_secret(secret),
+            validation: Validation::new(Algorithm::HS256),
+        }
+    }
+
+    /// Returns id of the user the token was issued to.
+    pub fn verify(&self, token: &str) -> Result<usize, AuthError> {
+        let token_data = decode::<Claims>(token, &self.key, &self.validation)
+            .map_err(AuthError::InvalidToken)?;
+        token_data
+            .claims
+            .sub
+            .parse::<usize>()
+            .map_err(|_| AuthError::InvalidSubject(token_data.claims.sub))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    use jsonwebtoken::{encode, errors::ErrorKind, EncodingKey, Header};
+    use serde::Serialize;
+
+    use super::*;
+
+    #[derive(Serialize)]
+    struct TestClaims<'a> {
+        sub: &'a str,
+        exp: u64,
+    }
+
+    fn token(secret: &[u8], sub: &str, expires_in: i64) -> String {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let claims = TestClaims {
+            sub,
+            exp: now.saturating_add_signed(expires_in),
+        };
+        encode(
+            &Header::default(),
+            &claims,
+            &EncodingKey::from_secret(secret),
+        )
+        .unwrap()
+    }
+
+    fn error_kind(result: Result<usize, AuthError>) -> ErrorKind {
+        match result {
+            Err(AuthError::InvalidToken(err)) => err.into_kind(),
+            other => panic!("token is not rejected: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn valid_token_gives_user_id() {
+        let verifier = TokenVerifier::new(b"secret");
+        assert_eq!(verifier.verify(&token(b"secret", "42", 3600)).unwrap(), 42);
+    }
+
+    #[test]
+    fn expired_token_is_rejected() {
+        let verifier = TokenVerifier::new(b"secret");
+        let kind = error_kind(verifier.verify(&token(b"secret", "42", -3600)));
+        assert_eq!(kind, ErrorKind::ExpiredSignature);
+    }
+
+    #[test]
+    fn token_of_other_secret_is_rejected() {
+        let verifier = TokenVerifier::new(b"secret");
+        let kind = error_kind(verifier.verify(&token(b"other", "42", 3600)));
+        assert_eq!(kind, ErrorKind::InvalidSignature);
+    }
+
+    #[test]
+    fn subject_must_be_user_id() {
+        let verifier = TokenVerifier::new(b"secret");
+        assert!(matches!(
+            verifier.verify(&token(b"secret", "alice", 3600)),
+            Err(AuthError::InvalidSubject(sub)) if sub == "alice"
+        ));
+    }
+}
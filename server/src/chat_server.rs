@@ -1,210 +1,1124 @@
-use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
-use actix::{Actor, Context, Handler, Message, Recipient};
+use actix::{
+    Actor, AsyncContext, Context, Handler, Message, Recipient, ResponseFuture, SpawnHandle,
+};
 use bytes::Bytes;
 use rand::{rngs::ThreadRng, Rng};
-use redis::Commands;
 
-use crate::{
-    connections_manager::ConnectionManager,
-    responses::{ChatMessageResult, ConnectResult, JRPCResponse, JoinError, JoinResult},
+use hcwc_bus::MessageBus;
+use hcwc_cache::{CacheDB, CacheError};
+use hcwc_protocol::{
+    mq_messages::{
+        self, ClientMessage, DeliveryStatus, HistoryQuery, HistoryReply, MessageStatus,
+        PresenceChanged, Receipt, RoomMessage, RoutedReceipt, RoutedRoomMessage, SentMessage,
+        TypingEvent, DEDUP_WINDOW, HISTORY_SUBJECT, PRESENCE_SUBJECT, PUBLISH_SUBJECT,
+        RECEIPT_SUBJECT, ROOM_PUBLISH_SUBJECT, TYPING_PUBLISH_SUBJECT,
+    },
+    notifications::{
+        self, ChatMessageParams, JRPCNotification, MessageStatusParams, RoomMessageParams,
+        TypingParams,
+    },
+    pending::PendingLimits,
+    requests::ResumeParams,
+    responses::{
+        AckEventsResult, ConnectResult, HistoryPage, JRPCError, JoinResult, MarkReadResult,
+        PresenceResult, RoomMembersResult, RoomResult, SendMessageResult, SendRoomMessageResult,
+        TypingResult, UnsubscribePresenceResult, UserPresence,
+    },
 };
 
-#[derive(Debug)]
+use crate::connections_manager::ConnectionManager;
+use crate::presence_watchers::PresenceWatchers;
+
+/// User is online while any of its sessions refreshes the presence in time,
+/// it is longer than the client timeout of the session.
+pub const PRESENCE_TTL: Duration = Duration::from_secs(30);
+
+/// Typing indicator is stopped for the recipient if the sender
+/// doesn't repeat `typing_start` in time.
+const TYPING_TTL: Duration = Duration::from_secs(6);
+
+/// Dropped session can be resumed for this long, it is shorter
+/// than `PRESENCE_TTL` so the user stays online meanwhile.
+///
+/// Client timeout is not longer than this, see `ServerConfig`.
+pub const RESUME_GRACE: Duration = Duration::from_secs(20);
+
+const _: () = assert!(RESUME_GRACE.as_secs() < PRESENCE_TTL.as_secs());
+
+/// Longest client message id, ids are a part of the cache keys.
+const MAX_CLIENT_MESSAGE_ID_LEN: usize = 128;
+
+/// Most users one presence request takes, each of them is read from the cache.
+const MAX_PRESENCE_USERS: usize = 256;
+
+fn check_presence_users(user_ids: &[usize]) -> Result<(), JRPCError> {
+    if user_ids.len() > MAX_PRESENCE_USERS {
+        return Err(JRPCError::invalid_params().with_data(format!(
+            "user_ids has more than {} users",
+            MAX_PRESENCE_USERS
+        )));
+    }
+    Ok(())
+}
+
 pub struct ChatServer {
     connection_manager: ConnectionManager,
+    presence_watchers: PresenceWatchers,
+    /// Expiry timers of the typing indicators shown to the users
+    /// of this server, keyed by sender and recipient
+    typing: HashMap<(usize, usize), SpawnHandle>,
+    /// Timers that remove dropped sessions, keyed by user id and session id
+    resume_expiry: HashMap<(usize, usize), SpawnHandle>,
     rng: ThreadRng,
-    redis_pool: r2d2::Pool<redis::Client>,
-    nats_conn: async_nats::Client,
+    cache: Arc<dyn CacheDB>,
+    bus: Arc<dyn MessageBus>,
     chat_uuid: String,
+    pending_limits: PendingLimits,
 }
 
 impl ChatServer {
     pub fn new(
-        redis_conn: r2d2::Pool<redis::Client>,
-        nats_conn: async_nats::Client,
+        cache: Arc<dyn CacheDB>,
+        bus: Arc<dyn MessageBus>,
         chat_uuid: String,
+        pending_limits: PendingLimits,
     ) -> ChatServer {
         ChatServer {
             connection_manager: ConnectionManager::new(),
+            presence_watchers: PresenceWatchers::new(),
+            typing: HashMap::new(),
+            resume_expiry: HashMap::new(),
             rng: rand::thread_rng(),
-            redis_pool: redis_conn,
-            chat_uuid: chat_uuid,
-            nats_conn: nats_conn,
+            cache,
+            chat_uuid,
+            bus,
+            pending_limits,
         }
     }
 }
 
+/// Reports status of the message to the server of its sender.
+fn publish_status(bus: &Arc<dyn MessageBus>, message: &ClientMessage, status: DeliveryStatus) {
+    let bus = bus.clone();
+    let subject = mq_messages::status_subject(&message.server_uuid);
+    let status = MessageStatus::new(message, status);
+    tokio::spawn(async move {
+        if bus
+            .publish(
+                &subject,
+                Bytes::from(serde_json::to_string(&status).unwrap()),
+            )
+            .await
+            .is_err()
+        {
+            println!("Cannot publish message status");
+        }
+    });
+}
+
+/// Tells every server that the user came online or went offline.
+async fn publish_presence(bus: &dyn MessageBus, presence: UserPresence) {
+    let changed = PresenceChanged { presence };
+    if bus
+        .publish(
+            PRESENCE_SUBJECT,
+            Bytes::from(serde_json::to_string(&changed).unwrap()),
+        )
+        .await
+        .is_err()
+    {
+        println!("Cannot publish presence");
+    }
+}
+
+/// Presence of the users as the cache sees it.
+async fn read_presence(
+    cache: &dyn CacheDB,
+    user_ids: &[usize],
+) -> Result<Vec<UserPresence>, JRPCError> {
+    let mut users = Vec::with_capacity(user_ids.len());
+    for user_id in user_ids {
+        let online = cache
+            .is_user_exist(*user_id)
+            .await
+            .map_err(|_| JRPCError::cache_unavailable())?;
+        let last_seen = cache
+            .last_seen(*user_id)
+            .await
+            .map_err(|_| JRPCError::cache_unavailable())?;
+        users.push(UserPresence {
+            user_id: *user_id,
+            online,
+            last_seen,
+        });
+    }
+    Ok(users)
+}
+
+/// Remembers the number and the status of the message with the client message id.
+async fn remember_sent(cache: &dyn CacheDB, message: &ClientMessage, status: DeliveryStatus) {
+    let Some(client_message_id) = &message.client_message_id else {
+        return;
+    };
+    let sent = SentMessage {
+        message_id: None,
+        seq: message.seq,
+        status,
+    };
+    if cache
+        .update_sent_message(message.id, client_message_id, sent, DEDUP_WINDOW)
+        .await
+        .is_err()
+    {
+        println!("Cannot update sent message in the cache");
+    }
+}
+
+/// Unix timestamp in milliseconds.
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+fn chat_message_notification(message: &ClientMessage) -> JRPCNotification {
+    JRPCNotification::new(
+        notifications::MESSAGE,
+        ChatMessageParams {
+            id: message.message_id,
+            sender: message.id,
+            message: message.msg.clone(),
+            timestamp: message.timestamp,
+            seq: message.seq,
+        },
+    )
+}
+
 impl Actor for ChatServer {
     type Context = Context<Self>;
 }
 
-/// New chat session is created
+/// New chat session of the authenticated user is created
+/// or the dropped one is resumed, returns id of the session.
 #[derive(Message)]
 #[rtype(usize)]
 pub struct Connect {
-    pub addr: Recipient<JRPCResponse>,
+    pub user_id: usize,
+    pub addr: Recipient<JRPCNotification>,
+    pub resume: Option<ResumeParams>,
 }
 
-/// Session is disconnected
+/// Socket of the session is disconnected,
+/// the session waits for resume for `RESUME_GRACE`.
 #[derive(Message)]
 #[rtype(result = "()")]
 pub struct Disconnect {
+    /// User ID
     pub id: usize,
+    pub session_id: usize,
+    pub addr: Recipient<JRPCNotification>,
+}
+
+/// Client received the events of the session up to `seq`.
+#[derive(Message)]
+#[rtype(result = "Result<AckEventsResult, JRPCError>")]
+pub struct AckEvents {
+    pub user_id: usize,
+    pub session_id: usize,
+    pub seq: u64,
 }
 
-/// Join room, if room does not exists create new one.
+/// Session of the user answered the heartbeat, user is still online.
 #[derive(Message)]
 #[rtype(result = "()")]
-pub struct Join {
-    /// Client ID
-    pub id: usize,
+pub struct RefreshPresence {
+    pub user_id: usize,
+}
 
+/// Session starts watching presence of the users,
+/// returns their current presence.
+#[derive(Message)]
+#[rtype(result = "Result<PresenceResult, JRPCError>")]
+pub struct SubscribePresence {
+    pub user_id: usize,
+    pub session_id: usize,
+    pub user_ids: Vec<usize>,
+}
+
+/// Session stops watching presence of the users.
+#[derive(Message)]
+#[rtype(result = "Result<UnsubscribePresenceResult, JRPCError>")]
+pub struct UnsubscribePresence {
+    pub user_id: usize,
+    pub session_id: usize,
+    pub user_ids: Vec<usize>,
+}
+
+/// Current presence of the users.
+#[derive(Message)]
+#[rtype(result = "Result<PresenceResult, JRPCError>")]
+pub struct GetPresence {
+    pub user_ids: Vec<usize>,
+}
+
+/// Checks that the recipient is online.
+#[derive(Message)]
+#[rtype(result = "Result<JoinResult, JRPCError>")]
+pub struct Join {
     /// recipient name
     pub recipient: usize,
 }
 
-#[derive(Serialize, Deserialize, Message)]
-#[rtype(result = "()")]
-pub struct ClientMessage {
-    /// Id of the client session
-    pub id: usize,
-    /// Peer message
-    pub msg: String,
-    /// Recipient
+/// Page of the conversation, the worker reads it from the history store.
+#[derive(Message)]
+#[rtype(result = "Result<HistoryPage, JRPCError>")]
+pub struct FetchHistory {
+    pub query: HistoryQuery,
+}
+
+/// Recipient read the message.
+#[derive(Message)]
+#[rtype(result = "Result<MarkReadResult, JRPCError>")]
+pub struct MarkRead {
+    pub user_id: usize,
+    pub message_id: u64,
+}
+
+/// New room with the user as its member, the given members are invited.
+#[derive(Message)]
+#[rtype(result = "Result<RoomResult, JRPCError>")]
+pub struct CreateRoom {
+    pub user_id: usize,
+    pub members: Vec<usize>,
+}
+
+/// Member of the room invites the user to it.
+#[derive(Message)]
+#[rtype(result = "Result<RoomResult, JRPCError>")]
+pub struct InviteRoom {
+    pub user_id: usize,
+    pub room_id: usize,
+    pub invited: usize,
+}
+
+/// Invited user becomes a member of the room.
+#[derive(Message)]
+#[rtype(result = "Result<RoomResult, JRPCError>")]
+pub struct JoinRoom {
+    pub user_id: usize,
+    pub room_id: usize,
+}
+
+/// User stops being a member of the room.
+#[derive(Message)]
+#[rtype(result = "Result<RoomResult, JRPCError>")]
+pub struct LeaveRoom {
+    pub user_id: usize,
+    pub room_id: usize,
+}
+
+/// Members of the room, only a member can list them.
+#[derive(Message)]
+#[rtype(result = "Result<RoomMembersResult, JRPCError>")]
+pub struct RoomMembers {
+    pub user_id: usize,
+    pub room_id: usize,
+}
+
+/// User started or stopped typing to the recipient.
+#[derive(Message)]
+#[rtype(result = "Result<TypingResult, JRPCError>")]
+pub struct Typing {
+    pub sender: usize,
     pub recipient: usize,
+    pub typing: bool,
 }
 
-impl Handler<Connect> for ChatServer {
-    type Result = usize;
+/// Message routed to this server by the worker.
+///
+/// Succeeds once the message is handed to the sessions of the recipient
+/// or queued for it, the bus message is acked only then.
+#[derive(Message)]
+#[rtype(result = "Result<(), CacheError>")]
+pub struct Deliver {
+    pub message: ClientMessage,
+}
 
-    fn handle(&mut self, msg: Connect, _: &mut Context<Self>) -> Self::Result {
-        let id = self.rng.gen::<usize>();
+impl ChatServer {
+    /// Shows or hides the typing indicator in every session of the recipient,
+    /// typing events are not replayed after resume.
+    fn notify_typing(&self, sender: usize, recipient: usize, typing: bool) {
+        let notification =
+            JRPCNotification::new(notifications::TYPING, TypingParams { sender, typing });
+        for addr in self.connection_manager.retrieve_connections(&recipient) {
+            addr.do_send(notification.clone());
+        }
+    }
 
-        self.redis_pool
-            .get()
-            .unwrap()
-            .set::<&str, &str, String>(format!("hcwc.user.{}", id).as_str(), &self.chat_uuid)
-            .unwrap();
+    /// Hides the typing indicator shown before the message of the sender,
+    /// the recipient doesn't wait for `TYPING_TTL` once the message comes.
+    fn stop_typing(&mut self, sender: usize, recipient: usize, ctx: &mut Context<Self>) {
+        if let Some(handle) = self.typing.remove(&(sender, recipient)) {
+            ctx.cancel_future(handle);
+            self.notify_typing(sender, recipient, false);
+        }
+    }
+}
+
+impl ChatServer {
+    /// Attaches the socket to the session of the user, returns id of the resumed session.
+    ///
+    /// Old socket of the session loses it even if its `Disconnect` is not handled yet.
+    /// Resume tokens are known to this server only, on another server the client
+    /// gets a new session with `resume_failed` and re-syncs from the history.
+    fn resume_session(
+        &mut self,
+        user_id: usize,
+        resume: &ResumeParams,
+        addr: &Recipient<JRPCNotification>,
+        ctx: &mut Context<Self>,
+    ) -> Option<usize> {
+        let (owner, session_id) = self.connection_manager.find_session(&resume.token)?;
+        if owner != user_id {
+            return None;
+        }
 
-        self.connection_manager.add_connection(id, msg.addr.clone());
+        let replay =
+            self.connection_manager
+                .resume(&user_id, &session_id, resume.last_seq, addr.clone())?;
 
-        msg.addr.do_send(JRPCResponse::new(
-            None,
-            Some(ConnectResult { id: id }),
-            None::<()>,
+        // Client learns the session is resumed before the replay.
+        addr.do_send(JRPCNotification::new(
+            notifications::CONNECTED,
+            ConnectResult {
+                id: user_id,
+                session_id,
+                resume_token: resume.token.clone(),
+                resumed: true,
+                resume_failed: false,
+            },
         ));
+        for event in replay {
+            addr.do_send(event);
+        }
+
+        if let Some(handle) = self.resume_expiry.remove(&(user_id, session_id)) {
+            ctx.cancel_future(handle);
+        }
+        Some(session_id)
+    }
+
+    /// Removes the session that wasn't resumed in time.
+    fn remove_session(&mut self, user_id: usize, session_id: usize) {
+        self.resume_expiry.remove(&(user_id, session_id));
+        self.presence_watchers.remove_session((user_id, session_id));
 
-        id
+        // Server stays in the user's set while any other session of the user is here.
+        if !self
+            .connection_manager
+            .remove_connection(&user_id, &session_id)
+        {
+            return;
+        }
+
+        let cache = self.cache.clone();
+        let bus = self.bus.clone();
+        let chat_uuid = self.chat_uuid.clone();
+        tokio::spawn(async move {
+            match cache.remove_user(user_id, &chat_uuid).await {
+                Err(_) => println!("Problem with the cache"),
+                Ok(false) => println!("Cannot find user in the cache"),
+                Ok(true) => println!("User deleted"),
+            }
+
+            // User may still be online on another server.
+            if cache.is_user_exist(user_id).await.unwrap_or(true) {
+                return;
+            }
+            let last_seen = now();
+            if cache.set_last_seen(user_id, last_seen).await.is_err() {
+                println!("Cannot set last seen in the cache");
+            }
+            let presence = UserPresence {
+                user_id,
+                online: false,
+                last_seen: Some(last_seen),
+            };
+            publish_presence(bus.as_ref(), presence).await;
+        });
     }
 }
 
-impl Handler<Disconnect> for ChatServer {
-    type Result = ();
+impl Handler<Connect> for ChatServer {
+    type Result = usize;
 
-    fn handle(&mut self, msg: Disconnect, _: &mut Context<Self>) {
-        let redis_conn = self.redis_pool.get();
+    fn handle(&mut self, msg: Connect, ctx: &mut Context<Self>) -> Self::Result {
+        let id = msg.user_id;
+        let resumed = msg
+            .resume
+            .as_ref()
+            .and_then(|resume| self.resume_session(id, resume, &msg.addr, ctx));
+        let first_session = !self.connection_manager.is_connected(&id);
 
-        if let Ok(mut redis_conn) = redis_conn {
-            let deleted_connection: Result<usize, redis::RedisError> =
-                redis_conn.srem::<&str, &usize, usize>("connections", &msg.id);
-            match deleted_connection {
-                Err(_) => {
-                    println!("Problem with redis");
-                }
-                Ok(deleted_connection) => {
-                    if deleted_connection == 0 {
-                        println!("Cannot find user in redis")
-                    } else {
-                        println!("User deleted")
+        let session_id = match resumed {
+            Some(session_id) => session_id,
+            None => {
+                let session_id = self.rng.gen::<usize>();
+                let resume_token = format!("{:032x}", self.rng.gen::<u128>());
+                self.connection_manager.add_connection(
+                    id,
+                    session_id,
+                    resume_token.clone(),
+                    msg.addr.clone(),
+                );
+
+                msg.addr.do_send(JRPCNotification::new(
+                    notifications::CONNECTED,
+                    ConnectResult {
+                        id,
+                        session_id,
+                        resume_token,
+                        resumed: false,
+                        resume_failed: msg.resume.is_some(),
+                    },
+                ));
+                session_id
+            }
+        };
+
+        let cache = self.cache.clone();
+        let bus = self.bus.clone();
+        let chat_uuid = self.chat_uuid.clone();
+        let server = ctx.address();
+        tokio::spawn(async move {
+            if cache
+                .add_new_user(id, &chat_uuid, PRESENCE_TTL)
+                .await
+                .is_err()
+            {
+                println!("Cannot add user to the cache");
+            }
+
+            if first_session {
+                let presence = UserPresence {
+                    user_id: id,
+                    online: true,
+                    last_seen: cache.last_seen(id).await.unwrap_or_default(),
+                };
+                publish_presence(bus.as_ref(), presence).await;
+            }
+
+            // Messages that came while the user was offline,
+            // the session sends delivery receipts for them.
+            // User is routable already, live messages may beat these,
+            // the session writes them out of order rather than drop them.
+            match cache.take_pending_messages(id).await {
+                Ok(messages) => {
+                    for message in messages {
+                        if let Ok(Err(err)) = server.send(Deliver { message }).await {
+                            println!("Cannot queue message in the cache: {}", err);
+                        }
                     }
                 }
+                Err(_) => println!("Cannot take pending messages from the cache"),
             }
-        }
+        });
+
+        session_id
+    }
+}
+
+impl Handler<Disconnect> for ChatServer {
+    type Result = ();
 
-        self.connection_manager.remove_connection(&msg.id);
-        if let Some(recipient) = self.connection_manager.retrieve_connection(&msg.id) {
-            recipient.do_send(JRPCResponse::new(None, None::<()>, None::<()>));
+    fn handle(&mut self, msg: Disconnect, ctx: &mut Context<Self>) {
+        let Disconnect {
+            id,
+            session_id,
+            addr,
+        } = msg;
+        // Session is already dropped or resumed by another socket.
+        if !self.connection_manager.detach(&id, &session_id, &addr) {
+            return;
         }
+
+        let handle = ctx.run_later(RESUME_GRACE, move |act, _| {
+            act.remove_session(id, session_id);
+        });
+        self.resume_expiry.insert((id, session_id), handle);
+
+        // Session doesn't refresh the presence while it is dropped.
+        let cache = self.cache.clone();
+        let chat_uuid = self.chat_uuid.clone();
+        tokio::spawn(async move {
+            if cache
+                .add_new_user(id, &chat_uuid, PRESENCE_TTL)
+                .await
+                .is_err()
+            {
+                println!("Cannot refresh user in the cache");
+            }
+        });
+    }
+}
+
+impl Handler<AckEvents> for ChatServer {
+    type Result = Result<AckEventsResult, JRPCError>;
+
+    fn handle(&mut self, msg: AckEvents, _: &mut Context<Self>) -> Self::Result {
+        self.connection_manager
+            .ack(&msg.user_id, &msg.session_id, msg.seq);
+        Ok(AckEventsResult { seq: msg.seq })
     }
 }
 
-impl Handler<JRPCResponse> for ChatServer {
+impl Handler<RefreshPresence> for ChatServer {
     type Result = ();
 
-    fn handle(&mut self, msg: JRPCResponse, _: &mut Context<Self>) {
-        let jrpc_result =
-            serde_json::from_value::<ChatMessageResult>(msg.result.clone().unwrap()).unwrap();
-        if let Some(addr) = self
+    fn handle(&mut self, msg: RefreshPresence, _: &mut Context<Self>) {
+        let cache = self.cache.clone();
+        let chat_uuid = self.chat_uuid.clone();
+        tokio::spawn(async move {
+            if cache
+                .add_new_user(msg.user_id, &chat_uuid, PRESENCE_TTL)
+                .await
+                .is_err()
+            {
+                println!("Cannot refresh user in the cache");
+            }
+        });
+    }
+}
+
+impl Handler<Deliver> for ChatServer {
+    type Result = ResponseFuture<Result<(), CacheError>>;
+
+    fn handle(&mut self, msg: Deliver, ctx: &mut Context<Self>) -> Self::Result {
+        let Deliver { message } = msg;
+        let notification = chat_message_notification(&message);
+        self.stop_typing(message.id, message.recipient, ctx);
+
+        // Every device of the recipient gets the message,
+        // sessions send delivery receipts once they write it to the socket.
+        if self
             .connection_manager
-            .retrieve_connection(&jrpc_result.recipient)
+            .retrieve_connections(&message.recipient)
+            .next()
+            .is_some()
         {
-            addr.do_send(msg);
+            self.connection_manager
+                .send_all(&message.recipient, &notification);
+            return Box::pin(async { Ok(()) });
         }
+
+        // Dropped sessions get the message from the pending queue on resume.
+        let dropped = self.connection_manager.is_connected(&message.recipient);
+        let cache = self.cache.clone();
+        let bus = self.bus.clone();
+        let pending_limits = self.pending_limits;
+        Box::pin(async move {
+            // Recipient disconnected after the worker routed the message here.
+            // If the recipient is online on another server it gets the message there.
+            if !dropped
+                && cache
+                    .is_user_exist(message.recipient)
+                    .await
+                    .unwrap_or(false)
+            {
+                return Ok(());
+            }
+
+            cache.queue_message(&message, pending_limits).await?;
+            publish_status(&bus, &message, DeliveryStatus::Queued);
+            Ok(())
+        })
     }
 }
 
 impl Handler<ClientMessage> for ChatServer {
+    type Result = ResponseFuture<Result<SendMessageResult, JRPCError>>;
+
+    fn handle(&mut self, mut msg: ClientMessage, _: &mut Context<Self>) -> Self::Result {
+        msg.server_uuid = self.chat_uuid.clone();
+        let cache = self.cache.clone();
+        let bus = self.bus.clone();
+        Box::pin(async move {
+            // Failed message that is sent again keeps its number.
+            let mut earlier_seq = None;
+            if let Some(client_message_id) = &msg.client_message_id {
+                if client_message_id.len() > MAX_CLIENT_MESSAGE_ID_LEN {
+                    return Err(JRPCError::invalid_params()
+                        .with_data("client_message_id is too long".to_string()));
+                }
+
+                // Id is claimed before the number is taken,
+                // so a retry doesn't take a number of its own.
+                let claim = SentMessage {
+                    message_id: None,
+                    seq: None,
+                    status: DeliveryStatus::Accepted,
+                };
+                let earlier = cache
+                    .claim_sent_message(msg.id, client_message_id, claim, DEDUP_WINDOW)
+                    .await
+                    .map_err(|_| JRPCError::cache_unavailable())?;
+                match earlier {
+                    Some(earlier) if earlier.status == DeliveryStatus::Failed => {
+                        earlier_seq = earlier.seq;
+                    }
+                    Some(earlier) => {
+                        return Ok(SendMessageResult {
+                            recipient: msg.recipient,
+                            message_id: earlier.message_id,
+                            status: earlier.status,
+                            seq: earlier.seq.unwrap_or_default(),
+                        });
+                    }
+                    None => {}
+                }
+            }
+
+            // Number is taken in the order the sender sent the messages,
+            // recipients restore this order however the workers route them.
+            let seq = match earlier_seq {
+                Some(seq) => seq,
+                None => match cache.next_sequence(msg.id, msg.recipient).await {
+                    Ok(seq) => seq,
+                    Err(_) => {
+                        remember_sent(cache.as_ref(), &msg, DeliveryStatus::Failed).await;
+                        return Err(JRPCError::cache_unavailable());
+                    }
+                },
+            };
+            msg.seq = Some(seq);
+
+            // Retry gets the number, the worker changes the status once it routes the message.
+            remember_sent(cache.as_ref(), &msg, DeliveryStatus::Accepted).await;
+            let published = bus
+                .publish(
+                    PUBLISH_SUBJECT,
+                    Bytes::from(serde_json::to_string(&msg).unwrap()),
+                )
+                .await;
+            if published.is_err() {
+                // Retry of the message publishes it again.
+                remember_sent(cache.as_ref(), &msg, DeliveryStatus::Failed).await;
+                return Err(JRPCError::broker_unavailable());
+            }
+
+            Ok(SendMessageResult {
+                recipient: msg.recipient,
+                message_id: None,
+                status: DeliveryStatus::Accepted,
+                seq,
+            })
+        })
+    }
+}
+
+impl Handler<Join> for ChatServer {
+    type Result = ResponseFuture<Result<JoinResult, JRPCError>>;
+
+    fn handle(&mut self, msg: Join, _: &mut Context<Self>) -> Self::Result {
+        let Join { recipient } = msg;
+        let cache = self.cache.clone();
+        Box::pin(async move {
+            match cache.is_user_exist(recipient).await {
+                Ok(true) => Ok(JoinResult {
+                    joined_user: recipient,
+                }),
+                Ok(false) => Err(JRPCError::recipient_not_found()),
+                Err(_) => Err(JRPCError::cache_unavailable()),
+            }
+        })
+    }
+}
+
+impl Handler<MessageStatus> for ChatServer {
     type Result = ();
 
-    fn handle(&mut self, msg: ClientMessage, _: &mut Context<Self>) {
-        let nats_conn_copy = self.nats_conn.clone();
+    fn handle(&mut self, msg: MessageStatus, _: &mut Context<Self>) {
+        self.connection_manager.send(
+            &msg.id,
+            &msg.session_id,
+            JRPCNotification::new(
+                notifications::MESSAGE_STATUS,
+                MessageStatusParams {
+                    request_id: msg.request_id,
+                    message_id: msg.message_id,
+                    recipient: msg.recipient,
+                    status: msg.status,
+                },
+            ),
+        );
+    }
+}
+
+impl Handler<FetchHistory> for ChatServer {
+    type Result = ResponseFuture<Result<HistoryPage, JRPCError>>;
+
+    fn handle(&mut self, msg: FetchHistory, _: &mut Context<Self>) -> Self::Result {
+        let bus = self.bus.clone();
+        Box::pin(async move {
+            let reply = bus
+                .request(
+                    HISTORY_SUBJECT,
+                    Bytes::from(serde_json::to_string(&msg.query).unwrap()),
+                )
+                .await
+                .map_err(|_| JRPCError::history_unavailable())?;
+
+            serde_json::from_slice::<HistoryReply>(&reply)
+                .map_err(|_| JRPCError::history_unavailable())?
+        })
+    }
+}
+
+impl Handler<Receipt> for ChatServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: Receipt, _: &mut Context<Self>) {
+        let bus = self.bus.clone();
         tokio::spawn(async move {
-            nats_conn_copy
+            if bus
                 .publish(
-                    "message.publish",
+                    RECEIPT_SUBJECT,
                     Bytes::from(serde_json::to_string(&msg).unwrap()),
                 )
                 .await
-                .unwrap();
+                .is_err()
+            {
+                println!("Cannot publish receipt");
+            }
         });
     }
 }
 
-impl Handler<Join> for ChatServer {
+impl Handler<MarkRead> for ChatServer {
+    type Result = ResponseFuture<Result<MarkReadResult, JRPCError>>;
+
+    fn handle(&mut self, msg: MarkRead, _: &mut Context<Self>) -> Self::Result {
+        let bus = self.bus.clone();
+        let receipt = Receipt {
+            message_id: msg.message_id,
+            recipient: msg.user_id,
+            status: DeliveryStatus::Read,
+        };
+        Box::pin(async move {
+            bus.publish(
+                RECEIPT_SUBJECT,
+                Bytes::from(serde_json::to_string(&receipt).unwrap()),
+            )
+            .await
+            .map_err(|_| JRPCError::broker_unavailable())?;
+
+            Ok(MarkReadResult {
+                message_id: msg.message_id,
+            })
+        })
+    }
+}
+
+impl Handler<RoutedReceipt> for ChatServer {
     type Result = ();
 
-    fn handle(&mut self, msg: Join, _: &mut Context<Self>) {
-        let Join { id, recipient } = msg;
-        let mut error_join: bool = false;
-        let mut error_message: &str = "";
-        let redis_conn = self.redis_pool.get();
+    fn handle(&mut self, msg: RoutedReceipt, _: &mut Context<Self>) {
+        let notification = JRPCNotification::new(
+            notifications::MESSAGE_STATUS,
+            MessageStatusParams {
+                request_id: None,
+                message_id: Some(msg.message_id),
+                recipient: msg.recipient,
+                status: msg.status,
+            },
+        );
 
-        if let Ok(mut redis_conn) = redis_conn {
-            let is_recipient_exist: Result<bool, redis::RedisError> =
-                redis_conn.exists::<&str, bool>(format!("hcwc.user.{}", recipient).as_str());
+        // Every device of the sender gets the receipt.
+        self.connection_manager.send_all(&msg.sender, &notification);
+    }
+}
 
-            if is_recipient_exist
-                .as_ref()
-                .is_ok_and(|is_exist| is_exist == &false)
-                || is_recipient_exist.is_err()
-            {
-                error_join = true;
-                error_message = "Recipient doesn't exist"
+impl Handler<CreateRoom> for ChatServer {
+    type Result = ResponseFuture<Result<RoomResult, JRPCError>>;
+
+    fn handle(&mut self, msg: CreateRoom, _: &mut Context<Self>) -> Self::Result {
+        let cache = self.cache.clone();
+        Box::pin(async move {
+            let mut invited = msg.members;
+            invited.retain(|user_id| *user_id != msg.user_id);
+            let room_id = cache
+                .create_room(msg.user_id, &invited)
+                .await
+                .map_err(|_| JRPCError::cache_unavailable())?;
+
+            Ok(RoomResult { room_id })
+        })
+    }
+}
+
+impl Handler<InviteRoom> for ChatServer {
+    type Result = ResponseFuture<Result<RoomResult, JRPCError>>;
+
+    fn handle(&mut self, msg: InviteRoom, _: &mut Context<Self>) -> Self::Result {
+        let cache = self.cache.clone();
+        Box::pin(async move {
+            match cache.is_room_member(msg.room_id, msg.user_id).await {
+                Ok(true) => {}
+                Ok(false) => return Err(JRPCError::not_room_member()),
+                Err(_) => return Err(JRPCError::cache_unavailable()),
+            }
+            let is_member = cache
+                .is_room_member(msg.room_id, msg.invited)
+                .await
+                .map_err(|_| JRPCError::cache_unavailable())?;
+            if !is_member {
+                cache
+                    .invite_room_member(msg.room_id, msg.invited)
+                    .await
+                    .map_err(|_| JRPCError::cache_unavailable())?;
             }
-        } else {
-            error_join = true;
-            error_message = "Cannot connect to the redis"
-        }
 
-        if let Some(send_to_user) = self.connection_manager.retrieve_connection(&id) {
-            if error_join {
-                send_to_user.do_send(JRPCResponse::new(
-                    None,
-                    None::<()>,
-                    Some(JoinError {
-                        error_message: error_message,
-                    }),
-                ))
-            } else {
-                send_to_user.do_send(JRPCResponse::new(
-                    None,
-                    Some(JoinResult {
-                        joined_user: recipient,
-                    }),
-                    None::<()>,
-                ))
+            Ok(RoomResult {
+                room_id: msg.room_id,
+            })
+        })
+    }
+}
+
+impl Handler<JoinRoom> for ChatServer {
+    type Result = ResponseFuture<Result<RoomResult, JRPCError>>;
+
+    fn handle(&mut self, msg: JoinRoom, _: &mut Context<Self>) -> Self::Result {
+        let cache = self.cache.clone();
+        Box::pin(async move {
+            let room = RoomResult {
+                room_id: msg.room_id,
+            };
+            match cache.join_room(msg.room_id, msg.user_id).await {
+                Ok(true) => return Ok(room),
+                Ok(false) => {}
+                Err(_) => return Err(JRPCError::cache_unavailable()),
+            }
+
+            // Member joining again is not an error.
+            match cache.is_room_member(msg.room_id, msg.user_id).await {
+                Ok(true) => return Ok(room),
+                Ok(false) => {}
+                Err(_) => return Err(JRPCError::cache_unavailable()),
+            }
+            match cache.room_exists(msg.room_id).await {
+                Ok(true) => Err(JRPCError::not_room_invited()),
+                Ok(false) => Err(JRPCError::room_not_found()),
+                Err(_) => Err(JRPCError::cache_unavailable()),
+            }
+        })
+    }
+}
+
+impl Handler<LeaveRoom> for ChatServer {
+    type Result = ResponseFuture<Result<RoomResult, JRPCError>>;
+
+    fn handle(&mut self, msg: LeaveRoom, _: &mut Context<Self>) -> Self::Result {
+        let cache = self.cache.clone();
+        Box::pin(async move {
+            match cache.remove_room_member(msg.room_id, msg.user_id).await {
+                Ok(true) => Ok(RoomResult {
+                    room_id: msg.room_id,
+                }),
+                Ok(false) => Err(JRPCError::not_room_member()),
+                Err(_) => Err(JRPCError::cache_unavailable()),
+            }
+        })
+    }
+}
+
+impl Handler<RoomMembers> for ChatServer {
+    type Result = ResponseFuture<Result<RoomMembersResult, JRPCError>>;
+
+    fn handle(&mut self, msg: RoomMembers, _: &mut Context<Self>) -> Self::Result {
+        let cache = self.cache.clone();
+        Box::pin(async move {
+            let mut members = cache
+                .room_members(msg.room_id)
+                .await
+                .map_err(|_| JRPCError::cache_unavailable())?;
+            if members.is_empty() {
+                return Err(JRPCError::room_not_found());
             }
+            if !members.contains(&msg.user_id) {
+                return Err(JRPCError::not_room_member());
+            }
+            members.sort_unstable();
+
+            Ok(RoomMembersResult {
+                room_id: msg.room_id,
+                members,
+            })
+        })
+    }
+}
+
+impl Handler<RoomMessage> for ChatServer {
+    type Result = ResponseFuture<Result<SendRoomMessageResult, JRPCError>>;
+
+    fn handle(&mut self, mut msg: RoomMessage, _: &mut Context<Self>) -> Self::Result {
+        msg.server_uuid = self.chat_uuid.clone();
+        let cache = self.cache.clone();
+        let bus = self.bus.clone();
+        Box::pin(async move {
+            let is_member = cache
+                .is_room_member(msg.room_id, msg.id)
+                .await
+                .map_err(|_| JRPCError::cache_unavailable())?;
+            if !is_member {
+                return Err(JRPCError::not_room_member());
+            }
+
+            bus.publish(
+                ROOM_PUBLISH_SUBJECT,
+                Bytes::from(serde_json::to_string(&msg).unwrap()),
+            )
+            .await
+            .map_err(|_| JRPCError::broker_unavailable())?;
+
+            Ok(SendRoomMessageResult {
+                room_id: msg.room_id,
+                status: DeliveryStatus::Accepted,
+            })
+        })
+    }
+}
+
+impl Handler<RoutedRoomMessage> for ChatServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: RoutedRoomMessage, _: &mut Context<Self>) {
+        let RoutedRoomMessage { members, message } = msg;
+        let notification = JRPCNotification::new(
+            notifications::ROOM_MESSAGE,
+            RoomMessageParams {
+                room_id: message.room_id,
+                sender: message.id,
+                message: message.msg,
+                timestamp: message.timestamp,
+            },
+        );
+
+        // Members that left this server in the meantime are skipped,
+        // room messages are not queued.
+        for member in members {
+            self.connection_manager.send_all(&member, &notification);
+        }
+    }
+}
+
+impl Handler<SubscribePresence> for ChatServer {
+    type Result = ResponseFuture<Result<PresenceResult, JRPCError>>;
+
+    fn handle(&mut self, msg: SubscribePresence, _: &mut Context<Self>) -> Self::Result {
+        if let Err(err) = check_presence_users(&msg.user_ids) {
+            return Box::pin(async move { Err(err) });
+        }
+        self.presence_watchers
+            .watch((msg.user_id, msg.session_id), &msg.user_ids);
+
+        let cache = self.cache.clone();
+        Box::pin(async move {
+            let users = read_presence(cache.as_ref(), &msg.user_ids).await?;
+            Ok(PresenceResult { users })
+        })
+    }
+}
+
+impl Handler<UnsubscribePresence> for ChatServer {
+    type Result = Result<UnsubscribePresenceResult, JRPCError>;
+
+    fn handle(&mut self, msg: UnsubscribePresence, _: &mut Context<Self>) -> Self::Result {
+        self.presence_watchers
+            .unwatch((msg.user_id, msg.session_id), &msg.user_ids);
+
+        Ok(UnsubscribePresenceResult {
+            user_ids: msg.user_ids,
+        })
+    }
+}
+
+impl Handler<GetPresence> for ChatServer {
+    type Result = ResponseFuture<Result<PresenceResult, JRPCError>>;
+
+    fn handle(&mut self, msg: GetPresence, _: &mut Context<Self>) -> Self::Result {
+        let cache = self.cache.clone();
+        Box::pin(async move {
+            check_presence_users(&msg.user_ids)?;
+            let users = read_presence(cache.as_ref(), &msg.user_ids).await?;
+            Ok(PresenceResult { users })
+        })
+    }
+}
+
+impl Handler<PresenceChanged> for ChatServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: PresenceChanged, _: &mut Context<Self>) {
+        let notification = JRPCNotification::new(notifications::PRESENCE, &msg.presence);
+
+        for (user_id, session_id) in self.presence_watchers.watchers(&msg.presence.user_id) {
+            self.connection_manager
+                .send(user_id, session_id, notification.clone());
+        }
+    }
+}
+
+impl Handler<Typing> for ChatServer {
+    type Result = ResponseFuture<Result<TypingResult, JRPCError>>;
+
+    fn handle(&mut self, msg: Typing, _: &mut Context<Self>) -> Self::Result {
+        let event = TypingEvent {
+            sender: msg.sender,
+            recipient: msg.recipient,
+            typing: msg.typing,
+        };
+        let bus = self.bus.clone();
+        Box::pin(async move {
+            bus.publish(
+                TYPING_PUBLISH_SUBJECT,
+                Bytes::from(serde_json::to_string(&event).unwrap()),
+            )
+            .await
+            .map_err(|_| JRPCError::broker_unavailable())?;
+
+            Ok(TypingResult {
+                recipient: event.recipient,
+                typing: event.typing,
+            })
+        })
+    }
+}
+
+impl Handler<TypingEvent> for ChatServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: TypingEvent, ctx: &mut Context<Self>) {
+        let key = (msg.sender, msg.recipient);
+        if let Some(handle) = self.typing.remove(&key) {
+            ctx.cancel_future(handle);
+        }
+        self.notify_typing(msg.sender, msg.recipient, msg.typing);
+
+        // Sender that crashed or lost the connection never sends `typing_stop`.
+        if msg.typing {
+            let handle = ctx.run_later(TYPING_TTL, move |act, _| {
+                act.typing.remove(&key);
+                act.notify_typing(key.0, key.1, false);
+            });
+            self.typing.insert(key, handle);
         }
     }
 }
@@ -1,3 +1,5 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::Arc;
 use std::time::Duration;
 
 use actix::clock::Instant;
@@ -6,47 +8,124 @@ use actix::ActorContext;
 use actix::ActorFutureExt;
 use actix::ContextFutureSpawner;
 use actix::Running;
+use actix::SpawnHandle;
 use actix::StreamHandler;
 use actix::WrapFuture;
 use actix::{Actor, Addr};
 use actix::{AsyncContext, Handler};
 use actix_web_actors::ws;
+use futures::future::{self, BoxFuture, FutureExt};
+use serde_json::Value;
 
-use crate::chat_server::ClientMessage;
-use crate::chat_server::Disconnect;
-use crate::chat_server::Join;
+use hcwc_protocol::mq_messages::{DeliveryStatus, Receipt};
+use hcwc_protocol::notifications::{self, ChatMessageParams, JRPCNotification, MessageGapParams};
+use hcwc_protocol::requests::JRPCAuthRequestParams;
+use hcwc_protocol::requests::JRPCRequest;
+use hcwc_protocol::requests::RequestId;
+use hcwc_protocol::requests::ResumeParams;
+use hcwc_protocol::responses::AuthResult;
+use hcwc_protocol::responses::JRPCError;
+use hcwc_protocol::responses::JRPCResponse;
+
+use crate::auth::TokenVerifier;
 use crate::chat_server::{ChatServer, Connect};
-use crate::requests::JRPCJoinRequestParams;
-use crate::requests::JRPCMessageRequestParams;
-use crate::requests::JRPCRequest;
-use crate::responses::JRPCResponse;
+use crate::chat_server::{Disconnect, RefreshPresence};
+use crate::methods::{parse_params, MethodContext, MethodRegistry, AUTH};
+
+/// How long a message waits for the messages the sender sent before it
+const REORDER_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Most messages of one sender waiting for the missing ones
+const MAX_HELD_MESSAGES: usize = 32;
+
+/// Sequence numbers of this many newest messages of one sender are remembered
+/// to recognise redelivered ones, older duplicates are written again.
+const MAX_WRITTEN_MESSAGES: usize = 1024;
+
+/// Senders whose order is remembered, the one that wrote least recently and
+/// has no held messages is forgotten, its next message is taken as it comes.
+const MAX_SENDERS: usize = 256;
+
+/// Heartbeat of the websocket.
+#[derive(Debug, Clone, Copy)]
+pub struct Heartbeat {
+    /// How often heartbeat pings are sent
+    pub interval: Duration,
+    /// How long before lack of client response causes a timeout
+    pub client_timeout: Duration,
+}
 
-/// How often heartbeat pings are sent
-const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
+impl Default for Heartbeat {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(5),
+            client_timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+/// Messages of one sender held until the messages sent before them arrive.
+#[derive(Default)]
+pub struct SenderSequence {
+    /// Last sequence number written to the socket
+    last: Option<u64>,
+    /// Sequence numbers of the newest messages written to the socket
+    written: BTreeSet<u64>,
+    held: BTreeMap<u64, JRPCNotification>,
+    /// Writes the held messages with a gap if the missing ones don't arrive
+    timer: Option<SpawnHandle>,
+    /// When the last message of the sender arrived
+    used: Option<Instant>,
+}
+
+impl SenderSequence {
+    fn is_known(&self, seq: u64) -> bool {
+        self.written.contains(&seq) || self.held.contains_key(&seq)
+    }
 
-/// How long before lack of client response causes a timeout
-const CLIENT_TIMEOUT: Duration = Duration::from_secs(10);
+    fn mark_written(&mut self, seq: u64) {
+        self.written.insert(seq);
+        if self.written.len() > MAX_WRITTEN_MESSAGES {
+            self.written.pop_first();
+        }
+    }
+}
 
 pub struct ChatSession {
+    /// Id of the authenticated user, `None` until the `auth` request succeeds
+    pub user_id: Option<usize>,
+    /// Id of this session among the sessions of the user
     pub session_id: usize,
-    pub name: Option<String>,
     pub addr: Addr<ChatServer>,
+    pub methods: Arc<MethodRegistry>,
+    pub verifier: Arc<TokenVerifier>,
+    /// Dropped session this one resumes
+    pub resume: Option<ResumeParams>,
+    /// Order of the messages from every sender
+    pub sequences: HashMap<usize, SenderSequence>,
+    pub heartbeat: Heartbeat,
     pub hb: Instant,
 }
 
 impl ChatSession {
-    /// helper method that sends ping to client every 5 seconds (HEARTBEAT_INTERVAL).
+    /// helper method that sends ping to client every `heartbeat.interval`.
     ///
     /// also this method checks heartbeats from client
     fn hb(&self, ctx: &mut ws::WebsocketContext<Self>) {
-        ctx.run_interval(HEARTBEAT_INTERVAL, |act, ctx| {
+        ctx.run_interval(self.heartbeat.interval, |act, ctx| {
             // check client heartbeats
-            if Instant::now().duration_since(act.hb) > CLIENT_TIMEOUT {
+            if Instant::now().duration_since(act.hb) > act.heartbeat.client_timeout {
                 // heartbeat timed out
                 println!("Websocket Client heartbeat failed, disconnecting!");
 
                 // notify chat server
-                act.addr.do_send(Disconnect { id: act.session_id });
+                if let Some(user_id) = act.user_id {
+                    act.addr.do_send(Disconnect {
+                        id: user_id,
+                        session_id: act.session_id,
+                        addr: ctx.address().recipient(),
+                    });
+                }
 
                 // stop actor
                 ctx.stop();
@@ -55,29 +134,161 @@ impl ChatSession {
                 return;
             }
 
+            // Client is alive, so is its presence.
+            if let Some(user_id) = act.user_id {
+                act.addr.do_send(RefreshPresence { user_id });
+            }
+
             ctx.ping(b"");
         });
     }
-}
 
-impl Actor for ChatSession {
-    type Context = ws::WebsocketContext<Self>;
+    /// Writes the message to the socket, the sender learns
+    /// that the message reached the recipient.
+    fn write_message(&self, msg: JRPCNotification, ctx: &mut ws::WebsocketContext<Self>) {
+        ctx.text(serde_json::to_string(&msg).unwrap());
 
-    /// Method is called on actor start.
-    /// We register ws session with ChatServer
-    fn started(&mut self, ctx: &mut Self::Context) {
-        // we'll start heartbeat process on session start.
+        let message_id = msg
+            .params
+            .and_then(|params| serde_json::from_value::<ChatMessageParams>(params).ok())
+            .and_then(|params| params.id);
+        if let (Some(message_id), Some(user_id)) = (message_id, self.user_id) {
+            self.addr.do_send(Receipt {
+                message_id,
+                recipient: user_id,
+                status: DeliveryStatus::Delivered,
+            });
+        }
+    }
 
-        // register self in chat server. `AsyncContext::wait` register
-        // future within context, but context waits until this future resolves
-        // before processing any other events.
-        // HttpContext::state() is instance of WsChatSessionState, state is shared
-        // across all routes within application
-        self.hb(ctx);
+    /// Forgets the sender that wrote least recently, senders
+    /// with held messages are kept until they are written.
+    fn evict_sequence(&mut self) {
+        let oldest = self
+            .sequences
+            .iter()
+            .filter(|(_, sequence)| sequence.held.is_empty())
+            .min_by_key(|(_, sequence)| sequence.used)
+            .map(|(sender, _)| *sender);
+        if let Some(sender) = oldest {
+            self.sequences.remove(&sender);
+        }
+    }
+
+    /// Writes messages of the sender in the order they were sent,
+    /// message that came too early waits for the missing ones.
+    fn order_message(
+        &mut self,
+        sender: usize,
+        seq: u64,
+        msg: JRPCNotification,
+        ctx: &mut ws::WebsocketContext<Self>,
+    ) {
+        if !self.sequences.contains_key(&sender) && self.sequences.len() >= MAX_SENDERS {
+            self.evict_sequence();
+        }
+        let sequence = self.sequences.entry(sender).or_default();
+        sequence.used = Some(Instant::now());
+        // Redelivered message, it is already written or waits to be.
+        if sequence.is_known(seq) {
+            return;
+        }
+        match sequence.last {
+            // Late message of a gap that was given up on, or a message sent
+            // before the first one this session got. It was never written,
+            // so it is written out of order rather than lost.
+            Some(last) if seq <= last => {
+                sequence.mark_written(seq);
+                self.write_message(msg, ctx);
+                return;
+            }
+            Some(last) if seq > last + 1 => {
+                sequence.held.insert(seq, msg);
+                if sequence.held.len() > MAX_HELD_MESSAGES {
+                    self.flush_held(sender, ctx);
+                } else if sequence.timer.is_none() {
+                    sequence.timer = Some(ctx.run_later(REORDER_TIMEOUT, move |act, ctx| {
+                        act.flush_held(sender, ctx);
+                    }));
+                }
+                return;
+            }
+            _ => {
+                sequence.last = Some(seq);
+                sequence.mark_written(seq);
+            }
+        }
+        self.write_message(msg, ctx);
+
+        // Messages that waited for this one.
+        let sequence = self.sequences.entry(sender).or_default();
+        let mut ready = vec![];
+        while let Some(entry) = sequence.last.and_then(|last| {
+            sequence
+                .held
+                .first_entry()
+                .filter(|entry| *entry.key() == last + 1)
+        }) {
+            let (seq, msg) = entry.remove_entry();
+            sequence.last = Some(seq);
+            sequence.mark_written(seq);
+            ready.push(msg);
+        }
+        if sequence.held.is_empty() {
+            if let Some(timer) = sequence.timer.take() {
+                ctx.cancel_future(timer);
+            }
+        }
+        for msg in ready {
+            self.write_message(msg, ctx);
+        }
+    }
+
+    /// Writes all held messages of the sender, client is told
+    /// about every gap and can fill it with `fill_gap`.
+    fn flush_held(&mut self, sender: usize, ctx: &mut ws::WebsocketContext<Self>) {
+        let Some(sequence) = self.sequences.get_mut(&sender) else {
+            return;
+        };
+        if let Some(timer) = sequence.timer.take() {
+            ctx.cancel_future(timer);
+        }
+        let held = std::mem::take(&mut sequence.held);
+        let mut last = sequence.last.unwrap_or_default();
+        if let Some(seq) = held.keys().next_back() {
+            sequence.last = Some(*seq);
+        }
+        for seq in held.keys() {
+            sequence.mark_written(*seq);
+        }
+
+        for (seq, msg) in held {
+            if seq > last + 1 {
+                let gap = JRPCNotification::new(
+                    notifications::MESSAGE_GAP,
+                    MessageGapParams {
+                        sender,
+                        from_seq: last + 1,
+                        to_seq: seq - 1,
+                    },
+                );
+                ctx.text(serde_json::to_string(&gap).unwrap());
+            }
+            last = seq;
+            self.write_message(msg, ctx);
+        }
+    }
+
+    /// Registers ws session of the authenticated user in ChatServer.
+    fn connect(&self, user_id: usize, ctx: &mut ws::WebsocketContext<Self>) {
+        // `AsyncContext::wait` register future within context, but context waits
+        // until this future resolves before processing any other events.
         let addr = ctx.address();
         self.addr
             .send(Connect {
+                user_id,
                 addr: addr.recipient(),
+                resume: self.resume.clone(),
             })
             .into_actor(self)
             .then(|res, act, ctx| {
@@ -91,21 +302,212 @@ impl Actor for ChatSession {
             .wait(ctx);
     }
 
-    fn stopping(&mut self, _: &mut Self::Context) -> Running {
+    /// Handles the first frame of the session that was opened without a token,
+    /// only `auth` request is accepted until the user is authenticated.
+    fn authenticate(&mut self, text: &str, ctx: &mut ws::WebsocketContext<Self>) {
+        let response = match self.verify_auth_request(text) {
+            Ok((request_id, user_id, resume)) => {
+                self.user_id = Some(user_id);
+                self.resume = resume;
+                self.connect(user_id, ctx);
+                JRPCResponse::new(request_id, Some(AuthResult { user_id }), None::<()>)
+            }
+            Err(error_response) => error_response,
+        };
+        ctx.text(serde_json::to_string(&response).unwrap());
+    }
+
+    /// Returns id of the request, id of the user the token was issued to
+    /// and the session to resume.
+    fn verify_auth_request(
+        &self,
+        text: &str,
+    ) -> Result<(Option<RequestId>, usize, Option<ResumeParams>), JRPCResponse> {
+        let payload = serde_json::from_str::<Value>(text)
+            .map_err(|_| JRPCResponse::from_error(None, JRPCError::parse_error()))?;
+        let raw_request_id = payload
+            .get("id")
+            .and_then(|id| serde_json::from_value::<RequestId>(id.clone()).ok());
+        let jrpc_request = serde_json::from_value::<JRPCRequest>(payload).map_err(|err| {
+            JRPCResponse::from_error(
+                raw_request_id,
+                JRPCError::invalid_request().with_data(err.to_string()),
+            )
+        })?;
+        let request_id = jrpc_request.id;
+
+        if jrpc_request.method != AUTH {
+            return Err(JRPCResponse::from_error(
+                request_id.clone(),
+                JRPCError::unauthorized().with_data("First request must be auth".to_string()),
+            ));
+        }
+
+        let auth_params = parse_params::<JRPCAuthRequestParams>(jrpc_request.params)
+            .map_err(|error| JRPCResponse::from_error(request_id.clone(), error))?;
+        let user_id = self.verifier.verify(&auth_params.token).map_err(|err| {
+            JRPCResponse::from_error(
+                request_id.clone(),
+                JRPCError::unauthorized().with_data(err.to_string()),
+            )
+        })?;
+
+        Ok((request_id, user_id, auth_params.resume))
+    }
+
+    /// Parses JSON-RPC request or batch of requests from the text frame
+    /// and passes them to the chat server.
+    ///
+    /// Malformed requests never stop the session, client gets JSON-RPC error instead.
+    fn handle_request(&self, text: &str, ctx: &mut ws::WebsocketContext<Self>) {
+        let payload = match serde_json::from_str::<Value>(text) {
+            Ok(payload) => payload,
+            Err(_) => {
+                let response = JRPCResponse::from_error(None, JRPCError::parse_error());
+                ctx.text(serde_json::to_string(&response).unwrap());
+                return;
+            }
+        };
+
+        match payload {
+            // Empty array is a single invalid request, not an empty batch.
+            Value::Array(requests) if !requests.is_empty() => {
+                let calls: Vec<_> = requests
+                    .into_iter()
+                    .map(|request| self.dispatch_request(request))
+                    .collect();
+                future::join_all(calls)
+                    .into_actor(self)
+                    .then(|responses, _, ctx| {
+                        // Notifications are not answered, batch of notifications gets nothing back.
+                        let responses: Vec<JRPCResponse> =
+                            responses.into_iter().flatten().collect();
+                        if !responses.is_empty() {
+                            ctx.text(serde_json::to_string(&responses).unwrap());
+                        }
+                        fut::ready(())
+                    })
+                    .spawn(ctx);
+            }
+            request => {
+                self.dispatch_request(request)
+                    .into_actor(self)
+                    .then(|response, _, ctx| {
+                        if let Some(response) = response {
+                            ctx.text(serde_json::to_string(&response).unwrap());
+                        }
+                        fut::ready(())
+                    })
+                    .spawn(ctx);
+            }
+        }
+    }
+
+    /// Resolves to the response for a single request, or to `None` if
+    /// the request is a notification.
+    fn dispatch_request(&self, request: Value) -> BoxFuture<'static, Option<JRPCResponse>> {
+        let raw_request_id = request
+            .get("id")
+            .and_then(|id| serde_json::from_value::<RequestId>(id.clone()).ok());
+        let jrpc_request = match serde_json::from_value::<JRPCRequest>(request) {
+            Ok(jrpc_request) => jrpc_request,
+            Err(err) => {
+                let error = JRPCError::invalid_request().with_data(err.to_string());
+                return future::ready(Some(JRPCResponse::from_error(raw_request_id, error)))
+                    .boxed();
+            }
+        };
+
+        let is_notification = jrpc_request.id.is_none();
+        let response = match self.route_request(jrpc_request) {
+            Ok(response) => response,
+            Err(error_response) => future::ready(error_response).boxed(),
+        };
+
+        response
+            .map(move |response| (!is_notification).then_some(response))
+            .boxed()
+    }
+
+    fn route_request(
+        &self,
+        jrpc_request: JRPCRequest,
+    ) -> Result<BoxFuture<'static, JRPCResponse>, JRPCResponse> {
+        let request_id = jrpc_request.id;
+
+        if jrpc_request.jsonrpc != "2.0" {
+            return Err(JRPCResponse::from_error(
+                request_id.clone(),
+                JRPCError::invalid_request().with_data("jsonrpc must be \"2.0\"".to_string()),
+            ));
+        }
+
+        let ctx = MethodContext {
+            addr: self.addr.clone(),
+            user_id: self.user_id.unwrap_or_default(),
+            session_id: self.session_id,
+            request_id: request_id.clone(),
+        };
+        let call = self
+            .methods
+            .call(&jrpc_request.method, jrpc_request.params, &ctx)
+            .map_err(|error| JRPCResponse::from_error(request_id.clone(), error))?;
+
+        Ok(call
+            .map(move |res| match res {
+                Ok(result) => JRPCResponse::new(request_id, Some(result), None::<()>),
+                Err(error) => JRPCResponse::from_error(request_id, error),
+            })
+            .boxed())
+    }
+}
+
+impl Actor for ChatSession {
+    type Context = ws::WebsocketContext<Self>;
+
+    /// Method is called on actor start.
+    /// We register ws session with ChatServer if it was authenticated
+    /// during the upgrade, otherwise we wait for the `auth` request.
+    fn started(&mut self, ctx: &mut Self::Context) {
+        // we'll start heartbeat process on session start.
+        self.hb(ctx);
+        if let Some(user_id) = self.user_id {
+            self.connect(user_id, ctx);
+        }
+    }
+
+    fn stopping(&mut self, ctx: &mut Self::Context) -> Running {
         // notify chat server
-        self.addr.do_send(Disconnect {
-            id: self.session_id,
-        });
+        if let Some(user_id) = self.user_id {
+            self.addr.do_send(Disconnect {
+                id: user_id,
+                session_id: self.session_id,
+                addr: ctx.address().recipient(),
+            });
+        }
         Running::Stop
     }
 }
 
 /// Handle messages from chat server, we simply send it to peer websocket
-impl Handler<JRPCResponse> for ChatSession {
+impl Handler<JRPCNotification> for ChatSession {
     type Result = ();
 
-    fn handle(&mut self, msg: JRPCResponse, ctx: &mut Self::Context) {
-        ctx.text(serde_json::to_value(msg).unwrap().to_string());
+    fn handle(&mut self, msg: JRPCNotification, ctx: &mut Self::Context) {
+        if msg.method != notifications::MESSAGE {
+            ctx.text(serde_json::to_string(&msg).unwrap());
+            return;
+        }
+
+        let sequence = msg
+            .params
+            .clone()
+            .and_then(|params| serde_json::from_value::<ChatMessageParams>(params).ok())
+            .and_then(|params| Some((params.sender, params.seq?)));
+        match sequence {
+            Some((sender, seq)) => self.order_message(sender, seq, msg, ctx),
+            None => self.write_message(msg, ctx),
+        }
     }
 }
 
@@ -128,33 +530,10 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for ChatSession {
             ws::Message::Pong(_) => {
                 self.hb = Instant::now();
             }
-            ws::Message::Text(text) => {
-                let jrpc_request: JRPCRequest = serde_json::from_str(&text).unwrap();
-                match jrpc_request.method {
-                    "join" => {
-                        let join_params = serde_json::from_value::<JRPCJoinRequestParams>(
-                            jrpc_request.params.unwrap(),
-                        )
-                        .unwrap();
-                        self.addr.do_send(Join {
-                            id: self.session_id,
-                            recipient: join_params.recipient,
-                        });
-                    }
-                    "send_message" => {
-                        let message_params = serde_json::from_value::<JRPCMessageRequestParams>(
-                            jrpc_request.params.unwrap(),
-                        )
-                        .unwrap();
-                        self.addr.do_send(ClientMessage {
-                            id: self.session_id,
-                            msg: message_params.message,
-                            recipient: message_params.recipient,
-                        })
-                    }
-                    _ => {}
-                }
-            }
+            ws::Message::Text(text) => match self.user_id {
+                Some(_) => self.handle_request(&text, ctx),
+                None => self.authenticate(&text, ctx),
+            },
             ws::Message::Binary(_) => println!("Unexpected binary"),
             ws::Message::Close(reason) => {
                 ctx.close(reason);
@@ -0,0 +1,120 @@
+//! Settings of the server, see `hcwc_config` for where they come from.
+
+use std::time::Duration;
+
+use clap::Parser;
+use hcwc_config::{
+    pick, require_non_empty, require_positive, CommonArgs, CommonConfig, ConfigError, ConfigFile,
+    ConfigResult,
+};
+
+use crate::{chat_server::RESUME_GRACE, chat_session::Heartbeat};
+
+/// Environment variable with the secret tokens are signed with,
+/// it has no flag to keep the secret out of the process list.
+pub const AUTH_SECRET_ENV: &str = "HCWC_AUTH_SECRET";
+
+const DEFAULT_HOST: &str = "127.0.0.1";
+const DEFAULT_PORT: u16 = 8080;
+const DEFAULT_WORKERS: usize = 6;
+
+/// Chat server serving the websocket sessions of the clients.
+#[derive(Parser, Debug)]
+#[command(name = "server")]
+pub struct Args {
+    #[command(flatten)]
+    pub common: CommonArgs,
+    /// Address the server listens on
+    #[arg(long, env = "HCWC_SERVER_HOST")]
+    pub host: Option<String>,
+    /// Port the server listens on
+    #[arg(long, env = "HCWC_SERVER_PORT")]
+    pub port: Option<u16>,
+    /// Number of HTTP workers
+    #[arg(long, env = "HCWC_SERVER_WORKERS")]
+    pub workers: Option<usize>,
+    /// Seconds between websocket pings
+    #[arg(long, env = "HCWC_HEARTBEAT_INTERVAL")]
+    pub heartbeat_interval: Option<u64>,
+    /// Seconds without an answer from the client before it is disconnected
+    #[arg(long, env = "HCWC_CLIENT_TIMEOUT")]
+    pub client_timeout: Option<u64>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    pub common: CommonConfig,
+    pub host: String,
+    pub port: u16,
+    pub workers: usize,
+    pub heartbeat: Heartbeat,
+    pub auth_secret: String,
+}
+
+impl ServerConfig {
+    /// Settings from the command line, the environment and the config file.
+    pub fn load() -> ConfigResult<Self> {
+        let args = Args::parse();
+        let file = args.common.read_file()?;
+        Self::resolve(&args, &file)
+    }
+
+    pub fn resolve(args: &Args, file: &ConfigFile) -> ConfigResult<Self> {
+        let common = CommonConfig::resolve(&args.common, file)?;
+        let server = &file.server;
+        let heartbeat_defaults = Heartbeat::default();
+
+        let host = pick(&args.host, &server.host).unwrap_or_else(|| DEFAULT_HOST.to_string());
+        let port = pick(&args.port, &server.port).unwrap_or(DEFAULT_PORT);
+        let workers = pick(&args.workers, &server.workers).unwrap_or(DEFAULT_WORKERS);
+        let heartbeat = Heartbeat {
+            interval: pick(&args.heartbeat_interval, &server.heartbeat_interval)
+                .map(Duration::from_secs)
+                .unwrap_or(heartbeat_defaults.interval),
+            client_timeout: pick(&args.client_timeout, &server.client_timeout)
+                .map(Duration::from_secs)
+                .unwrap_or(heartbeat_defaults.client_timeout),
+        };
+        let auth_secret = std::env::var(AUTH_SECRET_ENV)
+            .ok()
+            .or_else(|| server.auth_secret.clone())
+            .ok_or_else(|| {
+                ConfigError::invalid(
+                    "server.auth_secret",
+                    format!("must be set in the config file or with {}", AUTH_SECRET_ENV),
+                )
+            })?;
+
+        require_non_empty("server.host", &host)?;
+        require_positive("server.port", port as u64)?;
+        require_positive("server.workers", workers as u64)?;
+        require_positive("server.heartbeat_interval", heartbeat.interval.as_secs())?;
+        if heartbeat.client_timeout <= heartbeat.interval {
+            return Err(ConfigError::invalid(
+                "server.client_timeout",
+                "must be greater than server.heartbeat_interval",
+            ));
+        }
+        // Client notices the dropped socket within the timeout and resumes the session
+        // before it is gone. Presence outlives the grace, so it outlives the timeout too.
+        if heartbeat.client_timeout > RESUME_GRACE {
+            return Err(ConfigError::invalid(
+                "server.client_timeout",
+                format!(
+                    "must not be greater than the resume grace of {}s",
+                    RESUME_GRACE.as_secs()
+                ),
+            ));
+        }
+        require_non_empty("server.auth_secret", &auth_secret)?;
+
+        Ok(Self {
+            common,
+            host,
+            port,
+            workers,
+            heartbeat,
+            auth_secret,
+        })
+    }
+}
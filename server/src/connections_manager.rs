@@ -1,30 +1,203 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 
 use actix::Recipient;
 
-use crate::responses::JRPCResponse;
+use hcwc_protocol::notifications::JRPCNotification;
 
+/// Events kept for the session until the client acknowledges them,
+/// the oldest are dropped first.
+const MAX_UNACKED_EVENTS: usize = 1000;
+
+/// Session of the user and the events it hasn't acknowledged yet.
 #[derive(Debug)]
+struct Connection {
+    /// `None` while the session is dropped and waits for resume
+    addr: Option<Recipient<JRPCNotification>>,
+    resume_token: String,
+    next_seq: u64,
+    unacked: VecDeque<JRPCNotification>,
+}
+
+impl Connection {
+    fn send(&mut self, notification: JRPCNotification) {
+        self.next_seq += 1;
+        let notification = notification.with_event_seq(self.next_seq);
+        if let Some(addr) = &self.addr {
+            addr.do_send(notification.clone());
+        }
+
+        self.unacked.push_back(notification);
+        if self.unacked.len() > MAX_UNACKED_EVENTS {
+            self.unacked.pop_front();
+        }
+    }
+
+    fn ack(&mut self, seq: u64) {
+        while self
+            .unacked
+            .front()
+            .is_some_and(|event| event.event_seq().unwrap_or_default() <= seq)
+        {
+            self.unacked.pop_front();
+        }
+    }
+}
+
+/// Sessions connected to this server, user may have several of them,
+/// one for every device.
+///
+/// Dropped session stays here detached until it is resumed or removed,
+/// events sent to it are kept for the replay.
+#[derive(Debug, Default)]
 pub struct ConnectionManager {
-    connections: HashMap<usize, Recipient<JRPCResponse>>,
+    connections: HashMap<usize, HashMap<usize, Connection>>,
+    /// User id and session id of every resume token
+    resume_tokens: HashMap<String, (usize, usize)>,
 }
 
 impl ConnectionManager {
     pub fn new() -> Self {
-        Self {
-            connections: HashMap::new(),
+        Self::default()
+    }
+
+    pub fn add_connection(
+        &mut self,
+        user_id: usize,
+        session_id: usize,
+        resume_token: String,
+        connection: Recipient<JRPCNotification>,
+    ) {
+        self.resume_tokens
+            .insert(resume_token.clone(), (user_id, session_id));
+        self.connections.entry(user_id).or_default().insert(
+            session_id,
+            Connection {
+                addr: Some(connection),
+                resume_token,
+                next_seq: 0,
+                unacked: VecDeque::new(),
+            },
+        );
+    }
+
+    /// Removes the session, returns `true` if it was the last session of the user.
+    pub fn remove_connection(&mut self, user_id: &usize, session_id: &usize) -> bool {
+        let Some(sessions) = self.connections.get_mut(user_id) else {
+            return true;
+        };
+
+        if let Some(connection) = sessions.remove(session_id) {
+            self.resume_tokens.remove(&connection.resume_token);
+        }
+        if sessions.is_empty() {
+            self.connections.remove(user_id);
+            return true;
         }
+        false
     }
 
-    pub fn add_connection(&mut self, connection_id: usize, connection: Recipient<JRPCResponse>) {
-        self.connections.insert(connection_id, connection);
+    /// Detaches the socket from the session, returns `false` if the session
+    /// is already detached or resumed by another socket.
+    pub fn detach(
+        &mut self,
+        user_id: &usize,
+        session_id: &usize,
+        connection: &Recipient<JRPCNotification>,
+    ) -> bool {
+        let Some(session) = self
+            .connections
+            .get_mut(user_id)
+            .and_then(|sessions| sessions.get_mut(session_id))
+        else {
+            return false;
+        };
+
+        if session.addr.as_ref() != Some(connection) {
+            return false;
+        }
+        session.addr = None;
+        true
+    }
+
+    /// User id and session id of the session with the token, attached or not.
+    pub fn find_session(&self, resume_token: &str) -> Option<(usize, usize)> {
+        self.resume_tokens.get(resume_token).copied()
+    }
+
+    /// Attaches the socket to the session, returns the events after `last_seq`
+    /// the socket has to get. Returns `None` if there is no such session
+    /// or the client claims events the session has never sent.
+    ///
+    /// Socket that still holds the session is detached, its `Disconnect`
+    /// may come later than the new socket of the same client.
+    pub fn resume(
+        &mut self,
+        user_id: &usize,
+        session_id: &usize,
+        last_seq: u64,
+        connection: Recipient<JRPCNotification>,
+    ) -> Option<Vec<JRPCNotification>> {
+        let session = self
+            .connections
+            .get_mut(user_id)
+            .and_then(|sessions| sessions.get_mut(session_id))?;
+
+        if last_seq > session.next_seq {
+            return None;
+        }
+        session.ack(last_seq);
+        session.addr = Some(connection);
+        Some(session.unacked.iter().cloned().collect())
+    }
+
+    /// Client received the events of the session up to `seq`.
+    pub fn ack(&mut self, user_id: &usize, session_id: &usize, seq: u64) {
+        if let Some(session) = self
+            .connections
+            .get_mut(user_id)
+            .and_then(|sessions| sessions.get_mut(session_id))
+        {
+            session.ack(seq);
+        }
+    }
+
+    /// Sends the event to the session, detached session gets it on resume.
+    pub fn send(&mut self, user_id: &usize, session_id: &usize, notification: JRPCNotification) {
+        if let Some(session) = self
+            .connections
+            .get_mut(user_id)
+            .and_then(|sessions| sessions.get_mut(session_id))
+        {
+            session.send(notification);
+        }
+    }
+
+    /// Sends the event to every session of the user.
+    pub fn send_all(&mut self, user_id: &usize, notification: &JRPCNotification) {
+        for session in self
+            .connections
+            .get_mut(user_id)
+            .into_iter()
+            .flat_map(|sessions| sessions.values_mut())
+        {
+            session.send(notification.clone());
+        }
     }
 
-    pub fn remove_connection(&mut self, connection_id: &usize) {
-        self.connections.remove(&connection_id);
+    /// User has a session here, attached or waiting for resume.
+    pub fn is_connected(&self, user_id: &usize) -> bool {
+        self.connections.contains_key(user_id)
     }
 
-    pub fn retrieve_connection(&self, connection_id: &usize) -> Option<&Recipient<JRPCResponse>> {
-        self.connections.get(&connection_id)
+    /// Attached sessions of the user, events sent to them directly are not replayed.
+    pub fn retrieve_connections(
+        &self,
+        user_id: &usize,
+    ) -> impl Iterator<Item = &Recipient<JRPCNotification>> {
+        self.connections
+            .get(user_id)
+            .into_iter()
+            .flat_map(|sessions| sessions.values())
+            .filter_map(|session| session.addr.as_ref())
     }
 }
@@ -0,0 +1,10 @@
+//! Chat server, its actors are public to run it next to the worker in a single process.
+
+pub mod auth;
+pub mod chat_server;
+pub mod chat_session;
+pub mod config;
+pub mod connections_manager;
+pub mod methods;
+pub mod presence_watchers;
+pub mod subscriber;
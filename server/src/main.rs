@@ -1,97 +1,221 @@
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
 use actix::{clock::Instant, Actor, Addr};
-use actix_web::{web, App, Error, HttpRequest, HttpResponse, HttpServer};
+use actix_web::{error, http::header, web, App, Error, HttpRequest, HttpResponse, HttpServer};
 use actix_web_actors::ws;
-use redis::Commands;
+use hcwc_bus::MessageBus;
+use hcwc_cache::CacheDB;
+use hcwc_protocol::{mq_messages, requests::ResumeParams};
+use serde::Deserialize;
+
+use server::{auth, chat_server, chat_session, config::ServerConfig, methods, subscriber};
+
+#[derive(Deserialize)]
+struct TokenQuery {
+    token: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ResumeQuery {
+    resume_token: String,
+    #[serde(default)]
+    last_seq: u64,
+}
+
+/// Token from `Authorization: Bearer` header or `token` query parameter.
+fn request_token(req: &HttpRequest) -> Option<String> {
+    let header_token = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(str::to_string);
 
-mod cache;
-mod chat_server;
-mod chat_session;
-mod connections_manager;
-mod mq_messages;
-mod requests;
-mod responses;
-mod subscriber;
+    header_token.or_else(|| {
+        web::Query::<TokenQuery>::from_query(req.query_string())
+            .ok()
+            .and_then(|query| query.into_inner().token)
+    })
+}
+
+/// Session to resume from `resume_token` and `last_seq` query parameters.
+fn request_resume(req: &HttpRequest) -> Option<ResumeParams> {
+    web::Query::<ResumeQuery>::from_query(req.query_string())
+        .ok()
+        .map(|query| {
+            let query = query.into_inner();
+            ResumeParams {
+                token: query.resume_token,
+                last_seq: query.last_seq,
+            }
+        })
+}
 
 /// Entry point for our websocket route
+///
+/// Connection with a token is authenticated before the upgrade,
+/// without a token the first frame must be the `auth` request.
 async fn chat_route(
     req: HttpRequest,
     stream: web::Payload,
     srv: web::Data<Addr<chat_server::ChatServer>>,
+    methods: web::Data<methods::MethodRegistry>,
+    verifier: web::Data<auth::TokenVerifier>,
+    heartbeat: web::Data<chat_session::Heartbeat>,
 ) -> Result<HttpResponse, Error> {
+    let user_id = match request_token(&req) {
+        Some(token) => Some(
+            verifier
+                .verify(&token)
+                .map_err(|err| error::ErrorUnauthorized(err.to_string()))?,
+        ),
+        None => None,
+    };
+
     ws::start(
         chat_session::ChatSession {
+            user_id,
             session_id: 0,
-            name: None,
             hb: Instant::now(),
             addr: srv.get_ref().clone(),
+            methods: methods.into_inner(),
+            verifier: verifier.into_inner(),
+            resume: request_resume(&req),
+            sequences: HashMap::new(),
+            heartbeat: **heartbeat,
         },
         &req,
         stream,
     )
 }
 
-fn startup_redis(server_uuid: &str) -> std::io::Result<r2d2::Pool<redis::Client>> {
-    let redis_client = redis::Client::open("redis://127.0.0.1/").unwrap();
-    let redis_pool = r2d2::Pool::new(redis_client).unwrap();
+/// How often the server refreshes its liveness key.
+const SERVER_HEARTBEAT_INTERVAL: Duration = Duration::from_secs(10);
+/// Server that didn't refresh itself for this long is reaped by the workers.
+const SERVER_TTL: Duration = Duration::from_secs(30);
 
-    redis_pool
-        .get()
-        .map_err(|_| {
-            std::io::Error::new(
-                std::io::ErrorKind::ConnectionAborted,
-                "Cannot get redis connection",
-            )
-        })?
-        .sadd::<&str, &str, usize>("hcwc_servers", server_uuid)
-        .map_err(|_| {
+async fn startup_cache(
+    config: &hcwc_cache::CacheConfig,
+    server_uuid: &str,
+) -> std::io::Result<Arc<dyn CacheDB>> {
+    let cache = hcwc_cache::connect(config).await.map_err(|err| {
+        std::io::Error::new(
+            std::io::ErrorKind::ConnectionAborted,
+            format!("Cannot connect to the cache: {}", err),
+        )
+    })?;
+
+    cache.add_server(server_uuid).await.map_err(|err| {
+        std::io::Error::new(
+            std::io::ErrorKind::ConnectionAborted,
+            format!("Cannot add server uuid to available servers: {}", err),
+        )
+    })?;
+
+    Ok(cache)
+}
+
+async fn shutdown_cache(cache: Arc<dyn CacheDB>, server_uuid: &str) -> std::io::Result<()> {
+    cache.remove_server(server_uuid).await.map_err(|err| {
+        std::io::Error::new(
+            std::io::ErrorKind::ConnectionAborted,
+            format!("Cannot remove server from the cache: {}", err),
+        )
+    })
+}
+
+/// Drops own subjects of the server, workers don't route to it anymore.
+async fn shutdown_bus(bus: Arc<dyn MessageBus>, server_uuid: &str) -> std::io::Result<()> {
+    bus.remove_subjects(&mq_messages::server_subjects(server_uuid))
+        .await
+        .map_err(|err| {
             std::io::Error::new(
                 std::io::ErrorKind::ConnectionAborted,
-                "Cannot add server uuid to available servers in redis",
+                format!(
+                    "Cannot remove server subjects from the message bus: {}",
+                    err
+                ),
             )
-        })?;
-
-    Ok(redis_pool)
+        })
 }
 
-fn shutdown_redis(redis_pool: r2d2::Pool<redis::Client>, server_uuid: &str) -> std::io::Result<()> {
-    redis_pool
-        .get()
-        .map_err(|_| std::io::Error::new(std::io::ErrorKind::ConnectionAborted, "123123123"))?
-        .srem::<&str, &str, usize>("hcwc_servers", server_uuid)
-        .map_err(|_| std::io::Error::new(std::io::ErrorKind::ConnectionAborted, "123123123"))?;
-    redis_pool
-        .get()
-        .map_err(|_| std::io::Error::new(std::io::ErrorKind::ConnectionAborted, "123123123"))?
-        .del::<&str, usize>(&format!("hcwc.server.{}", server_uuid))
-        .map_err(|_| std::io::Error::new(std::io::ErrorKind::ConnectionAborted, "123123123"))?;
-    Ok(())
+/// Keeps the server registered, if the server crashes its
+/// liveness key expires and the workers remove it.
+async fn server_heartbeat(cache: Arc<dyn CacheDB>, server_uuid: String) {
+    let mut interval = tokio::time::interval(SERVER_HEARTBEAT_INTERVAL);
+    loop {
+        interval.tick().await;
+        if let Err(err) = cache.refresh_server(&server_uuid, SERVER_TTL).await {
+            println!("Cannot refresh server in the cache: {}", err);
+        }
+    }
 }
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
+    let config = match ServerConfig::load() {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+    };
+    let verifier = web::Data::new(auth::TokenVerifier::new(config.auth_secret.as_bytes()));
+    let heartbeat = web::Data::new(config.heartbeat);
     let instance_uuid = uuid::Uuid::new_v4().to_string();
-    let redis_pool = startup_redis(&instance_uuid)?;
-    let nats_client = async_nats::connect("localhost").await.unwrap();
-    let server =
-        chat_server::ChatServer::new(redis_pool.clone(), nats_client, instance_uuid.clone())
-            .start();
-    let server_clone = server.clone();
-    let uuid_clone = instance_uuid.clone();
+    let cache = startup_cache(&config.common.cache, &instance_uuid).await?;
+    let bus = hcwc_bus::connect(&config.common.bus).await.map_err(|err| {
+        std::io::Error::new(
+            std::io::ErrorKind::ConnectionAborted,
+            format!("Cannot connect to the message bus: {}", err),
+        )
+    })?;
+    let server = chat_server::ChatServer::new(
+        cache.clone(),
+        bus.clone(),
+        instance_uuid.clone(),
+        config.common.pending,
+    )
+    .start();
+    tokio::spawn(server_heartbeat(cache.clone(), instance_uuid.clone()));
+
+    let receiver = match subscriber::subscriber(
+        server.clone(),
+        bus.clone(),
+        instance_uuid.clone(),
+        config.common.queue_group.clone(),
+    )
+    .await
+    {
+        Ok(receiver) => receiver,
+        Err(err) => {
+            shutdown_cache(cache, &instance_uuid).await?;
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::ConnectionAborted,
+                format!("Cannot subscribe to the message bus: {}", err),
+            ));
+        }
+    };
+    tokio::spawn(receiver);
 
-    tokio::spawn(async move { subscriber::subscriber(server_clone, uuid_clone).await });
+    let methods = web::Data::new(methods::registry());
 
     let res = HttpServer::new(move || {
         App::new()
             .app_data(web::Data::new(server.clone()))
+            .app_data(methods.clone())
+            .app_data(verifier.clone())
+            .app_data(heartbeat.clone())
             .route("/ws/", web::get().to(chat_route))
             .route("/", web::get().to(HttpResponse::Ok))
     })
-    .workers(6)
-    .bind(("127.0.0.1", 8080))?
+    .workers(config.workers)
+    .bind((config.host.as_str(), config.port))?
     .run()
     .await;
 
-    shutdown_redis(redis_pool, &instance_uuid)?;
+    shutdown_cache(cache, &instance_uuid).await?;
+    shutdown_bus(bus, &instance_uuid).await?;
 
     res
 }
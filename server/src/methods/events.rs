@@ -0,0 +1,25 @@
+use hcwc_protocol::{requests::JRPCAckEventsRequestParams, responses::AckEventsResult};
+
+use crate::chat_server::AckEvents;
+
+use super::{MethodContext, RpcMethod};
+
+/// Confirms the events of the session up to `seq`,
+/// they are not replayed after resume.
+pub struct AckEventsMethod;
+
+impl RpcMethod for AckEventsMethod {
+    const NAME: &'static str = "ack_events";
+
+    type Params = JRPCAckEventsRequestParams;
+    type Result = AckEventsResult;
+    type Message = AckEvents;
+
+    fn message(params: Self::Params, ctx: &MethodContext) -> Self::Message {
+        AckEvents {
+            user_id: ctx.user_id,
+            session_id: ctx.session_id,
+            seq: params.seq,
+        }
+    }
+}
@@ -0,0 +1,68 @@
+use hcwc_protocol::{
+    mq_messages::{HistoryQuery, SequenceRange},
+    requests::{JRPCFillGapRequestParams, JRPCHistoryRequestParams},
+    responses::HistoryPage,
+};
+
+use crate::chat_server::FetchHistory;
+
+use super::{MethodContext, RpcMethod};
+
+/// Page size if the client didn't ask for a specific one.
+const DEFAULT_PAGE_SIZE: usize = 50;
+/// Biggest page a client can ask for.
+const MAX_PAGE_SIZE: usize = 200;
+
+/// Page of the conversation with another user.
+pub struct HistoryMethod;
+
+impl RpcMethod for HistoryMethod {
+    const NAME: &'static str = "history";
+
+    type Params = JRPCHistoryRequestParams;
+    type Result = HistoryPage;
+    type Message = FetchHistory;
+
+    fn message(params: Self::Params, ctx: &MethodContext) -> Self::Message {
+        FetchHistory {
+            query: HistoryQuery {
+                user_id: ctx.user_id,
+                peer: params.peer,
+                before: params.before,
+                after: params.after,
+                limit: params
+                    .limit
+                    .unwrap_or(DEFAULT_PAGE_SIZE)
+                    .clamp(1, MAX_PAGE_SIZE),
+                sequence: None,
+            },
+        }
+    }
+}
+
+/// Messages from the peer that the client missed, up to `MAX_PAGE_SIZE` of them.
+pub struct FillGapMethod;
+
+impl RpcMethod for FillGapMethod {
+    const NAME: &'static str = "fill_gap";
+
+    type Params = JRPCFillGapRequestParams;
+    type Result = HistoryPage;
+    type Message = FetchHistory;
+
+    fn message(params: Self::Params, ctx: &MethodContext) -> Self::Message {
+        FetchHistory {
+            query: HistoryQuery {
+                user_id: ctx.user_id,
+                peer: params.peer,
+                before: None,
+                after: None,
+                limit: MAX_PAGE_SIZE,
+                sequence: Some(SequenceRange {
+                    from: params.from_seq,
+                    to: params.to_seq,
+                }),
+            },
+        }
+    }
+}
@@ -0,0 +1,70 @@
+use hcwc_protocol::{
+    mq_messages::ClientMessage,
+    requests::{JRPCJoinRequestParams, JRPCMarkReadRequestParams, JRPCMessageRequestParams},
+    responses::{JoinResult, MarkReadResult, SendMessageResult},
+};
+
+use crate::chat_server::{Join, MarkRead};
+
+use super::{MethodContext, RpcMethod};
+
+/// Checks that the recipient is online.
+pub struct JoinMethod;
+
+impl RpcMethod for JoinMethod {
+    const NAME: &'static str = "join";
+
+    type Params = JRPCJoinRequestParams;
+    type Result = JoinResult;
+    type Message = Join;
+
+    fn message(params: Self::Params, _: &MethodContext) -> Self::Message {
+        Join {
+            recipient: params.recipient,
+        }
+    }
+}
+
+/// Sends message to the recipient.
+pub struct SendMessageMethod;
+
+impl RpcMethod for SendMessageMethod {
+    const NAME: &'static str = "send_message";
+
+    type Params = JRPCMessageRequestParams;
+    type Result = SendMessageResult;
+    type Message = ClientMessage;
+
+    fn message(params: Self::Params, ctx: &MethodContext) -> Self::Message {
+        ClientMessage {
+            id: ctx.user_id,
+            session_id: ctx.session_id,
+            server_uuid: String::new(),
+            request_id: ctx.request_id.clone(),
+            msg: params.message,
+            recipient: params.recipient,
+            message_id: None,
+            timestamp: None,
+            seq: None,
+            client_message_id: params.client_message_id,
+        }
+    }
+}
+
+/// Sends read receipt to the sender of the message.
+pub struct MarkReadMethod;
+
+impl RpcMethod for MarkReadMethod {
+    const NAME: &'static str = "mark_read";
+
+    type Params = JRPCMarkReadRequestParams;
+    type Result = MarkReadResult;
+    type Message = MarkRead;
+
+    fn message(params: Self::Params, ctx: &MethodContext) -> Self::Message {
+        MarkRead {
+            user_id: ctx.user_id,
+            message_id: params.message_id,
+        }
+    }
+}
@@ -0,0 +1,168 @@
+use std::collections::BTreeMap;
+
+use actix::{dev::ToEnvelope, Actor, Addr, Handler, Message};
+use futures::future::{self, BoxFuture, FutureExt};
+use schemars::{schema_for, JsonSchema};
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+
+use hcwc_protocol::requests::RequestId;
+use hcwc_protocol::responses::{JRPCError, ResponseResult};
+
+use crate::chat_server::ChatServer;
+
+mod events;
+mod history;
+mod messages;
+mod presence;
+mod rooms;
+mod typing;
+
+/// Name of the introspection method, it is always available.
+pub const DISCOVER: &str = "rpc.discover";
+
+/// Name of the method that authenticates the session opened without a token,
+/// it is handled by the session itself.
+pub const AUTH: &str = "auth";
+
+/// Everything method needs to know about the session that called it.
+pub struct MethodContext {
+    pub addr: Addr<ChatServer>,
+    pub user_id: usize,
+    pub session_id: usize,
+    pub request_id: Option<RequestId>,
+}
+
+/// JSON-RPC method available to the clients.
+///
+/// Method turns its params into the chat server message,
+/// result of the message handler is the result of the method.
+pub trait RpcMethod: 'static {
+    const NAME: &'static str;
+
+    type Params: DeserializeOwned + JsonSchema;
+    type Result: ResponseResult + JsonSchema + Send + 'static;
+    type Message: Message<Result = Result<Self::Result, JRPCError>> + Send + 'static;
+
+    fn message(params: Self::Params, ctx: &MethodContext) -> Self::Message;
+}
+
+type MethodCall = BoxFuture<'static, Result<Value, JRPCError>>;
+type MethodHandler =
+    Box<dyn Fn(Option<Value>, &MethodContext) -> Result<MethodCall, JRPCError> + Send + Sync>;
+
+struct RegisteredMethod {
+    handler: MethodHandler,
+    params: Value,
+    result: Value,
+}
+
+/// Methods available to the clients, keyed by method name.
+#[derive(Default)]
+pub struct MethodRegistry {
+    methods: BTreeMap<&'static str, RegisteredMethod>,
+}
+
+impl MethodRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register<M>(&mut self) -> &mut Self
+    where
+        M: RpcMethod,
+        ChatServer: Handler<M::Message>,
+        <ChatServer as Actor>::Context: ToEnvelope<ChatServer, M::Message>,
+    {
+        let handler: MethodHandler = Box::new(|params, ctx| {
+            let message = M::message(parse_params::<M::Params>(params)?, ctx);
+            Ok(ctx
+                .addr
+                .send(message)
+                .map(|res| match res {
+                    Ok(Ok(result)) => Ok(result.result().unwrap_or(Value::Null)),
+                    Ok(Err(error)) => Err(error),
+                    Err(_) => Err(JRPCError::internal_error()),
+                })
+                .boxed())
+        });
+
+        self.methods.insert(
+            M::NAME,
+            RegisteredMethod {
+                handler,
+                params: serde_json::to_value(schema_for!(M::Params)).unwrap(),
+                result: serde_json::to_value(schema_for!(M::Result)).unwrap(),
+            },
+        );
+        self
+    }
+
+    /// Starts the call of the method, unknown method is method not found error.
+    pub fn call(
+        &self,
+        method: &str,
+        params: Option<Value>,
+        ctx: &MethodContext,
+    ) -> Result<MethodCall, JRPCError> {
+        if method == DISCOVER {
+            return Ok(future::ready(Ok(self.discover())).boxed());
+        }
+
+        let registered = self
+            .methods
+            .get(method)
+            .ok_or_else(|| JRPCError::method_not_found().with_data(method.to_string()))?;
+        (registered.handler)(params, ctx)
+    }
+
+    /// Lists available methods with schemas of their params and results.
+    pub fn discover(&self) -> Value {
+        let mut methods: Vec<Value> = self
+            .methods
+            .iter()
+            .map(|(name, method)| {
+                json!({
+                    "name": name,
+                    "params": method.params,
+                    "result": method.result,
+                })
+            })
+            .collect();
+        methods.push(json!({ "name": DISCOVER, "params": null, "result": null }));
+
+        json!({ "methods": methods })
+    }
+}
+
+/// Registry with all methods of the chat.
+pub fn registry() -> MethodRegistry {
+    let mut registry = MethodRegistry::new();
+    registry
+        .register::<messages::JoinMethod>()
+        .register::<messages::SendMessageMethod>()
+        .register::<messages::MarkReadMethod>()
+        .register::<rooms::CreateRoomMethod>()
+        .register::<rooms::InviteRoomMethod>()
+        .register::<rooms::JoinRoomMethod>()
+        .register::<rooms::LeaveRoomMethod>()
+        .register::<rooms::RoomMembersMethod>()
+        .register::<rooms::SendRoomMessageMethod>()
+        .register::<history::HistoryMethod>()
+        .register::<history::FillGapMethod>()
+        .register::<presence::SubscribePresenceMethod>()
+        .register::<presence::UnsubscribePresenceMethod>()
+        .register::<presence::GetPresenceMethod>()
+        .register::<typing::TypingStartMethod>()
+        .register::<typing::TypingStopMethod>()
+        .register::<events::AckEventsMethod>();
+    registry
+}
+
+/// Deserializes request params, missing or mismatched params are invalid params error.
+pub fn parse_params<T: DeserializeOwned>(params: Option<Value>) -> Result<T, JRPCError> {
+    let params = params
+        .ok_or_else(|| JRPCError::invalid_params().with_data("Missing params".to_string()))?;
+    serde_json::from_value::<T>(params)
+        .map_err(|err| JRPCError::invalid_params().with_data(err.to_string()))
+}
@@ -0,0 +1,64 @@
+use hcwc_protocol::{
+    requests::JRPCPresenceRequestParams,
+    responses::{PresenceResult, UnsubscribePresenceResult},
+};
+
+use crate::chat_server::{GetPresence, SubscribePresence, UnsubscribePresence};
+
+use super::{MethodContext, RpcMethod};
+
+/// Pushes presence changes of the users to this session,
+/// returns their current presence.
+pub struct SubscribePresenceMethod;
+
+impl RpcMethod for SubscribePresenceMethod {
+    const NAME: &'static str = "subscribe_presence";
+
+    type Params = JRPCPresenceRequestParams;
+    type Result = PresenceResult;
+    type Message = SubscribePresence;
+
+    fn message(params: Self::Params, ctx: &MethodContext) -> Self::Message {
+        SubscribePresence {
+            user_id: ctx.user_id,
+            session_id: ctx.session_id,
+            user_ids: params.user_ids,
+        }
+    }
+}
+
+/// Stops pushing presence changes of the users.
+pub struct UnsubscribePresenceMethod;
+
+impl RpcMethod for UnsubscribePresenceMethod {
+    const NAME: &'static str = "unsubscribe_presence";
+
+    type Params = JRPCPresenceRequestParams;
+    type Result = UnsubscribePresenceResult;
+    type Message = UnsubscribePresence;
+
+    fn message(params: Self::Params, ctx: &MethodContext) -> Self::Message {
+        UnsubscribePresence {
+            user_id: ctx.user_id,
+            session_id: ctx.session_id,
+            user_ids: params.user_ids,
+        }
+    }
+}
+
+/// Current presence of the users with the time they were last seen.
+pub struct GetPresenceMethod;
+
+impl RpcMethod for GetPresenceMethod {
+    const NAME: &'static str = "get_presence";
+
+    type Params = JRPCPresenceRequestParams;
+    type Result = PresenceResult;
+    type Message = GetPresence;
+
+    fn message(params: Self::Params, _: &MethodContext) -> Self::Message {
+        GetPresence {
+            user_ids: params.user_ids,
+        }
+    }
+}
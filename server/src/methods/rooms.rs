@@ -0,0 +1,127 @@
+use hcwc_protocol::{
+    mq_messages::RoomMessage,
+    requests::{
+        JRPCCreateRoomRequestParams, JRPCRoomInviteRequestParams, JRPCRoomMessageRequestParams,
+        JRPCRoomRequestParams,
+    },
+    responses::{RoomMembersResult, RoomResult, SendRoomMessageResult},
+};
+
+use crate::chat_server::{CreateRoom, InviteRoom, JoinRoom, LeaveRoom, RoomMembers};
+
+use super::{MethodContext, RpcMethod};
+
+/// Creates new room, the caller becomes its member and the listed users are invited.
+pub struct CreateRoomMethod;
+
+impl RpcMethod for CreateRoomMethod {
+    const NAME: &'static str = "create_room";
+
+    type Params = JRPCCreateRoomRequestParams;
+    type Result = RoomResult;
+    type Message = CreateRoom;
+
+    fn message(params: Self::Params, ctx: &MethodContext) -> Self::Message {
+        CreateRoom {
+            user_id: ctx.user_id,
+            members: params.members,
+        }
+    }
+}
+
+/// Invites the user to the room, only a member can invite.
+pub struct InviteRoomMethod;
+
+impl RpcMethod for InviteRoomMethod {
+    const NAME: &'static str = "invite_room";
+
+    type Params = JRPCRoomInviteRequestParams;
+    type Result = RoomResult;
+    type Message = InviteRoom;
+
+    fn message(params: Self::Params, ctx: &MethodContext) -> Self::Message {
+        InviteRoom {
+            user_id: ctx.user_id,
+            room_id: params.room_id,
+            invited: params.user_id,
+        }
+    }
+}
+
+/// Joins the room the user is invited to.
+///
+/// Room ids are sequential and easy to guess, the invite is what
+/// keeps users out of the rooms they are not asked to.
+pub struct JoinRoomMethod;
+
+impl RpcMethod for JoinRoomMethod {
+    const NAME: &'static str = "join_room";
+
+    type Params = JRPCRoomRequestParams;
+    type Result = RoomResult;
+    type Message = JoinRoom;
+
+    fn message(params: Self::Params, ctx: &MethodContext) -> Self::Message {
+        JoinRoom {
+            user_id: ctx.user_id,
+            room_id: params.room_id,
+        }
+    }
+}
+
+/// Leaves the room.
+pub struct LeaveRoomMethod;
+
+impl RpcMethod for LeaveRoomMethod {
+    const NAME: &'static str = "leave_room";
+
+    type Params = JRPCRoomRequestParams;
+    type Result = RoomResult;
+    type Message = LeaveRoom;
+
+    fn message(params: Self::Params, ctx: &MethodContext) -> Self::Message {
+        LeaveRoom {
+            user_id: ctx.user_id,
+            room_id: params.room_id,
+        }
+    }
+}
+
+/// Lists members of the room.
+pub struct RoomMembersMethod;
+
+impl RpcMethod for RoomMembersMethod {
+    const NAME: &'static str = "room_members";
+
+    type Params = JRPCRoomRequestParams;
+    type Result = RoomMembersResult;
+    type Message = RoomMembers;
+
+    fn message(params: Self::Params, ctx: &MethodContext) -> Self::Message {
+        RoomMembers {
+            user_id: ctx.user_id,
+            room_id: params.room_id,
+        }
+    }
+}
+
+/// Sends message to every member of the room.
+pub struct SendRoomMessageMethod;
+
+impl RpcMethod for SendRoomMessageMethod {
+    const NAME: &'static str = "send_room_message";
+
+    type Params = JRPCRoomMessageRequestParams;
+    type Result = SendRoomMessageResult;
+    type Message = RoomMessage;
+
+    fn message(params: Self::Params, ctx: &MethodContext) -> Self::Message {
+        RoomMessage {
+            id: ctx.user_id,
+            server_uuid: String::new(),
+            room_id: params.room_id,
+            msg: params.message,
+            timestamp: None,
+        }
+    }
+}
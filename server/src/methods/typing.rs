@@ -0,0 +1,44 @@
+use hcwc_protocol::{requests::JRPCTypingRequestParams, responses::TypingResult};
+
+use crate::chat_server::Typing;
+
+use super::{MethodContext, RpcMethod};
+
+/// Shows the typing indicator to the recipient,
+/// it expires unless the method is called again.
+pub struct TypingStartMethod;
+
+impl RpcMethod for TypingStartMethod {
+    const NAME: &'static str = "typing_start";
+
+    type Params = JRPCTypingRequestParams;
+    type Result = TypingResult;
+    type Message = Typing;
+
+    fn message(params: Self::Params, ctx: &MethodContext) -> Self::Message {
+        Typing {
+            sender: ctx.user_id,
+            recipient: params.recipient,
+            typing: true,
+        }
+    }
+}
+
+/// Hides the typing indicator from the recipient.
+pub struct TypingStopMethod;
+
+impl RpcMethod for TypingStopMethod {
+    const NAME: &'static str = "typing_stop";
+
+    type Params = JRPCTypingRequestParams;
+    type Result = TypingResult;
+    type Message = Typing;
+
+    fn message(params: Self::Params, ctx: &MethodContext) -> Self::Message {
+        Typing {
+            sender: ctx.user_id,
+            recipient: params.recipient,
+            typing: false,
+        }
+    }
+}
@@ -0,0 +1,64 @@
+use std::collections::{HashMap, HashSet};
+
+/// User id and session id of the session.
+pub type SessionKey = (usize, usize);
+
+/// Sessions connected to this server that watch presence of other users.
+#[derive(Debug, Default)]
+pub struct PresenceWatchers {
+    /// Sessions watching every user
+    watchers: HashMap<usize, HashSet<SessionKey>>,
+    /// Users watched by every session
+    watched: HashMap<SessionKey, HashSet<usize>>,
+}
+
+impl PresenceWatchers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn watch(&mut self, session: SessionKey, user_ids: &[usize]) {
+        for user_id in user_ids {
+            self.watchers.entry(*user_id).or_default().insert(session);
+        }
+        self.watched
+            .entry(session)
+            .or_default()
+            .extend(user_ids.iter().copied());
+    }
+
+    pub fn unwatch(&mut self, session: SessionKey, user_ids: &[usize]) {
+        for user_id in user_ids {
+            self.remove_watcher(*user_id, &session);
+        }
+        if let Some(watched) = self.watched.get_mut(&session) {
+            for user_id in user_ids {
+                watched.remove(user_id);
+            }
+            if watched.is_empty() {
+                self.watched.remove(&session);
+            }
+        }
+    }
+
+    /// Session is gone, it stops watching everyone.
+    pub fn remove_session(&mut self, session: SessionKey) {
+        for user_id in self.watched.remove(&session).unwrap_or_default() {
+            self.remove_watcher(user_id, &session);
+        }
+    }
+
+    /// Sessions watching the user.
+    pub fn watchers(&self, user_id: &usize) -> impl Iterator<Item = &SessionKey> {
+        self.watchers.get(user_id).into_iter().flatten()
+    }
+
+    fn remove_watcher(&mut self, user_id: usize, session: &SessionKey) {
+        if let Some(sessions) = self.watchers.get_mut(&user_id) {
+            sessions.remove(session);
+            if sessions.is_empty() {
+                self.watchers.remove(&user_id);
+            }
+        }
+    }
+}
@@ -1,35 +1,142 @@
+use std::{future::Future, sync::Arc};
+
 use actix::Addr;
 use futures_util::stream::StreamExt;
+use serde::de::DeserializeOwned;
 
-use crate::{
-    chat_server::{ChatServer, ClientMessage},
-    responses::{ChatMessageResult, JRPCResponse},
+use hcwc_bus::{BusError, BusMessage, MessageBus, Subscription};
+use hcwc_protocol::mq_messages::{
+    self, ClientMessage, MessageStatus, PresenceChanged, RoutedReceipt, RoutedRoomMessage,
+    TypingEvent,
 };
 
-pub async fn subscriber(chat_server: Addr<ChatServer>, server_uuid: String) {
-    let nc = async_nats::connect("localhost").await.unwrap();
-    let mut qsub = nc
-        .queue_subscribe(
-            format!("message.{}.send", server_uuid),
-            "my_group".to_string(),
-        )
-        .await
-        .unwrap();
+use crate::chat_server::{ChatServer, Deliver};
+
+/// Message is handled by the chat server, it is not delivered again.
+async fn ack(msg: &BusMessage) {
+    if let Err(err) = msg.ack().await {
+        println!("Cannot ack message: {}", err);
+    }
+}
+
+/// Payload of the message, malformed message is acked and skipped,
+/// it doesn't parse when it is delivered again either.
+async fn parse<T: DeserializeOwned>(msg: &BusMessage) -> Option<T> {
+    match serde_json::from_slice(&msg.payload) {
+        Ok(res) => Some(res),
+        Err(err) => {
+            println!("Malformed message on {}: {}", msg.subject, err);
+            ack(msg).await;
+            None
+        }
+    }
+}
+
+/// Subscriptions of the server to its own subjects and to the presence changes.
+struct Subscriptions {
+    send: Subscription,
+    status: Subscription,
+    receipt: Subscription,
+    room: Subscription,
+    typing: Subscription,
+    presence: Subscription,
+}
+
+/// Subscribes the server to its subjects, returns the loop
+/// passing messages from the bus to the chat server.
+///
+/// Fails if any subscription fails, the server can't work without them.
+pub async fn subscriber(
+    chat_server: Addr<ChatServer>,
+    bus: Arc<dyn MessageBus>,
+    server_uuid: String,
+    queue_group: String,
+) -> Result<impl Future<Output = ()>, BusError> {
+    let subscriptions = Subscriptions {
+        send: bus
+            .queue_subscribe(&mq_messages::send_subject(&server_uuid), &queue_group)
+            .await?,
+        status: bus
+            .queue_subscribe(&mq_messages::status_subject(&server_uuid), &queue_group)
+            .await?,
+        receipt: bus
+            .queue_subscribe(&mq_messages::receipt_subject(&server_uuid), &queue_group)
+            .await?,
+        room: bus
+            .queue_subscribe(&mq_messages::room_send_subject(&server_uuid), &queue_group)
+            .await?,
+        typing: bus
+            .queue_subscribe(
+                &mq_messages::typing_send_subject(&server_uuid),
+                &queue_group,
+            )
+            .await?,
+        // Every server gets every presence change.
+        presence: bus.subscribe(mq_messages::PRESENCE_SUBJECT).await?,
+    };
+    Ok(receive(chat_server, subscriptions))
+}
+
+async fn receive(chat_server: Addr<ChatServer>, subscriptions: Subscriptions) {
+    let Subscriptions {
+        send: mut qsub,
+        status: mut status_sub,
+        receipt: mut receipt_sub,
+        room: mut room_sub,
+        typing: mut typing_sub,
+        presence: mut presence_sub,
+    } = subscriptions;
 
     loop {
         // Receive a message.
-        if let Some(msg) = qsub.next().await {
-            let res = serde_json::from_slice::<ClientMessage>(&msg.payload).unwrap();
-            chat_server.do_send(JRPCResponse::new(
-                Some(0),
-                Some(ChatMessageResult {
-                    message: res.msg,
-                    recipient: res.recipient,
-                }),
-                None::<()>,
-            ));
-            println!("{:?}", msg);
-            println!("{:?}", chat_server);
+        tokio::select! {
+            Some(msg) = qsub.next() => {
+                let Some(res) = parse::<ClientMessage>(&msg).await else {
+                    continue;
+                };
+                // Unacked message is delivered again by the durable transports.
+                match chat_server.send(Deliver { message: res }).await {
+                    Ok(Ok(())) => ack(&msg).await,
+                    Ok(Err(err)) => println!("Cannot deliver message: {}", err),
+                    Err(err) => println!("Cannot deliver message: {}", err),
+                }
+            }
+            Some(msg) = status_sub.next() => {
+                let Some(res) = parse::<MessageStatus>(&msg).await else {
+                    continue;
+                };
+                chat_server.do_send(res);
+                ack(&msg).await;
+            }
+            Some(msg) = receipt_sub.next() => {
+                let Some(res) = parse::<RoutedReceipt>(&msg).await else {
+                    continue;
+                };
+                chat_server.do_send(res);
+                ack(&msg).await;
+            }
+            Some(msg) = room_sub.next() => {
+                let Some(res) = parse::<RoutedRoomMessage>(&msg).await else {
+                    continue;
+                };
+                chat_server.do_send(res);
+                ack(&msg).await;
+            }
+            Some(msg) = presence_sub.next() => {
+                let Some(res) = parse::<PresenceChanged>(&msg).await else {
+                    continue;
+                };
+                chat_server.do_send(res);
+                ack(&msg).await;
+            }
+            Some(msg) = typing_sub.next() => {
+                // Typing event is worthless once it is late, it is never delivered again.
+                ack(&msg).await;
+                if let Ok(res) = serde_json::from_slice::<TypingEvent>(&msg.payload) {
+                    chat_server.do_send(res);
+                }
+            }
+            else => break,
         }
     }
 }
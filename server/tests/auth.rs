@@ -0,0 +1,73 @@
+//! Session opened without a token authenticates with its first request.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use hcwc_protocol::{
+    notifications,
+    responses::{PARSE_ERROR, UNAUTHORIZED},
+};
+use jsonwebtoken::{encode, EncodingKey, Header};
+use serde_json::{json, Value};
+
+mod common;
+
+use common::{chat_server, Socket};
+
+/// Token of the user signed with the secret of the test sessions.
+fn token(user_id: usize) -> String {
+    let exp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+        + 3600;
+    encode(
+        &Header::default(),
+        &json!({"sub": user_id.to_string(), "exp": exp}),
+        &EncodingKey::from_secret(b"secret"),
+    )
+    .unwrap()
+}
+
+fn auth(token: &str) -> String {
+    json!({"jsonrpc": "2.0", "id": 1, "method": "auth", "params": {"token": token}}).to_string()
+}
+
+#[actix_web::test]
+async fn first_frame_must_be_json() {
+    let mut socket = Socket::connect(chat_server(), None);
+    socket.send_text("hello");
+
+    let response = socket.next_json().await;
+    assert_eq!(response["id"], Value::Null);
+    assert_eq!(response["error"]["code"], PARSE_ERROR);
+
+    // Session is still open for `auth`.
+    socket.send_text(&auth(&token(7)));
+    let response = socket.next_json().await;
+    assert_eq!(response["result"]["user_id"], 7);
+    socket.expect(notifications::CONNECTED).await;
+}
+
+#[actix_web::test]
+async fn first_request_must_be_auth() {
+    let mut socket = Socket::connect(chat_server(), None);
+    socket.send_text(
+        &json!({"jsonrpc": "2.0", "id": 3, "method": "create_room", "params": {}}).to_string(),
+    );
+
+    let response = socket.next_json().await;
+    assert_eq!(response["id"], 3);
+    assert_eq!(response["error"]["code"], UNAUTHORIZED);
+    socket.expect_nothing().await;
+}
+
+#[actix_web::test]
+async fn bad_token_is_unauthorized() {
+    let mut socket = Socket::connect(chat_server(), None);
+    socket.send_text(&auth("not a token"));
+
+    let response = socket.next_json().await;
+    assert_eq!(response["id"], 1);
+    assert_eq!(response["error"]["code"], UNAUTHORIZED);
+    socket.expect_nothing().await;
+}
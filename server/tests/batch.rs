@@ -0,0 +1,86 @@
+//! Batches of JSON-RPC requests in one frame.
+
+use hcwc_protocol::{notifications, responses::INVALID_REQUEST};
+use serde_json::{json, Value};
+
+mod common;
+
+use common::{chat_server, Socket};
+
+async fn start() -> Socket {
+    let mut socket = Socket::connect(chat_server(), Some(1));
+    socket.expect(notifications::CONNECTED).await;
+    socket
+}
+
+fn create_room(id: Option<u64>) -> Value {
+    let mut request = json!({"jsonrpc": "2.0", "method": "create_room", "params": {}});
+    if let Some(id) = id {
+        request["id"] = json!(id);
+    }
+    request
+}
+
+/// Ids of the responses, in the order of the requests.
+fn ids(responses: &Value) -> Vec<Value> {
+    responses
+        .as_array()
+        .expect("batch is answered with an array")
+        .iter()
+        .map(|response| response["id"].clone())
+        .collect()
+}
+
+#[actix_web::test]
+async fn batch_is_answered_with_array() {
+    let mut socket = start().await;
+    socket.send_text(&json!([create_room(Some(1)), create_room(Some(2))]).to_string());
+
+    let responses = socket.next_json().await;
+    assert_eq!(ids(&responses), vec![json!(1), json!(2)]);
+    for response in responses.as_array().unwrap() {
+        assert!(response["result"]["room_id"].is_u64());
+    }
+}
+
+#[actix_web::test]
+async fn notifications_in_batch_are_not_answered() {
+    let mut socket = start().await;
+    socket.send_text(
+        &json!([
+            create_room(None),
+            create_room(Some(1)),
+            7,
+            create_room(None)
+        ])
+        .to_string(),
+    );
+
+    let responses = socket.next_json().await;
+    assert_eq!(ids(&responses), vec![json!(1), Value::Null]);
+    assert_eq!(responses[1]["error"]["code"], INVALID_REQUEST);
+    socket.expect_nothing().await;
+}
+
+#[actix_web::test]
+async fn empty_batch_is_invalid_request() {
+    let mut socket = start().await;
+    socket.send_text("[]");
+
+    let response = socket.next_json().await;
+    assert!(response.is_object(), "empty batch is a single request");
+    assert_eq!(response["id"], Value::Null);
+    assert_eq!(response["error"]["code"], INVALID_REQUEST);
+}
+
+#[actix_web::test]
+async fn batch_of_notifications_gets_nothing() {
+    let mut socket = start().await;
+    socket.send_text(&json!([create_room(None), create_room(None)]).to_string());
+    socket.expect_nothing().await;
+
+    // Notifications were carried out all the same.
+    socket.send_text(&create_room(Some(1)).to_string());
+    let response = socket.next_json().await;
+    assert_eq!(response["result"]["room_id"], 3);
+}
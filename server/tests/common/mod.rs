@@ -0,0 +1,192 @@
+//! Websocket sessions driven without a socket, frames they write are read back.
+
+#![allow(dead_code)]
+
+use std::{collections::HashMap, pin::Pin, sync::Arc, time::Duration};
+
+use actix::{clock::Instant, Actor, Addr};
+use actix_web::error::PayloadError;
+use actix_web_actors::ws;
+use bytes::Bytes;
+use futures::{channel::mpsc, Stream, StreamExt};
+use hcwc_bus::{InProcessBus, MessageBus};
+use hcwc_cache::{CacheDB, MemoryCache};
+use hcwc_protocol::{
+    notifications::JRPCNotification, pending::PendingLimits, requests::ResumeParams,
+};
+use serde_json::Value;
+use server::{
+    auth::TokenVerifier,
+    chat_server::ChatServer,
+    chat_session::{ChatSession, Heartbeat},
+    methods,
+};
+
+/// How long a test waits for a frame it expects.
+pub const TIMEOUT: Duration = Duration::from_secs(2);
+
+type Frames = Pin<Box<dyn Stream<Item = Result<Bytes, actix_web::Error>>>>;
+
+/// Chat server on the in-memory cache and bus.
+pub fn chat_server() -> Addr<ChatServer> {
+    start_chat_server(
+        Arc::new(MemoryCache::new()),
+        Arc::new(InProcessBus::new()),
+        "test",
+    )
+}
+
+pub fn start_chat_server(
+    cache: Arc<dyn CacheDB>,
+    bus: Arc<dyn MessageBus>,
+    server_uuid: &str,
+) -> Addr<ChatServer> {
+    ChatServer::new(
+        cache,
+        bus,
+        server_uuid.to_string(),
+        PendingLimits::default(),
+    )
+    .start()
+}
+
+pub struct Socket {
+    pub addr: Addr<ChatSession>,
+    /// Frames of the client, the session reads them as they come
+    input: mpsc::UnboundedSender<Result<Bytes, PayloadError>>,
+    frames: Frames,
+    buffer: Vec<u8>,
+}
+
+impl Socket {
+    /// Session of the user authenticated during the upgrade,
+    /// `None` for the session that hasn't sent `auth` yet.
+    pub fn connect(server: Addr<ChatServer>, user_id: Option<usize>) -> Self {
+        Self::start(server, user_id, None, Heartbeat::default())
+    }
+
+    /// Session of the user that pings the client with the heartbeat.
+    pub fn with_heartbeat(server: Addr<ChatServer>, user_id: usize, heartbeat: Heartbeat) -> Self {
+        Self::start(server, Some(user_id), None, heartbeat)
+    }
+
+    /// Session of the user that resumes the session with the token.
+    pub fn resume(server: Addr<ChatServer>, user_id: usize, resume: ResumeParams) -> Self {
+        Self::start(server, Some(user_id), Some(resume), Heartbeat::default())
+    }
+
+    fn start(
+        server: Addr<ChatServer>,
+        user_id: Option<usize>,
+        resume: Option<ResumeParams>,
+        heartbeat: Heartbeat,
+    ) -> Self {
+        let session = ChatSession {
+            user_id,
+            session_id: 0,
+            hb: Instant::now(),
+            addr: server,
+            methods: Arc::new(methods::registry()),
+            verifier: Arc::new(TokenVerifier::new(b"secret")),
+            resume,
+            sequences: HashMap::new(),
+            heartbeat,
+        };
+        let (input, client_frames) = mpsc::unbounded();
+        let (addr, frames) = ws::WebsocketContext::create_with_addr(session, client_frames);
+        Self {
+            addr,
+            input,
+            frames: Box::pin(frames),
+            buffer: vec![],
+        }
+    }
+
+    /// Writes the text frame of the client, client frames
+    /// are masked, the key of zeros leaves the text as it is.
+    pub fn send_text(&self, text: &str) {
+        let mut frame = vec![0x81];
+        match text.len() {
+            len if len < 126 => frame.push(0x80 | len as u8),
+            len if len <= u16::MAX as usize => {
+                frame.push(0x80 | 126);
+                frame.extend_from_slice(&(len as u16).to_be_bytes());
+            }
+            len => {
+                frame.push(0x80 | 127);
+                frame.extend_from_slice(&(len as u64).to_be_bytes());
+            }
+        }
+        frame.extend_from_slice(&[0; 4]);
+        frame.extend_from_slice(text.as_bytes());
+        self.input.unbounded_send(Ok(Bytes::from(frame))).unwrap();
+    }
+
+    /// Text of the next complete frame in the buffer, server frames are not masked.
+    fn take_frame(&mut self) -> Option<String> {
+        loop {
+            let (opcode, len) = (*self.buffer.first()? & 0x0f, *self.buffer.get(1)? & 0x7f);
+            let (header, len) = match len {
+                126 => (
+                    4,
+                    u16::from_be_bytes(self.buffer.get(2..4)?.try_into().ok()?) as usize,
+                ),
+                127 => (
+                    10,
+                    u64::from_be_bytes(self.buffer.get(2..10)?.try_into().ok()?) as usize,
+                ),
+                len => (2, len as usize),
+            };
+            if self.buffer.len() < header + len {
+                return None;
+            }
+            let frame: Vec<u8> = self.buffer.drain(..header + len).skip(header).collect();
+            // Pings of the heartbeat are skipped.
+            if opcode == 0x1 {
+                return Some(String::from_utf8(frame).unwrap());
+            }
+        }
+    }
+
+    /// Next notification written to the socket, `None` if nothing comes in time.
+    pub async fn next(&mut self, timeout: Duration) -> Option<JRPCNotification> {
+        let text = self.next_text(timeout).await?;
+        Some(serde_json::from_str(&text).unwrap())
+    }
+
+    /// Next frame written to the socket as JSON, responses are not notifications.
+    pub async fn next_json(&mut self) -> Value {
+        let text = self.next_text(TIMEOUT).await.expect("nothing is written");
+        serde_json::from_str(&text).unwrap()
+    }
+
+    async fn next_text(&mut self, timeout: Duration) -> Option<String> {
+        let deadline = tokio::time::Instant::now() + timeout;
+        loop {
+            if let Some(text) = self.take_frame() {
+                return Some(text);
+            }
+            match tokio::time::timeout_at(deadline, self.frames.next()).await {
+                Ok(Some(bytes)) => self.buffer.extend_from_slice(&bytes.unwrap()),
+                Ok(None) | Err(_) => return None,
+            }
+        }
+    }
+
+    /// Next notification with the method, other notifications are skipped.
+    pub async fn expect(&mut self, method: &str) -> JRPCNotification {
+        let deadline = tokio::time::Instant::now() + TIMEOUT;
+        loop {
+            let left = deadline.saturating_duration_since(tokio::time::Instant::now());
+            match self.next(left).await {
+                Some(notification) if notification.method == method => return notification,
+                Some(_) => {}
+                None => panic!("no {} notification", method),
+            }
+        }
+    }
+
+    pub async fn expect_nothing(&mut self) {
+        assert!(self.next(Duration::from_millis(100)).await.is_none());
+    }
+}
@@ -0,0 +1,128 @@
+//! Where the settings of the server come from and which of them are rejected.
+
+use clap::Parser;
+use hcwc_config::{ConfigFile, ServerSection};
+use server::config::{Args, ServerConfig};
+
+fn args(flags: &[&str]) -> Args {
+    Args::try_parse_from(std::iter::once("server").chain(flags.iter().copied())).unwrap()
+}
+
+fn file(server: ServerSection) -> ConfigFile {
+    ConfigFile {
+        server: ServerSection {
+            auth_secret: Some("secret".to_string()),
+            ..server
+        },
+        ..Default::default()
+    }
+}
+
+fn error(flags: &[&str], server: ServerSection) -> String {
+    ServerConfig::resolve(&args(flags), &file(server))
+        .unwrap_err()
+        .to_string()
+}
+
+// The only test that sets the environment, clap reads it in every test.
+#[test]
+fn flag_beats_env_beats_file_beats_default() {
+    let config = ServerConfig::resolve(&args(&[]), &file(ServerSection::default())).unwrap();
+    assert_eq!(config.workers, 6);
+
+    let from_file = ServerSection {
+        workers: Some(2),
+        ..Default::default()
+    };
+    let config = ServerConfig::resolve(&args(&[]), &file(from_file)).unwrap();
+    assert_eq!(config.workers, 2);
+
+    std::env::set_var("HCWC_SERVER_WORKERS", "3");
+    let from_file = ServerSection {
+        workers: Some(2),
+        ..Default::default()
+    };
+    let config = ServerConfig::resolve(&args(&[]), &file(from_file)).unwrap();
+    assert_eq!(config.workers, 3);
+
+    let from_file = ServerSection {
+        workers: Some(2),
+        ..Default::default()
+    };
+    let config = ServerConfig::resolve(&args(&["--workers", "4"]), &file(from_file)).unwrap();
+    std::env::remove_var("HCWC_SERVER_WORKERS");
+    assert_eq!(config.workers, 4);
+}
+
+#[test]
+fn heartbeat_from_flags_and_file() {
+    let from_file = ServerSection {
+        heartbeat_interval: Some(2),
+        client_timeout: Some(8),
+        ..Default::default()
+    };
+    let config =
+        ServerConfig::resolve(&args(&["--client-timeout", "6"]), &file(from_file)).unwrap();
+    assert_eq!(config.heartbeat.interval.as_secs(), 2);
+    assert_eq!(config.heartbeat.client_timeout.as_secs(), 6);
+}
+
+#[test]
+fn client_timeout_must_exceed_heartbeat_interval() {
+    assert_eq!(
+        error(
+            &["--heartbeat-interval", "10", "--client-timeout", "10"],
+            ServerSection::default()
+        ),
+        "Bad server.client_timeout: must be greater than server.heartbeat_interval"
+    );
+}
+
+#[test]
+fn client_timeout_must_fit_resume_grace() {
+    assert_eq!(
+        error(&["--client-timeout", "21"], ServerSection::default()),
+        "Bad server.client_timeout: must not be greater than the resume grace of 20s"
+    );
+    let config = ServerConfig::resolve(
+        &args(&["--client-timeout", "20"]),
+        &file(ServerSection::default()),
+    )
+    .unwrap();
+    assert_eq!(config.heartbeat.client_timeout.as_secs(), 20);
+}
+
+#[test]
+fn zero_values_are_rejected() {
+    assert_eq!(
+        error(&["--heartbeat-interval", "0"], ServerSection::default()),
+        "Bad server.heartbeat_interval: must be greater than 0"
+    );
+    assert_eq!(
+        error(&["--port", "0"], ServerSection::default()),
+        "Bad server.port: must be greater than 0"
+    );
+}
+
+#[test]
+fn auth_secret_is_required() {
+    let message = ServerConfig::resolve(&args(&[]), &ConfigFile::default())
+        .unwrap_err()
+        .to_string();
+    assert_eq!(
+        message,
+        "Bad server.auth_secret: must be set in the config file or with HCWC_AUTH_SECRET"
+    );
+}
+
+#[test]
+fn memory_backends_are_rejected() {
+    assert_eq!(
+        error(&["--bus-backend", "memory"], ServerSection::default()),
+        "Bad bus.backend: memory bus is not shared between processes, expected nats, jetstream or redis"
+    );
+    assert_eq!(
+        error(&["--cache-backend", "memory"], ServerSection::default()),
+        "Bad cache.backend: memory cache is not shared between processes, expected redis"
+    );
+}
@@ -0,0 +1,91 @@
+//! Pages of the history read through the worker, with no broker.
+
+use std::sync::Arc;
+
+use hcwc_bus::{InProcessBus, MessageBus};
+use hcwc_cache::{CacheDB, MemoryCache};
+use hcwc_protocol::{notifications, pending::PendingLimits};
+use serde_json::{json, Value};
+use worker::history::{HistoryStore, MemoryHistory};
+
+mod common;
+
+use common::{start_chat_server, Socket};
+
+/// Session of user 1 with a worker answering from the history
+/// of its conversation with user 2.
+async fn start(messages: usize) -> Socket {
+    let bus: Arc<dyn MessageBus> = Arc::new(InProcessBus::new());
+    let cache: Arc<dyn CacheDB> = Arc::new(MemoryCache::new());
+    let history: Arc<dyn HistoryStore> = Arc::new(MemoryHistory::new());
+    for i in 0..messages {
+        history
+            .store(1, 2, &format!("message {}", i), Some(i as u64 + 1))
+            .unwrap();
+    }
+    let server = start_chat_server(cache.clone(), bus.clone(), "node");
+    tokio::spawn(worker::run(
+        bus,
+        cache,
+        history,
+        PendingLimits::default(),
+        "group".to_string(),
+    ));
+
+    let mut socket = Socket::connect(server, Some(1));
+    socket.expect(notifications::CONNECTED).await;
+    socket
+}
+
+/// Ids of the messages of the page and whether there are more.
+async fn page(socket: &mut Socket, params: Value) -> (Vec<u64>, bool) {
+    socket.send_text(
+        &json!({"jsonrpc": "2.0", "id": 1, "method": "history", "params": params}).to_string(),
+    );
+    let response = socket.next_json().await;
+    let result = &response["result"];
+    let ids = result["messages"]
+        .as_array()
+        .unwrap_or_else(|| panic!("no page: {}", response))
+        .iter()
+        .map(|message| message["id"].as_u64().unwrap())
+        .collect();
+    (ids, result["has_more"].as_bool().unwrap())
+}
+
+#[actix_web::test]
+async fn has_more_in_both_directions() {
+    let mut socket = start(5).await;
+
+    assert_eq!(
+        page(&mut socket, json!({"peer": 2, "limit": 2})).await,
+        (vec![4, 5], true)
+    );
+    assert_eq!(
+        page(&mut socket, json!({"peer": 2, "before": 4, "limit": 2})).await,
+        (vec![2, 3], true)
+    );
+    assert_eq!(
+        page(&mut socket, json!({"peer": 2, "before": 2, "limit": 2})).await,
+        (vec![1], false)
+    );
+    assert_eq!(
+        page(&mut socket, json!({"peer": 2, "after": 1, "limit": 2})).await,
+        (vec![2, 3], true)
+    );
+    assert_eq!(
+        page(&mut socket, json!({"peer": 2, "after": 3, "limit": 2})).await,
+        (vec![4, 5], false)
+    );
+}
+
+#[actix_web::test]
+async fn limit_is_clamped() {
+    let mut socket = start(205).await;
+
+    let (ids, has_more) = page(&mut socket, json!({"peer": 2, "limit": 1000})).await;
+    assert_eq!((ids.len(), ids[0], has_more), (200, 6, true));
+
+    let (ids, has_more) = page(&mut socket, json!({"peer": 2, "limit": 0})).await;
+    assert_eq!((ids, has_more), (vec![205], true));
+}
@@ -0,0 +1,110 @@
+//! JSON-RPC errors of malformed requests and the method listing.
+
+use hcwc_protocol::{
+    notifications,
+    responses::{INVALID_PARAMS, INVALID_REQUEST, METHOD_NOT_FOUND, PARSE_ERROR},
+};
+use serde_json::{json, Value};
+
+mod common;
+
+use common::{chat_server, Socket};
+
+async fn start() -> Socket {
+    let mut socket = Socket::connect(chat_server(), Some(1));
+    socket.expect(notifications::CONNECTED).await;
+    socket
+}
+
+async fn call(socket: &mut Socket, request: Value) -> Value {
+    socket.send_text(&request.to_string());
+    socket.next_json().await
+}
+
+#[actix_web::test]
+async fn malformed_frame_is_parse_error() {
+    let mut socket = start().await;
+    socket.send_text("{\"jsonrpc\": \"2.0\",");
+
+    let response = socket.next_json().await;
+    assert_eq!(response["id"], Value::Null);
+    assert_eq!(response["error"]["code"], PARSE_ERROR);
+
+    // Session keeps answering.
+    let response = call(
+        &mut socket,
+        json!({"jsonrpc": "2.0", "id": 1, "method": "create_room", "params": {}}),
+    )
+    .await;
+    assert_eq!(response["id"], 1);
+    assert!(response["result"]["room_id"].is_u64());
+}
+
+#[actix_web::test]
+async fn wrong_version_is_invalid_request() {
+    let mut socket = start().await;
+    let response = call(
+        &mut socket,
+        json!({"jsonrpc": "1.0", "id": 1, "method": "create_room", "params": {}}),
+    )
+    .await;
+    assert_eq!(response["id"], 1);
+    assert_eq!(response["error"]["code"], INVALID_REQUEST);
+}
+
+#[actix_web::test]
+async fn unknown_method_is_not_found() {
+    let mut socket = start().await;
+    let response = call(
+        &mut socket,
+        json!({"jsonrpc": "2.0", "id": "a", "method": "no_such_method"}),
+    )
+    .await;
+    assert_eq!(response["id"], "a");
+    assert_eq!(response["error"]["code"], METHOD_NOT_FOUND);
+    assert_eq!(response["error"]["data"], "no_such_method");
+}
+
+#[actix_web::test]
+async fn bad_params_are_invalid_params() {
+    let mut socket = start().await;
+    let response = call(
+        &mut socket,
+        json!({"jsonrpc": "2.0", "id": 1, "method": "room_members", "params": {"room_id": "one"}}),
+    )
+    .await;
+    assert_eq!(response["error"]["code"], INVALID_PARAMS);
+
+    let response = call(
+        &mut socket,
+        json!({"jsonrpc": "2.0", "id": 2, "method": "room_members"}),
+    )
+    .await;
+    assert_eq!(response["id"], 2);
+    assert_eq!(response["error"]["code"], INVALID_PARAMS);
+}
+
+#[actix_web::test]
+async fn discover_lists_methods_with_schemas() {
+    let mut socket = start().await;
+    let response = call(
+        &mut socket,
+        json!({"jsonrpc": "2.0", "id": 1, "method": "rpc.discover"}),
+    )
+    .await;
+
+    let methods = response["result"]["methods"].as_array().unwrap();
+    let method = |name: &str| {
+        methods
+            .iter()
+            .find(|method| method["name"] == name)
+            .unwrap_or_else(|| panic!("{} is not listed", name))
+            .clone()
+    };
+    let room_members = method("room_members");
+    assert!(room_members["params"]["properties"]["room_id"].is_object());
+    assert!(room_members["result"]["properties"]["members"].is_object());
+    assert_eq!(method("rpc.discover")["params"], Value::Null);
+    // The session answers `auth` itself, it is not a method of the registry.
+    assert!(methods.iter().all(|method| method["name"] != "auth"));
+}
@@ -0,0 +1,115 @@
+//! Order of the messages of one sender on the session of the recipient.
+
+use hcwc_protocol::notifications::{self, ChatMessageParams, JRPCNotification, MessageGapParams};
+
+mod common;
+
+use common::{Socket, TIMEOUT};
+
+const SENDER: usize = 7;
+
+fn send(socket: &Socket, seq: u64) {
+    send_from(socket, SENDER, seq);
+}
+
+fn send_from(socket: &Socket, sender: usize, seq: u64) {
+    socket.addr.do_send(JRPCNotification::new(
+        notifications::MESSAGE,
+        ChatMessageParams {
+            id: None,
+            sender,
+            message: format!("message {}", seq),
+            timestamp: None,
+            seq: Some(seq),
+        },
+    ));
+}
+
+async fn expect_message(socket: &mut Socket, seq: u64) {
+    let msg = socket.next(TIMEOUT).await.expect("message is not written");
+    assert_eq!(msg.method, notifications::MESSAGE);
+    let params: ChatMessageParams = serde_json::from_value(msg.params.unwrap()).unwrap();
+    assert_eq!((params.sender, params.seq), (SENDER, Some(seq)));
+}
+
+async fn expect_gap(socket: &mut Socket, from_seq: u64, to_seq: u64) {
+    let msg = socket.next(TIMEOUT).await.expect("gap is not reported");
+    assert_eq!(msg.method, notifications::MESSAGE_GAP);
+    let params: MessageGapParams = serde_json::from_value(msg.params.unwrap()).unwrap();
+    assert_eq!(
+        (params.sender, params.from_seq, params.to_seq),
+        (SENDER, from_seq, to_seq)
+    );
+}
+
+fn start() -> Socket {
+    Socket::connect(common::chat_server(), None)
+}
+
+#[actix_web::test]
+async fn early_message_waits_for_missing_one() {
+    let mut socket = start();
+    send(&socket, 1);
+    send(&socket, 3);
+    send(&socket, 2);
+
+    expect_message(&mut socket, 1).await;
+    expect_message(&mut socket, 2).await;
+    expect_message(&mut socket, 3).await;
+    socket.expect_nothing().await;
+}
+
+#[actix_web::test]
+async fn message_before_first_one_is_not_dropped() {
+    let mut socket = start();
+    send(&socket, 5);
+    expect_message(&mut socket, 5).await;
+
+    // Pending message flushed after a live one.
+    send(&socket, 4);
+    expect_message(&mut socket, 4).await;
+
+    // Redelivered messages are written once.
+    send(&socket, 4);
+    send(&socket, 5);
+    socket.expect_nothing().await;
+}
+
+#[actix_web::test]
+async fn gap_is_reported_after_timeout() {
+    let mut socket = start();
+    send(&socket, 1);
+    send(&socket, 4);
+
+    expect_message(&mut socket, 1).await;
+    expect_gap(&mut socket, 2, 3).await;
+    expect_message(&mut socket, 4).await;
+
+    // Late message of the gap still reaches the client.
+    send(&socket, 3);
+    expect_message(&mut socket, 3).await;
+    send(&socket, 5);
+    expect_message(&mut socket, 5).await;
+}
+
+#[actix_web::test]
+async fn sender_with_held_messages_is_not_forgotten() {
+    let mut socket = start();
+    send(&socket, 1);
+    send(&socket, 3);
+    expect_message(&mut socket, 1).await;
+
+    // More senders than the session remembers.
+    for sender in 100..400 {
+        send_from(&socket, sender, 1);
+    }
+    for _ in 100..400 {
+        let msg = socket.next(TIMEOUT).await.expect("message is not written");
+        assert_eq!(msg.method, notifications::MESSAGE);
+    }
+
+    send(&socket, 2);
+    expect_message(&mut socket, 2).await;
+    expect_message(&mut socket, 3).await;
+    socket.expect_nothing().await;
+}
@@ -0,0 +1,144 @@
+//! Presence of the users kept by the heartbeat of their sessions
+//! and pushed to the sessions that watch it.
+
+use std::{sync::Arc, time::Duration};
+
+use bytes::Bytes;
+use hcwc_bus::{InProcessBus, MessageBus};
+use hcwc_cache::{CacheDB, MemoryCache};
+use hcwc_protocol::{
+    mq_messages::{PresenceChanged, PRESENCE_SUBJECT},
+    notifications,
+    responses::{UserPresence, INVALID_PARAMS},
+};
+use serde_json::{json, Value};
+use server::{chat_session::Heartbeat, subscriber};
+
+mod common;
+
+use common::{start_chat_server, Socket};
+
+const SERVER_UUID: &str = "test";
+
+/// Waits until the user's presence in the cache is as expected.
+async fn wait_presence(cache: &dyn CacheDB, user_id: usize, online: bool) {
+    for _ in 0..100 {
+        if cache.is_user_exist(user_id).await.unwrap() == online {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    panic!(
+        "user {} is not {}",
+        user_id,
+        if online { "online" } else { "offline" }
+    );
+}
+
+#[actix_web::test]
+async fn heartbeat_refreshes_presence() {
+    let cache: Arc<dyn CacheDB> = Arc::new(MemoryCache::new());
+    let server = start_chat_server(cache.clone(), Arc::new(InProcessBus::new()), SERVER_UUID);
+    let heartbeat = Heartbeat {
+        interval: Duration::from_millis(20),
+        client_timeout: Duration::from_secs(10),
+    };
+    let mut quiet = Socket::connect(server.clone(), Some(1));
+    let mut beating = Socket::with_heartbeat(server.clone(), 2, heartbeat);
+    quiet.expect(notifications::CONNECTED).await;
+    beating.expect(notifications::CONNECTED).await;
+    wait_presence(cache.as_ref(), 1, true).await;
+    wait_presence(cache.as_ref(), 2, true).await;
+
+    // Presence of both users has expired in the cache.
+    cache.remove_user(1, SERVER_UUID).await.unwrap();
+    cache.remove_user(2, SERVER_UUID).await.unwrap();
+
+    // Next ping of the session marks the user again,
+    // the session runs while its frames are read.
+    beating.expect_nothing().await;
+    quiet.expect_nothing().await;
+    assert!(cache.is_user_exist(2).await.unwrap());
+    assert!(!cache.is_user_exist(1).await.unwrap());
+}
+
+fn presence_request(id: u64, method: &str, user_ids: &[usize]) -> String {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "method": method,
+        "params": { "user_ids": user_ids },
+    })
+    .to_string()
+}
+
+async fn expect_presence(socket: &mut Socket) -> UserPresence {
+    let notification = socket.expect(notifications::PRESENCE).await;
+    serde_json::from_value(notification.params.unwrap()).unwrap()
+}
+
+#[actix_web::test]
+async fn watcher_is_told_when_user_comes_online_and_goes_offline() {
+    let bus: Arc<dyn MessageBus> = Arc::new(InProcessBus::new());
+    let server = start_chat_server(Arc::new(MemoryCache::new()), bus.clone(), SERVER_UUID);
+    tokio::spawn(
+        subscriber::subscriber(
+            server.clone(),
+            bus.clone(),
+            SERVER_UUID.to_string(),
+            "group".to_string(),
+        )
+        .await
+        .unwrap(),
+    );
+
+    let mut watcher = Socket::connect(server.clone(), Some(1));
+    watcher.expect(notifications::CONNECTED).await;
+    watcher.send_text(&presence_request(1, "subscribe_presence", &[2]));
+    let response = watcher.next_json().await;
+    assert_eq!(response["result"]["users"][0]["online"], Value::Bool(false));
+
+    let mut watched = Socket::connect(server.clone(), Some(2));
+    watched.expect(notifications::CONNECTED).await;
+    let presence = expect_presence(&mut watcher).await;
+    assert_eq!((presence.user_id, presence.online), (2, true));
+
+    // User went offline on another server.
+    let offline = PresenceChanged {
+        presence: UserPresence {
+            user_id: 2,
+            online: false,
+            last_seen: Some(1),
+        },
+    };
+    let payload = Bytes::from(serde_json::to_string(&offline).unwrap());
+    bus.publish(PRESENCE_SUBJECT, payload.clone())
+        .await
+        .unwrap();
+    let presence = expect_presence(&mut watcher).await;
+    assert_eq!((presence.user_id, presence.online), (2, false));
+    assert_eq!(presence.last_seen, Some(1));
+
+    watcher.send_text(&presence_request(2, "unsubscribe_presence", &[2]));
+    watcher.next_json().await;
+    bus.publish(PRESENCE_SUBJECT, payload).await.unwrap();
+    watcher.expect_nothing().await;
+}
+
+#[actix_web::test]
+async fn presence_request_is_capped() {
+    let server = common::chat_server();
+    let mut socket = Socket::connect(server, Some(1));
+    socket.expect(notifications::CONNECTED).await;
+
+    let users: Vec<usize> = (1..=257).collect();
+    for (id, method) in [(1, "subscribe_presence"), (2, "get_presence")] {
+        socket.send_text(&presence_request(id, method, &users));
+        let response = socket.next_json().await;
+        assert_eq!(response["error"]["code"], json!(INVALID_PARAMS));
+
+        socket.send_text(&presence_request(id, method, &users[..256]));
+        let response = socket.next_json().await;
+        assert_eq!(response["result"]["users"].as_array().unwrap().len(), 256);
+    }
+}
@@ -0,0 +1,107 @@
+//! Resume of the session by a new socket of the same client.
+
+use actix::Addr;
+use hcwc_protocol::{
+    mq_messages::ClientMessage,
+    notifications::{self, ChatMessageParams},
+    requests::ResumeParams,
+    responses::ConnectResult,
+};
+use server::chat_server::{ChatServer, Deliver};
+
+mod common;
+
+use common::Socket;
+
+const USER: usize = 1;
+
+async fn deliver(server: &Addr<ChatServer>, message_id: u64) {
+    server
+        .send(Deliver {
+            message: ClientMessage {
+                id: 2,
+                session_id: 0,
+                server_uuid: "other".to_string(),
+                request_id: None,
+                msg: "hello".to_string(),
+                recipient: USER,
+                message_id: Some(message_id),
+                timestamp: None,
+                seq: Some(message_id),
+                client_message_id: None,
+            },
+        })
+        .await
+        .unwrap()
+        .unwrap();
+}
+
+async fn expect_message(socket: &mut Socket, message_id: u64) {
+    let message = socket.expect(notifications::MESSAGE).await;
+    assert!(message.event_seq().is_some());
+    let params: ChatMessageParams = serde_json::from_value(message.params.unwrap()).unwrap();
+    assert_eq!(params.id, Some(message_id));
+}
+
+#[actix_web::test]
+async fn resume_takes_over_attached_session() {
+    let server = common::chat_server();
+    let mut old = Socket::connect(server.clone(), Some(USER));
+    let connected = old.expect(notifications::CONNECTED).await;
+    let connected: ConnectResult = serde_json::from_value(connected.params.unwrap()).unwrap();
+    assert!(!connected.resume_failed);
+    deliver(&server, 1).await;
+    expect_message(&mut old, 1).await;
+
+    // Old socket is not disconnected yet.
+    let mut new = Socket::resume(
+        server.clone(),
+        USER,
+        ResumeParams {
+            token: connected.resume_token,
+            last_seq: 0,
+        },
+    );
+    let resumed = new.expect(notifications::CONNECTED).await;
+    let resumed: ConnectResult = serde_json::from_value(resumed.params.unwrap()).unwrap();
+    assert!(resumed.resumed);
+    assert_eq!(resumed.session_id, connected.session_id);
+    expect_message(&mut new, 1).await;
+
+    deliver(&server, 2).await;
+    expect_message(&mut new, 2).await;
+    old.expect_nothing().await;
+}
+
+#[actix_web::test]
+async fn failed_resume_starts_new_session() {
+    let server = common::chat_server();
+    let mut old = Socket::connect(server.clone(), Some(USER));
+    let connected = old.expect(notifications::CONNECTED).await;
+    let connected: ConnectResult = serde_json::from_value(connected.params.unwrap()).unwrap();
+    deliver(&server, 1).await;
+    expect_message(&mut old, 1).await;
+
+    // Client claims an event the session has never sent.
+    let mut new = Socket::resume(
+        server.clone(),
+        USER,
+        ResumeParams {
+            token: connected.resume_token.clone(),
+            last_seq: 5,
+        },
+    );
+    let started = new.expect(notifications::CONNECTED).await;
+    let started: ConnectResult = serde_json::from_value(started.params.unwrap()).unwrap();
+    assert!(!started.resumed);
+    assert!(started.resume_failed);
+    assert_ne!(started.session_id, connected.session_id);
+    assert_ne!(started.resume_token, connected.resume_token);
+    // Neither a second `connected` nor the replay of the old session.
+    new.expect_nothing().await;
+
+    // Old session keeps its socket.
+    deliver(&server, 2).await;
+    expect_message(&mut old, 2).await;
+    expect_message(&mut new, 2).await;
+}
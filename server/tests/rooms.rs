@@ -0,0 +1,149 @@
+//! Who can join, list and write to a room.
+
+use actix::Addr;
+use hcwc_protocol::{
+    mq_messages::RoomMessage,
+    responses::{JRPCError, NOT_ROOM_INVITED, NOT_ROOM_MEMBER, ROOM_NOT_FOUND},
+};
+use server::chat_server::{ChatServer, CreateRoom, InviteRoom, JoinRoom, LeaveRoom, RoomMembers};
+
+mod common;
+
+use common::chat_server;
+
+/// Room of user 1 with the given users invited.
+async fn create_room(server: &Addr<ChatServer>, invited: &[usize]) -> usize {
+    server
+        .send(CreateRoom {
+            user_id: 1,
+            members: invited.to_vec(),
+        })
+        .await
+        .unwrap()
+        .unwrap()
+        .room_id
+}
+
+async fn join(server: &Addr<ChatServer>, user_id: usize, room_id: usize) -> Result<(), JRPCError> {
+    server
+        .send(JoinRoom { user_id, room_id })
+        .await
+        .unwrap()
+        .map(|_| ())
+}
+
+async fn members(
+    server: &Addr<ChatServer>,
+    user_id: usize,
+    room_id: usize,
+) -> Result<Vec<usize>, JRPCError> {
+    server
+        .send(RoomMembers { user_id, room_id })
+        .await
+        .unwrap()
+        .map(|result| result.members)
+}
+
+#[actix_web::test]
+async fn listed_users_are_invited_not_added() {
+    let server = chat_server();
+    let room_id = create_room(&server, &[2]).await;
+    assert_eq!(members(&server, 1, room_id).await.unwrap(), vec![1]);
+
+    join(&server, 2, room_id).await.unwrap();
+    assert_eq!(members(&server, 2, room_id).await.unwrap(), vec![1, 2]);
+    // Joining again changes nothing.
+    join(&server, 2, room_id).await.unwrap();
+    assert_eq!(members(&server, 1, room_id).await.unwrap(), vec![1, 2]);
+}
+
+#[actix_web::test]
+async fn uninvited_user_cannot_join() {
+    let server = chat_server();
+    let room_id = create_room(&server, &[2]).await;
+
+    let err = join(&server, 3, room_id).await.unwrap_err();
+    assert_eq!(err.code, NOT_ROOM_INVITED);
+
+    let err = server
+        .send(InviteRoom {
+            user_id: 3,
+            room_id,
+            invited: 4,
+        })
+        .await
+        .unwrap()
+        .err()
+        .unwrap();
+    assert_eq!(err.code, NOT_ROOM_MEMBER);
+
+    // Member invites the user.
+    server
+        .send(InviteRoom {
+            user_id: 1,
+            room_id,
+            invited: 3,
+        })
+        .await
+        .unwrap()
+        .unwrap();
+    join(&server, 3, room_id).await.unwrap();
+    assert_eq!(members(&server, 3, room_id).await.unwrap(), vec![1, 3]);
+}
+
+#[actix_web::test]
+async fn non_member_is_rejected() {
+    let server = chat_server();
+    let room_id = create_room(&server, &[2]).await;
+
+    let err = members(&server, 2, room_id).await.unwrap_err();
+    assert_eq!(err.code, NOT_ROOM_MEMBER);
+
+    let err = server
+        .send(LeaveRoom {
+            user_id: 2,
+            room_id,
+        })
+        .await
+        .unwrap()
+        .err()
+        .unwrap();
+    assert_eq!(err.code, NOT_ROOM_MEMBER);
+
+    let err = server
+        .send(RoomMessage {
+            id: 2,
+            server_uuid: String::new(),
+            room_id,
+            msg: "hello".to_string(),
+            timestamp: None,
+        })
+        .await
+        .unwrap()
+        .err()
+        .unwrap();
+    assert_eq!(err.code, NOT_ROOM_MEMBER);
+}
+
+#[actix_web::test]
+async fn missing_room_is_not_found() {
+    let server = chat_server();
+
+    let err = join(&server, 1, 42).await.unwrap_err();
+    assert_eq!(err.code, ROOM_NOT_FOUND);
+    let err = members(&server, 1, 42).await.unwrap_err();
+    assert_eq!(err.code, ROOM_NOT_FOUND);
+
+    // Room is gone with its last member, its invites too.
+    let room_id = create_room(&server, &[2]).await;
+    server
+        .send(LeaveRoom {
+            user_id: 1,
+            room_id,
+        })
+        .await
+        .unwrap()
+        .unwrap();
+    let err = join(&server, 2, room_id).await.unwrap_err();
+    assert_eq!(err.code, ROOM_NOT_FOUND);
+}
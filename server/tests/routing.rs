@@ -0,0 +1,471 @@
+//! Server and worker routing in one process, with no broker and no redis.
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use actix::Addr;
+use async_trait::async_trait;
+use bytes::Bytes;
+use hcwc_bus::{BusError, BusResult, InProcessBus, MessageBus, Subscription};
+use hcwc_cache::{CacheDB, MemoryCache};
+use hcwc_protocol::{
+    mq_messages::{self, ClientMessage, DeliveryStatus, Receipt},
+    notifications::{self, ChatMessageParams, MessageStatusParams},
+    pending::PendingLimits,
+    requests::RequestId,
+    responses::ConnectResult,
+};
+use server::{
+    chat_server::{ChatServer, MarkRead},
+    subscriber,
+};
+use worker::history::{Cursor, HistoryStore, MemoryHistory};
+
+mod common;
+
+use common::{start_chat_server, Socket};
+
+const SERVER_UUID: &str = "node";
+const QUEUE_GROUP: &str = "group";
+
+/// Waits until the workers can find the user's server in the cache.
+async fn wait_routable(cache: &dyn CacheDB, user_id: usize) {
+    for _ in 0..100 {
+        if !cache.user_servers(user_id).await.unwrap().is_empty() {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    panic!("user {} is not registered", user_id);
+}
+
+/// Bus that fails publishes to the subject while `down` is set.
+struct FailingBus {
+    inner: InProcessBus,
+    subject: String,
+    down: AtomicBool,
+}
+
+#[async_trait]
+impl MessageBus for FailingBus {
+    async fn publish(&self, subject: &str, payload: Bytes) -> BusResult<()> {
+        if subject == self.subject && self.down.load(Ordering::SeqCst) {
+            return Err(BusError::Nats("subject is down".to_string()));
+        }
+        self.inner.publish(subject, payload).await
+    }
+
+    async fn queue_subscribe(&self, subject: &str, group: &str) -> BusResult<Subscription> {
+        self.inner.queue_subscribe(subject, group).await
+    }
+
+    async fn subscribe(&self, subject: &str) -> BusResult<Subscription> {
+        self.inner.subscribe(subject).await
+    }
+
+    async fn request(&self, subject: &str, payload: Bytes) -> BusResult<Bytes> {
+        self.inner.request(subject, payload).await
+    }
+}
+
+/// Chat server with its subscriber and a worker, all on the same bus and cache.
+async fn start(
+    bus: &Arc<dyn MessageBus>,
+    cache: &Arc<dyn CacheDB>,
+    history: &Arc<dyn HistoryStore>,
+) -> Addr<ChatServer> {
+    let server = start_chat_server(cache.clone(), bus.clone(), SERVER_UUID);
+    tokio::spawn(
+        subscriber::subscriber(
+            server.clone(),
+            bus.clone(),
+            SERVER_UUID.to_string(),
+            QUEUE_GROUP.to_string(),
+        )
+        .await
+        .unwrap(),
+    );
+    tokio::spawn(worker::run(
+        bus.clone(),
+        cache.clone(),
+        history.clone(),
+        PendingLimits::default(),
+        QUEUE_GROUP.to_string(),
+    ));
+    server
+}
+
+/// Message from user 1 to user 2.
+fn message(text: &str, client_message_id: Option<&str>) -> ClientMessage {
+    ClientMessage {
+        id: 1,
+        session_id: 0,
+        server_uuid: String::new(),
+        request_id: None,
+        msg: text.to_string(),
+        recipient: 2,
+        message_id: None,
+        timestamp: None,
+        seq: None,
+        client_message_id: client_message_id.map(str::to_string),
+    }
+}
+
+#[actix_web::test]
+async fn message_is_routed_to_recipient() {
+    let bus: Arc<dyn MessageBus> = Arc::new(InProcessBus::new());
+    let cache: Arc<dyn CacheDB> = Arc::new(MemoryCache::new());
+    let history: Arc<dyn HistoryStore> = Arc::new(MemoryHistory::new());
+    let server = start(&bus, &cache, &history).await;
+
+    let mut sender = Socket::connect(server.clone(), Some(1));
+    let mut recipient = Socket::connect(server.clone(), Some(2));
+    let connected = sender.expect(notifications::CONNECTED).await;
+    let connected: ConnectResult = serde_json::from_value(connected.params.unwrap()).unwrap();
+    recipient.expect(notifications::CONNECTED).await;
+    wait_routable(cache.as_ref(), 2).await;
+
+    let result = server
+        .send(ClientMessage {
+            session_id: connected.session_id,
+            request_id: Some(RequestId::Number(10)),
+            ..message("hello", None)
+        })
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(result.status, DeliveryStatus::Accepted);
+    assert_eq!(result.seq, 1);
+
+    let message = recipient.expect(notifications::MESSAGE).await;
+    let message: ChatMessageParams = serde_json::from_value(message.params.unwrap()).unwrap();
+    assert_eq!(message.sender, 1);
+    assert_eq!(message.message, "hello");
+    assert_eq!(message.seq, Some(1));
+    let message_id = message.id.expect("message is not stored in the history");
+
+    // Worker routed the message, then the recipient's session confirmed it.
+    let status = sender.expect(notifications::MESSAGE_STATUS).await;
+    let status: MessageStatusParams = serde_json::from_value(status.params.unwrap()).unwrap();
+    assert_eq!(status.request_id, Some(RequestId::Number(10)));
+    assert_eq!(status.status, DeliveryStatus::Routed);
+    let status = sender.expect(notifications::MESSAGE_STATUS).await;
+    let status: MessageStatusParams = serde_json::from_value(status.params.unwrap()).unwrap();
+    assert_eq!(status.message_id, Some(message_id));
+    assert_eq!(status.status, DeliveryStatus::Delivered);
+
+    let stored = history
+        .conversation(
+            2,
+            1,
+            Cursor {
+                before: None,
+                after: None,
+                limit: 10,
+            },
+        )
+        .unwrap();
+    assert_eq!(stored.len(), 1);
+    assert_eq!(stored[0].id, message_id);
+}
+
+#[actix_web::test]
+async fn retry_keeps_sequence_number() {
+    let bus: Arc<dyn MessageBus> = Arc::new(InProcessBus::new());
+    let cache: Arc<dyn CacheDB> = Arc::new(MemoryCache::new());
+    let history: Arc<dyn HistoryStore> = Arc::new(MemoryHistory::new());
+    let server = start(&bus, &cache, &history).await;
+    let mut recipient = Socket::connect(server.clone(), Some(2));
+    recipient.expect(notifications::CONNECTED).await;
+    wait_routable(cache.as_ref(), 2).await;
+
+    for (text, client_message_id, seq) in [("first", "a", 1), ("first", "a", 1), ("second", "b", 2)]
+    {
+        let result = server
+            .send(message(text, Some(client_message_id)))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(result.seq, seq);
+    }
+
+    for seq in [1, 2] {
+        let message = recipient.expect(notifications::MESSAGE).await;
+        let message: ChatMessageParams = serde_json::from_value(message.params.unwrap()).unwrap();
+        assert_eq!(message.seq, Some(seq));
+    }
+    recipient.expect_nothing().await;
+}
+
+#[actix_web::test]
+async fn malformed_message_is_skipped() {
+    let bus: Arc<dyn MessageBus> = Arc::new(InProcessBus::new());
+    let cache: Arc<dyn CacheDB> = Arc::new(MemoryCache::new());
+    let server = start_chat_server(cache.clone(), bus.clone(), SERVER_UUID);
+    tokio::spawn(
+        subscriber::subscriber(
+            server.clone(),
+            bus.clone(),
+            SERVER_UUID.to_string(),
+            QUEUE_GROUP.to_string(),
+        )
+        .await
+        .unwrap(),
+    );
+    let mut recipient = Socket::connect(server.clone(), Some(2));
+    recipient.expect(notifications::CONNECTED).await;
+    wait_routable(cache.as_ref(), 2).await;
+
+    let subject = mq_messages::send_subject(SERVER_UUID);
+    bus.publish(&subject, Bytes::from("not json"))
+        .await
+        .unwrap();
+    let message = ClientMessage {
+        id: 1,
+        session_id: 0,
+        server_uuid: "other".to_string(),
+        request_id: None,
+        msg: "hello".to_string(),
+        recipient: 2,
+        message_id: Some(3),
+        timestamp: None,
+        seq: Some(1),
+        client_message_id: None,
+    };
+    bus.publish(
+        &subject,
+        Bytes::from(serde_json::to_string(&message).unwrap()),
+    )
+    .await
+    .unwrap();
+
+    let message = recipient.expect(notifications::MESSAGE).await;
+    let message: ChatMessageParams = serde_json::from_value(message.params.unwrap()).unwrap();
+    assert_eq!(message.id, Some(3));
+}
+
+#[actix_web::test]
+async fn dead_letter_of_routing_is_replayed_once() {
+    let failing = Arc::new(FailingBus {
+        inner: InProcessBus::new(),
+        subject: mq_messages::send_subject(SERVER_UUID),
+        down: AtomicBool::new(true),
+    });
+    let bus: Arc<dyn MessageBus> = failing.clone();
+    let cache: Arc<dyn CacheDB> = Arc::new(MemoryCache::new());
+    let history: Arc<dyn HistoryStore> = Arc::new(MemoryHistory::new());
+    let server = start(&bus, &cache, &history).await;
+    let mut recipient = Socket::connect(server.clone(), Some(2));
+    recipient.expect(notifications::CONNECTED).await;
+    wait_routable(cache.as_ref(), 2).await;
+
+    server
+        .send(message("hello", Some("a")))
+        .await
+        .unwrap()
+        .unwrap();
+
+    // Routing is retried with backoff before the message is dead.
+    let mut letters = Vec::new();
+    for _ in 0..100 {
+        letters = cache.dead_letters().await.unwrap();
+        if !letters.is_empty() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    assert_eq!(letters.len(), 1);
+    let dead: ClientMessage = serde_json::from_str(&letters[0].payload).unwrap();
+    let message_id = dead
+        .message_id
+        .expect("dead letter is not the stored message");
+    recipient.expect_nothing().await;
+
+    failing.down.store(false, Ordering::SeqCst);
+    let letter = worker::replay_dead_letter(cache.as_ref(), bus.as_ref(), letters[0].id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(letter.subject, mq_messages::PUBLISH_SUBJECT);
+    assert!(cache.dead_letters().await.unwrap().is_empty());
+
+    let message = recipient.expect(notifications::MESSAGE).await;
+    let message: ChatMessageParams = serde_json::from_value(message.params.unwrap()).unwrap();
+    assert_eq!(message.id, Some(message_id));
+    assert_eq!(message.seq, Some(1));
+    recipient.expect_nothing().await;
+
+    let stored = history
+        .conversation(
+            2,
+            1,
+            Cursor {
+                before: None,
+                after: None,
+                limit: 10,
+            },
+        )
+        .unwrap();
+    assert_eq!(stored.len(), 1);
+}
+
+/// Statuses the sender is told about until nothing more comes.
+async fn statuses(sender: &mut Socket) -> Vec<DeliveryStatus> {
+    let mut statuses = Vec::new();
+    while let Some(notification) = sender.next(Duration::from_millis(300)).await {
+        if notification.method == notifications::MESSAGE_STATUS {
+            let status: MessageStatusParams =
+                serde_json::from_value(notification.params.unwrap()).unwrap();
+            statuses.push(status.status);
+        }
+    }
+    statuses
+}
+
+#[actix_web::test]
+async fn sender_gets_every_receipt_once() {
+    let bus: Arc<dyn MessageBus> = Arc::new(InProcessBus::new());
+    let cache: Arc<dyn CacheDB> = Arc::new(MemoryCache::new());
+    let history: Arc<dyn HistoryStore> = Arc::new(MemoryHistory::new());
+    let server = start(&bus, &cache, &history).await;
+
+    let mut sender = Socket::connect(server.clone(), Some(1));
+    let mut phone = Socket::connect(server.clone(), Some(2));
+    let mut laptop = Socket::connect(server.clone(), Some(2));
+    let connected = sender.expect(notifications::CONNECTED).await;
+    let connected: ConnectResult = serde_json::from_value(connected.params.unwrap()).unwrap();
+    phone.expect(notifications::CONNECTED).await;
+    laptop.expect(notifications::CONNECTED).await;
+    wait_routable(cache.as_ref(), 2).await;
+
+    server
+        .send(ClientMessage {
+            session_id: connected.session_id,
+            ..message("hello", None)
+        })
+        .await
+        .unwrap()
+        .unwrap();
+    let message = phone.expect(notifications::MESSAGE).await;
+    laptop.expect(notifications::MESSAGE).await;
+    let message: ChatMessageParams = serde_json::from_value(message.params.unwrap()).unwrap();
+    let message_id = message.id.unwrap();
+
+    // Both devices wrote the message.
+    assert_eq!(
+        statuses(&mut sender).await,
+        vec![DeliveryStatus::Routed, DeliveryStatus::Delivered]
+    );
+
+    server
+        .send(MarkRead {
+            user_id: 2,
+            message_id,
+        })
+        .await
+        .unwrap()
+        .unwrap();
+    server.do_send(Receipt {
+        message_id,
+        recipient: 2,
+        status: DeliveryStatus::Delivered,
+    });
+    // Late `Delivered` doesn't take `Read` back.
+    assert_eq!(statuses(&mut sender).await, vec![DeliveryStatus::Read]);
+}
+
+#[actix_web::test]
+async fn users_of_reaped_server_get_pending_messages() {
+    let bus: Arc<dyn MessageBus> = Arc::new(InProcessBus::new());
+    let cache: Arc<dyn CacheDB> = Arc::new(MemoryCache::new());
+    let history: Arc<dyn HistoryStore> = Arc::new(MemoryHistory::new());
+    let server = start(&bus, &cache, &history).await;
+
+    // Server of user 2 crashed, its liveness key has expired.
+    cache.add_server("crashed").await.unwrap();
+    cache
+        .refresh_server("crashed", Duration::from_millis(10))
+        .await
+        .unwrap();
+    cache
+        .add_new_user(2, "crashed", Duration::from_secs(60))
+        .await
+        .unwrap();
+    cache
+        .refresh_server(SERVER_UUID, Duration::from_secs(60))
+        .await
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    let reaped = worker::reap_stale_servers(cache.as_ref(), bus.as_ref())
+        .await
+        .unwrap();
+    assert_eq!(reaped, vec!["crashed".to_string()]);
+    assert!(cache.user_servers(2).await.unwrap().is_empty());
+
+    let mut sender = Socket::connect(server.clone(), Some(1));
+    let connected = sender.expect(notifications::CONNECTED).await;
+    let connected: ConnectResult = serde_json::from_value(connected.params.unwrap()).unwrap();
+    server
+        .send(ClientMessage {
+            session_id: connected.session_id,
+            ..message("hello", None)
+        })
+        .await
+        .unwrap()
+        .unwrap();
+    let status = sender.expect(notifications::MESSAGE_STATUS).await;
+    let status: MessageStatusParams = serde_json::from_value(status.params.unwrap()).unwrap();
+    assert_eq!(status.status, DeliveryStatus::Queued);
+
+    let pending = cache.take_pending_messages(2).await.unwrap();
+    assert_eq!(pending.len(), 1);
+    assert_eq!(pending[0].msg, "hello");
+}
+
+#[actix_web::test]
+async fn typing_is_never_stored_queued_or_acknowledged() {
+    let bus: Arc<dyn MessageBus> = Arc::new(InProcessBus::new());
+    let cache: Arc<dyn CacheDB> = Arc::new(MemoryCache::new());
+    let history: Arc<dyn HistoryStore> = Arc::new(MemoryHistory::new());
+    let server = start(&bus, &cache, &history).await;
+
+    let mut sender = Socket::connect(server.clone(), Some(1));
+    let mut recipient = Socket::connect(server.clone(), Some(2));
+    sender.expect(notifications::CONNECTED).await;
+    recipient.expect(notifications::CONNECTED).await;
+    wait_routable(cache.as_ref(), 2).await;
+
+    // User 2 is online, user 3 is not.
+    for (id, recipient) in [(1, 2), (2, 3)] {
+        sender.send_text(
+            &serde_json::json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "method": "typing_start",
+                "params": { "recipient": recipient },
+            })
+            .to_string(),
+        );
+        let response = sender.next_json().await;
+        assert_eq!(response["result"]["typing"], serde_json::json!(true));
+    }
+    let typing = recipient.expect(notifications::TYPING).await;
+    assert_eq!(typing.event_seq(), None);
+
+    sender.expect_nothing().await;
+    assert!(cache.take_pending_messages(3).await.unwrap().is_empty());
+    for peer in [2, 3] {
+        let cursor = Cursor {
+            before: None,
+            after: None,
+            limit: 10,
+        };
+        assert!(history.conversation(1, peer, cursor).unwrap().is_empty());
+    }
+}
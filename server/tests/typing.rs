@@ -0,0 +1,114 @@
+//! Typing indicators shown to the recipient, they expire on their own.
+
+use std::time::Duration;
+
+use actix::Addr;
+use hcwc_protocol::{
+    mq_messages::{ClientMessage, TypingEvent},
+    notifications::{self, TypingParams},
+};
+use server::{
+    chat_server::{ChatServer, Deliver},
+    chat_session::Heartbeat,
+};
+
+mod common;
+
+use common::Socket;
+
+/// Longer than the indicator lives without `typing_start`.
+const PAST_TTL: Duration = Duration::from_secs(7);
+
+/// Typing event from user 1 to user 2, as the worker routes it here.
+fn typing(server: &Addr<ChatServer>, typing: bool) {
+    server.do_send(TypingEvent {
+        sender: 1,
+        recipient: 2,
+        typing,
+    });
+}
+
+/// Session of user 2 that outlives the tests, the client never answers pings.
+async fn recipient(server: &Addr<ChatServer>) -> Socket {
+    let heartbeat = Heartbeat {
+        interval: Duration::from_secs(5),
+        client_timeout: Duration::from_secs(60),
+    };
+    let mut socket = Socket::with_heartbeat(server.clone(), 2, heartbeat);
+    socket.expect(notifications::CONNECTED).await;
+    socket
+}
+
+async fn expect_typing(socket: &mut Socket, timeout: Duration) -> bool {
+    let notification = socket.next(timeout).await.expect("no typing notification");
+    assert_eq!(notification.method, notifications::TYPING);
+    let params: TypingParams = serde_json::from_value(notification.params.unwrap()).unwrap();
+    assert_eq!(params.sender, 1);
+    params.typing
+}
+
+#[actix_web::test]
+async fn indicator_expires_without_typing_start() {
+    tokio::time::pause();
+    let server = common::chat_server();
+    let mut recipient = recipient(&server).await;
+
+    typing(&server, true);
+    assert!(expect_typing(&mut recipient, common::TIMEOUT).await);
+
+    // Sender crashed, the indicator is stopped once it expires.
+    let started = tokio::time::Instant::now();
+    assert!(!expect_typing(&mut recipient, PAST_TTL).await);
+    assert!(started.elapsed() >= Duration::from_secs(6));
+}
+
+#[actix_web::test]
+async fn repeated_typing_start_keeps_indicator() {
+    tokio::time::pause();
+    let server = common::chat_server();
+    let mut recipient = recipient(&server).await;
+
+    typing(&server, true);
+    assert!(expect_typing(&mut recipient, common::TIMEOUT).await);
+    tokio::time::sleep(Duration::from_secs(4)).await;
+    typing(&server, true);
+    assert!(expect_typing(&mut recipient, common::TIMEOUT).await);
+
+    // First indicator would have expired by now.
+    let started = tokio::time::Instant::now();
+    assert!(!expect_typing(&mut recipient, PAST_TTL).await);
+    assert!(started.elapsed() >= Duration::from_secs(6));
+}
+
+#[actix_web::test]
+async fn message_stops_indicator() {
+    tokio::time::pause();
+    let server = common::chat_server();
+    let mut recipient = recipient(&server).await;
+
+    typing(&server, true);
+    assert!(expect_typing(&mut recipient, common::TIMEOUT).await);
+    server
+        .send(Deliver {
+            message: ClientMessage {
+                id: 1,
+                session_id: 0,
+                server_uuid: "other".to_string(),
+                request_id: None,
+                msg: "hello".to_string(),
+                recipient: 2,
+                message_id: Some(1),
+                timestamp: None,
+                seq: Some(1),
+                client_message_id: None,
+            },
+        })
+        .await
+        .unwrap()
+        .unwrap();
+
+    assert!(!expect_typing(&mut recipient, common::TIMEOUT).await);
+    recipient.expect(notifications::MESSAGE).await;
+    // Indicator is not stopped again when it would have expired.
+    assert!(recipient.next(PAST_TTL).await.is_none());
+}
@@ -0,0 +1,98 @@
+//! Administration of the chat.
+//!
+//! ```text
+//! hcwc-admin deadletters            lists dead letters
+//! hcwc-admin replay <id>...         publishes dead letters again
+//! hcwc-admin replay-all             publishes all dead letters again
+//! ```
+//!
+//! Cache and bus are selected with the same settings as the worker.
+
+use std::process::ExitCode;
+
+use clap::{Parser, Subcommand};
+use hcwc_bus::MessageBus;
+use hcwc_cache::CacheDB;
+use hcwc_config::{CommonArgs, CommonConfig};
+
+/// Administration of the chat.
+#[derive(Parser, Debug)]
+#[command(name = "hcwc-admin")]
+struct Args {
+    #[command(flatten)]
+    common: CommonArgs,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Lists dead letters
+    Deadletters,
+    /// Publishes dead letters again
+    Replay {
+        #[arg(required = true)]
+        ids: Vec<u64>,
+    },
+    /// Publishes all dead letters again
+    ReplayAll,
+}
+
+async fn list_dead_letters(cache: &dyn CacheDB) -> Result<(), String> {
+    let letters = cache.dead_letters().await.map_err(|err| err.to_string())?;
+    if letters.is_empty() {
+        println!("No dead letters");
+    }
+    for letter in letters {
+        println!(
+            "{}\t{}\t{} attempts\t{}\n\t{}",
+            letter.id, letter.subject, letter.attempts, letter.reason, letter.payload
+        );
+    }
+    Ok(())
+}
+
+async fn replay(cache: &dyn CacheDB, bus: &dyn MessageBus, id: u64) -> Result<(), String> {
+    let letter = worker::replay_dead_letter(cache, bus, id)
+        .await
+        .map_err(|err| format!("Cannot replay dead letter {}: {}", id, err))?
+        .ok_or_else(|| format!("No dead letter {}", id))?;
+    println!("Dead letter {} is replayed to {}", id, letter.subject);
+    Ok(())
+}
+
+async fn run(args: Args) -> Result<(), String> {
+    let file = args.common.read_file().map_err(|err| err.to_string())?;
+    let config = CommonConfig::resolve(&args.common, &file).map_err(|err| err.to_string())?;
+    let cache = hcwc_cache::connect(&config.cache)
+        .await
+        .map_err(|err| format!("Cannot connect to the cache: {}", err))?;
+
+    let ids = match args.command {
+        Command::Deadletters => return list_dead_letters(cache.as_ref()).await,
+        Command::ReplayAll => {
+            let letters = cache.dead_letters().await.map_err(|err| err.to_string())?;
+            letters.iter().map(|letter| letter.id).collect()
+        }
+        Command::Replay { ids } => ids,
+    };
+
+    let bus = hcwc_bus::connect(&config.bus)
+        .await
+        .map_err(|err| format!("Cannot connect to the message bus: {}", err))?;
+    for id in ids {
+        replay(cache.as_ref(), bus.as_ref(), id).await?;
+    }
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    match run(Args::parse()).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("{}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,60 @@
+//! Settings of the worker, see `hcwc_config` for where they come from.
+
+use std::path::PathBuf;
+
+use clap::Parser;
+use hcwc_config::{
+    parse, pick, require_non_empty, CommonArgs, CommonConfig, ConfigFile, ConfigResult,
+};
+
+use crate::history::{HistoryBackend, HistoryConfig};
+
+/// Worker storing the history and routing the messages between the servers.
+#[derive(Parser, Debug)]
+#[command(name = "worker")]
+pub struct Args {
+    #[command(flatten)]
+    pub common: CommonArgs,
+    /// History storage: sqlite or memory
+    #[arg(long, env = "HCWC_HISTORY_BACKEND")]
+    pub history_backend: Option<String>,
+    /// Path of the SQLite database with the history
+    #[arg(long, env = "HCWC_HISTORY_PATH")]
+    pub history_path: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct WorkerConfig {
+    pub common: CommonConfig,
+    pub history: HistoryConfig,
+}
+
+impl WorkerConfig {
+    /// Settings from the command line, the environment and the config file.
+    pub fn load() -> ConfigResult<Self> {
+        let args = Args::parse();
+        let file = args.common.read_file()?;
+        Self::resolve(&args, &file)
+    }
+
+    pub fn resolve(args: &Args, file: &ConfigFile) -> ConfigResult<Self> {
+        let common = CommonConfig::resolve(&args.common, file)?;
+        let defaults = HistoryConfig::default();
+
+        let history = HistoryConfig {
+            backend: parse(
+                "history.backend",
+                pick(&args.history_backend, &file.history.backend),
+                defaults.backend,
+            )?,
+            path: pick(&args.history_path, &file.history.path)
+                .map(PathBuf::from)
+                .unwrap_or(defaults.path),
+        };
+        if history.backend == HistoryBackend::Sqlite {
+            require_non_empty("history.path", &history.path.to_string_lossy())?;
+        }
+
+        Ok(Self { common, history })
+    }
+}
@@ -0,0 +1,53 @@
+use std::fmt;
+
+use hcwc_bus::BusError;
+use hcwc_cache::CacheError;
+
+use crate::history::HistoryError;
+
+/// Failure of a step of the worker.
+#[derive(Debug)]
+pub enum WorkerError {
+    Cache(CacheError),
+    Bus(BusError),
+    History(HistoryError),
+    /// Message on the bus is not what the subject carries.
+    Payload(serde_json::Error),
+}
+
+impl fmt::Display for WorkerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WorkerError::Cache(err) => write!(f, "Cache error: {}", err),
+            WorkerError::Bus(err) => write!(f, "Bus error: {}", err),
+            WorkerError::History(err) => write!(f, "History error: {}", err),
+            WorkerError::Payload(err) => write!(f, "Malformed payload: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for WorkerError {}
+
+impl From<CacheError> for WorkerError {
+    fn from(err: CacheError) -> Self {
+        WorkerError::Cache(err)
+    }
+}
+
+impl From<BusError> for WorkerError {
+    fn from(err: BusError) -> Self {
+        WorkerError::Bus(err)
+    }
+}
+
+impl From<HistoryError> for WorkerError {
+    fn from(err: HistoryError) -> Self {
+        WorkerError::History(err)
+    }
+}
+
+impl From<serde_json::Error> for WorkerError {
+    fn from(err: serde_json::Error) -> Self {
+        WorkerError::Payload(err)
+    }
+}
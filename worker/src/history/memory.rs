@@ -0,0 +1,121 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use hcwc_protocol::{keys, mq_messages::SequenceRange, responses::HistoryMessage};
+
+use super::{now, Cursor, HistoryError, HistoryStore};
+
+#[derive(Default)]
+struct State {
+    next_id: u64,
+    /// Messages of every conversation ordered by id
+    conversations: HashMap<String, Vec<HistoryMessage>>,
+}
+
+/// History kept in memory of the worker, for tests and single node deployments.
+#[derive(Default)]
+pub struct MemoryHistory {
+    state: Mutex<State>,
+}
+
+impl MemoryHistory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl HistoryStore for MemoryHistory {
+    fn store(
+        &self,
+        sender: usize,
+        recipient: usize,
+        message: &str,
+        seq: Option<u64>,
+    ) -> Result<HistoryMessage, HistoryError> {
+        let mut state = self.state.lock().map_err(|_| HistoryError::Poisoned)?;
+        state.next_id += 1;
+        let stored = HistoryMessage {
+            id: state.next_id,
+            sender,
+            recipient,
+            message: message.to_string(),
+            timestamp: now(),
+            seq,
+        };
+        state
+            .conversations
+            .entry(keys::conversation_id(sender, recipient))
+            .or_default()
+            .push(stored.clone());
+        Ok(stored)
+    }
+
+    fn message(&self, id: u64) -> Result<Option<HistoryMessage>, HistoryError> {
+        let state = self.state.lock().map_err(|_| HistoryError::Poisoned)?;
+        Ok(state.conversations.values().find_map(|messages| {
+            messages
+                .binary_search_by_key(&id, |message| message.id)
+                .ok()
+                .map(|index| messages[index].clone())
+        }))
+    }
+
+    fn conversation(
+        &self,
+        user_id: usize,
+        peer: usize,
+        cursor: Cursor,
+    ) -> Result<Vec<HistoryMessage>, HistoryError> {
+        let state = self.state.lock().map_err(|_| HistoryError::Poisoned)?;
+        let Some(messages) = state
+            .conversations
+            .get(&keys::conversation_id(user_id, peer))
+        else {
+            return Ok(vec![]);
+        };
+
+        let in_range = messages.iter().filter(|message| {
+            cursor.after.is_none_or(|after| message.id > after)
+                && cursor.before.is_none_or(|before| message.id < before)
+        });
+
+        let page: Vec<HistoryMessage> = if cursor.after.is_some() {
+            in_range.take(cursor.limit).cloned().collect()
+        } else {
+            let mut page: Vec<HistoryMessage> =
+                in_range.rev().take(cursor.limit).cloned().collect();
+            page.reverse();
+            page
+        };
+        Ok(page)
+    }
+
+    fn sequence(
+        &self,
+        sender: usize,
+        recipient: usize,
+        range: SequenceRange,
+        limit: usize,
+    ) -> Result<Vec<HistoryMessage>, HistoryError> {
+        let state = self.state.lock().map_err(|_| HistoryError::Poisoned)?;
+        let Some(messages) = state
+            .conversations
+            .get(&keys::conversation_id(sender, recipient))
+        else {
+            return Ok(vec![]);
+        };
+
+        let mut page: Vec<HistoryMessage> = messages
+            .iter()
+            .filter(|message| {
+                message.sender == sender
+                    && message
+                        .seq
+                        .is_some_and(|seq| range.from <= seq && seq <= range.to)
+            })
+            .cloned()
+            .collect();
+        page.sort_by_key(|message| message.seq);
+        page.truncate(limit);
+        Ok(page)
+    }
+}
@@ -0,0 +1,152 @@
+use std::{
+    fmt,
+    path::PathBuf,
+    str::FromStr,
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use hcwc_protocol::{mq_messages::SequenceRange, responses::HistoryMessage};
+
+mod memory;
+mod sqlite;
+
+pub use memory::MemoryHistory;
+pub use sqlite::SqliteHistory;
+
+/// Storage of the history.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HistoryBackend {
+    Sqlite,
+    Memory,
+}
+
+impl FromStr for HistoryBackend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sqlite" => Ok(HistoryBackend::Sqlite),
+            "memory" => Ok(HistoryBackend::Memory),
+            _ => Err(format!(
+                "unknown history backend `{}`, expected sqlite or memory",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct HistoryConfig {
+    pub backend: HistoryBackend,
+    /// Path of the SQLite database, used by `sqlite` backend
+    pub path: PathBuf,
+}
+
+impl Default for HistoryConfig {
+    fn default() -> Self {
+        Self {
+            backend: HistoryBackend::Sqlite,
+            path: PathBuf::from("hcwc_history.db"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum HistoryError {
+    Sqlite(rusqlite::Error),
+    /// Another thread panicked while using the store.
+    Poisoned,
+}
+
+impl fmt::Display for HistoryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HistoryError::Sqlite(err) => write!(f, "SQLite error: {}", err),
+            HistoryError::Poisoned => write!(f, "History store is poisoned"),
+        }
+    }
+}
+
+impl From<rusqlite::Error> for HistoryError {
+    fn from(err: rusqlite::Error) -> Self {
+        HistoryError::Sqlite(err)
+    }
+}
+
+/// Part of the conversation to read.
+///
+/// With `after` the page starts right after it and goes forward,
+/// otherwise the page ends right before `before` (or at the newest message).
+#[derive(Debug, Clone, Copy)]
+pub struct Cursor {
+    pub before: Option<u64>,
+    pub after: Option<u64>,
+    pub limit: usize,
+}
+
+/// Storage of all messages that went through the worker.
+pub trait HistoryStore: Send + Sync {
+    /// Stores the message, assigns id and timestamp to it.
+    fn store(
+        &self,
+        sender: usize,
+        recipient: usize,
+        message: &str,
+        seq: Option<u64>,
+    ) -> Result<HistoryMessage, HistoryError>;
+
+    /// Message with the id, if there is one.
+    fn message(&self, id: u64) -> Result<Option<HistoryMessage>, HistoryError>;
+
+    /// Messages of the conversation between two users, oldest first.
+    ///
+    /// Returns up to `cursor.limit` messages.
+    fn conversation(
+        &self,
+        user_id: usize,
+        peer: usize,
+        cursor: Cursor,
+    ) -> Result<Vec<HistoryMessage>, HistoryError>;
+
+    /// Messages from `sender` to `recipient` with sequence numbers
+    /// in the range, ordered by sequence number.
+    ///
+    /// Returns up to `limit` messages.
+    fn sequence(
+        &self,
+        sender: usize,
+        recipient: usize,
+        range: SequenceRange,
+        limit: usize,
+    ) -> Result<Vec<HistoryMessage>, HistoryError>;
+}
+
+/// Builds store selected in the config.
+pub fn open(config: &HistoryConfig) -> Result<Box<dyn HistoryStore>, HistoryError> {
+    match config.backend {
+        HistoryBackend::Sqlite => Ok(Box::new(SqliteHistory::open(&config.path)?)),
+        HistoryBackend::Memory => Ok(Box::new(MemoryHistory::new())),
+    }
+}
+
+/// Runs the call on the blocking pool, SQLite calls
+/// must not stall the threads of the runtime.
+pub async fn blocking<T, F>(history: &Arc<dyn HistoryStore>, call: F) -> Result<T, HistoryError>
+where
+    T: Send + 'static,
+    F: FnOnce(&dyn HistoryStore) -> Result<T, HistoryError> + Send + 'static,
+{
+    let history = history.clone();
+    tokio::task::spawn_blocking(move || call(history.as_ref()))
+        .await
+        .unwrap_or(Err(HistoryError::Poisoned))
+}
+
+/// Unix timestamp in milliseconds.
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis() as u64)
+        .unwrap_or_default()
+}
@@ -0,0 +1,173 @@
+use std::{path::Path, sync::Mutex};
+
+use rusqlite::{params, Connection, OptionalExtension, Row};
+
+use hcwc_protocol::{keys, mq_messages::SequenceRange, responses::HistoryMessage};
+
+use super::{now, Cursor, HistoryError, HistoryStore};
+
+/// History in the embedded SQLite database.
+pub struct SqliteHistory {
+    connection: Mutex<Connection>,
+}
+
+impl SqliteHistory {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, HistoryError> {
+        let connection = Connection::open(path)?;
+        connection.execute_batch(
+            "CREATE TABLE IF NOT EXISTS messages (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                conversation TEXT NOT NULL,
+                sender INTEGER NOT NULL,
+                recipient INTEGER NOT NULL,
+                message TEXT NOT NULL,
+                timestamp INTEGER NOT NULL,
+                seq INTEGER
+            );
+            CREATE INDEX IF NOT EXISTS messages_conversation
+                ON messages (conversation, id);",
+        )?;
+
+        // Databases created before the sequence numbers lack the column.
+        let has_seq = connection.query_row(
+            "SELECT COUNT(*) FROM pragma_table_info('messages') WHERE name = 'seq'",
+            [],
+            |row| row.get::<_, i64>(0),
+        )? > 0;
+        if !has_seq {
+            connection.execute_batch("ALTER TABLE messages ADD COLUMN seq INTEGER;")?;
+        }
+        connection.execute_batch(
+            "CREATE INDEX IF NOT EXISTS messages_sequence
+                ON messages (sender, recipient, seq);",
+        )?;
+
+        Ok(Self {
+            connection: Mutex::new(connection),
+        })
+    }
+}
+
+fn history_message(row: &Row<'_>) -> rusqlite::Result<HistoryMessage> {
+    Ok(HistoryMessage {
+        id: row.get::<_, i64>(0)? as u64,
+        sender: row.get::<_, i64>(1)? as usize,
+        recipient: row.get::<_, i64>(2)? as usize,
+        message: row.get(3)?,
+        timestamp: row.get::<_, i64>(4)? as u64,
+        seq: row.get::<_, Option<i64>>(5)?.map(|seq| seq as u64),
+    })
+}
+
+impl HistoryStore for SqliteHistory {
+    fn store(
+        &self,
+        sender: usize,
+        recipient: usize,
+        message: &str,
+        seq: Option<u64>,
+    ) -> Result<HistoryMessage, HistoryError> {
+        let connection = self.connection.lock().map_err(|_| HistoryError::Poisoned)?;
+        let timestamp = now();
+        connection.execute(
+            "INSERT INTO messages (conversation, sender, recipient, message, timestamp, seq)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                keys::conversation_id(sender, recipient),
+                sender as i64,
+                recipient as i64,
+                message,
+                timestamp as i64,
+                seq.map(|seq| seq as i64),
+            ],
+        )?;
+
+        Ok(HistoryMessage {
+            id: connection.last_insert_rowid() as u64,
+            sender,
+            recipient,
+            message: message.to_string(),
+            timestamp,
+            seq,
+        })
+    }
+
+    fn message(&self, id: u64) -> Result<Option<HistoryMessage>, HistoryError> {
+        let connection = self.connection.lock().map_err(|_| HistoryError::Poisoned)?;
+        let message = connection
+            .query_row(
+                "SELECT id, sender, recipient, message, timestamp, seq FROM messages WHERE id = ?1",
+                params![id as i64],
+                history_message,
+            )
+            .optional()?;
+        Ok(message)
+    }
+
+    fn conversation(
+        &self,
+        user_id: usize,
+        peer: usize,
+        cursor: Cursor,
+    ) -> Result<Vec<HistoryMessage>, HistoryError> {
+        let connection = self.connection.lock().map_err(|_| HistoryError::Poisoned)?;
+        // Forward pages are read oldest first, backward pages newest first and reversed.
+        let order = if cursor.after.is_some() {
+            "ASC"
+        } else {
+            "DESC"
+        };
+        let mut statement = connection.prepare(&format!(
+            "SELECT id, sender, recipient, message, timestamp, seq FROM messages
+                WHERE conversation = ?1 AND id > ?2 AND id < ?3
+                ORDER BY id {} LIMIT ?4",
+            order
+        ))?;
+
+        let mut messages = statement
+            .query_map(
+                params![
+                    keys::conversation_id(user_id, peer),
+                    cursor.after.unwrap_or(0) as i64,
+                    cursor.before.map_or(i64::MAX, |before| before as i64),
+                    cursor.limit as i64,
+                ],
+                history_message,
+            )?
+            .collect::<rusqlite::Result<Vec<HistoryMessage>>>()?;
+
+        if cursor.after.is_none() {
+            messages.reverse();
+        }
+        Ok(messages)
+    }
+
+    fn sequence(
+        &self,
+        sender: usize,
+        recipient: usize,
+        range: SequenceRange,
+        limit: usize,
+    ) -> Result<Vec<HistoryMessage>, HistoryError> {
+        let connection = self.connection.lock().map_err(|_| HistoryError::Poisoned)?;
+        let mut statement = connection.prepare(
+            "SELECT id, sender, recipient, message, timestamp, seq FROM messages
+                WHERE sender = ?1 AND recipient = ?2 AND seq >= ?3 AND seq <= ?4
+                ORDER BY seq LIMIT ?5",
+        )?;
+
+        let messages = statement
+            .query_map(
+                params![
+                    sender as i64,
+                    recipient as i64,
+                    range.from as i64,
+                    range.to as i64,
+                    limit as i64,
+                ],
+                history_message,
+            )?
+            .collect::<rusqlite::Result<Vec<HistoryMessage>>>()?;
+        Ok(messages)
+    }
+}
@@ -0,0 +1,624 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use bytes::Bytes;
+use futures_util::stream::StreamExt;
+use hcwc_bus::{BusMessage, MessageBus, Subscription};
+use hcwc_cache::CacheDB;
+use hcwc_protocol::{
+    mq_messages::{
+        self, ClientMessage, DeadLetter, DeliveryStatus, HistoryQuery, HistoryReply, MessageStatus,
+        Receipt, RoomMessage, RoutedReceipt, RoutedRoomMessage, SentMessage, TypingEvent,
+        DEDUP_WINDOW,
+    },
+    pending::PendingLimits,
+    responses::{HistoryPage, JRPCError},
+};
+
+use error::WorkerError;
+use history::{Cursor, HistoryStore};
+use retry::{with_backoff, MAX_ATTEMPTS};
+
+pub mod config;
+pub mod error;
+pub mod history;
+mod retry;
+
+/// How often the worker looks for crashed servers.
+const REAP_INTERVAL: Duration = Duration::from_secs(15);
+
+async fn retireve_servers(
+    cache: &dyn CacheDB,
+    recipient: usize,
+) -> Result<Vec<String>, WorkerError> {
+    with_backoff(|| async { Ok(cache.user_servers(recipient).await?) }).await
+}
+
+/// Publishes the payload, retrying transient failures of the bus.
+async fn publish(bus: &dyn MessageBus, subject: &str, payload: Bytes) -> Result<(), WorkerError> {
+    with_backoff(|| async { Ok(bus.publish(subject, payload.clone()).await?) }).await
+}
+
+/// Reports status of the message to the server of its sender.
+async fn publish_status(bus: &dyn MessageBus, message: &ClientMessage, status: DeliveryStatus) {
+    let status = MessageStatus::new(message, status);
+    if let Err(err) = publish(
+        bus,
+        &mq_messages::status_subject(&message.server_uuid),
+        Bytes::from(serde_json::to_string(&status).unwrap()),
+    )
+    .await
+    {
+        println!("Cannot publish message status: {}", err);
+    }
+}
+
+/// Keeps the message the worker gave up on and publishes it
+/// to the dead letter subject, `hcwc-admin` replays it later.
+async fn dead_letter(
+    bus: &dyn MessageBus,
+    cache: &dyn CacheDB,
+    subject: &str,
+    payload: &[u8],
+    reason: WorkerError,
+) {
+    println!("Message from {} is dead: {}", subject, reason);
+    let mut letter = DeadLetter {
+        id: 0,
+        subject: subject.to_string(),
+        payload: String::from_utf8_lossy(payload).into_owned(),
+        reason: reason.to_string(),
+        attempts: MAX_ATTEMPTS,
+        failed_at: history::now(),
+    };
+    match cache.add_dead_letter(letter.clone()).await {
+        Ok(id) => letter.id = id,
+        Err(err) => println!("Cannot keep dead letter: {}", err),
+    }
+
+    if let Err(err) = publish(
+        bus,
+        mq_messages::DEADLETTER_SUBJECT,
+        Bytes::from(serde_json::to_string(&letter).unwrap()),
+    )
+    .await
+    {
+        println!("Cannot publish dead letter: {}", err);
+    }
+}
+
+/// Claims the client message id of the message, returns outcome
+/// of the earlier message with the same id if it is routed already.
+///
+/// Message the server claimed is still `Accepted`, the one that was
+/// redelivered before it is routed is routed again, the session drops
+/// the copy by its sequence number.
+async fn claim_message(
+    cache: &dyn CacheDB,
+    message: &ClientMessage,
+) -> Result<Option<SentMessage>, WorkerError> {
+    let Some(client_message_id) = &message.client_message_id else {
+        return Ok(None);
+    };
+    let sent = SentMessage {
+        message_id: None,
+        seq: message.seq,
+        status: DeliveryStatus::Accepted,
+    };
+    let earlier = with_backoff(|| async {
+        Ok(cache
+            .claim_sent_message(message.id, client_message_id, sent, DEDUP_WINDOW)
+            .await?)
+    })
+    .await?;
+    match earlier {
+        // Failed message is replayed from the dead letters.
+        Some(earlier) if earlier.status == DeliveryStatus::Failed => {
+            remember_status(cache, message, DeliveryStatus::Accepted).await;
+            Ok(None)
+        }
+        Some(earlier) if earlier.status == DeliveryStatus::Accepted => Ok(None),
+        earlier => Ok(earlier),
+    }
+}
+
+/// Remembers the outcome of the message for its retries.
+async fn remember_status(cache: &dyn CacheDB, message: &ClientMessage, status: DeliveryStatus) {
+    let Some(client_message_id) = &message.client_message_id else {
+        return;
+    };
+    let sent = SentMessage {
+        message_id: message.message_id,
+        seq: message.seq,
+        status,
+    };
+    if let Err(err) = cache
+        .update_sent_message(message.id, client_message_id, sent, DEDUP_WINDOW)
+        .await
+    {
+        println!("Cannot update sent message: {}", err);
+    }
+}
+
+/// Confirms that the message is processed, unacked message
+/// is delivered again by the transports that support it.
+async fn ack(msg: &BusMessage) {
+    if let Err(err) = msg.ack().await {
+        println!("Cannot ack message: {}", err);
+    }
+}
+
+/// Routes receipts of the recipients to the servers of the senders.
+async fn receipt_router(
+    bus: Arc<dyn MessageBus>,
+    cache: Arc<dyn CacheDB>,
+    history: Arc<dyn HistoryStore>,
+    mut qsub: Subscription,
+) {
+    while let Some(msg) = qsub.next().await {
+        if let Err(err) = route_receipt(bus.as_ref(), cache.as_ref(), &history, &msg.payload).await
+        {
+            dead_letter(
+                bus.as_ref(),
+                cache.as_ref(),
+                mq_messages::RECEIPT_SUBJECT,
+                &msg.payload,
+                err,
+            )
+            .await;
+        }
+        ack(&msg).await;
+    }
+}
+
+/// Sender is taken from the history, receipt for the message
+/// that was sent to another user is dropped, so is a duplicate one.
+async fn route_receipt(
+    bus: &dyn MessageBus,
+    cache: &dyn CacheDB,
+    history: &Arc<dyn HistoryStore>,
+    payload: &[u8],
+) -> Result<(), WorkerError> {
+    let receipt = serde_json::from_slice::<Receipt>(payload)?;
+    let message = with_backoff(|| async {
+        Ok(history::blocking(history, move |history| history.message(receipt.message_id)).await?)
+    })
+    .await?;
+    let message = match message {
+        Some(message) if message.recipient == receipt.recipient => message,
+        _ => return Ok(()),
+    };
+    // Every device of the recipient sends its own receipt, and a late
+    // `Delivered` can come after `Read`, the sender gets only what is new.
+    let recorded = with_backoff(|| async {
+        Ok(cache
+            .record_receipt(message.id, receipt.status, DEDUP_WINDOW)
+            .await?)
+    })
+    .await?;
+    if !recorded {
+        return Ok(());
+    }
+
+    let routed = RoutedReceipt {
+        message_id: message.id,
+        sender: message.sender,
+        recipient: message.recipient,
+        status: receipt.status,
+    };
+    let servers = retireve_servers(cache, message.sender).await?;
+    for server in servers {
+        publish(
+            bus,
+            &mq_messages::receipt_subject(&server),
+            Bytes::from(serde_json::to_string(&routed).unwrap()),
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+/// Resolves rooms to their members and publishes every room message
+/// once per server that holds a session of any member.
+async fn room_router(bus: Arc<dyn MessageBus>, cache: Arc<dyn CacheDB>, mut qsub: Subscription) {
+    while let Some(msg) = qsub.next().await {
+        if let Err(err) = route_room_message(bus.as_ref(), cache.as_ref(), &msg.payload).await {
+            dead_letter(
+                bus.as_ref(),
+                cache.as_ref(),
+                mq_messages::ROOM_PUBLISH_SUBJECT,
+                &msg.payload,
+                err,
+            )
+            .await;
+        }
+        ack(&msg).await;
+    }
+}
+
+/// Room messages are not kept in the history and not queued for offline members.
+async fn route_room_message(
+    bus: &dyn MessageBus,
+    cache: &dyn CacheDB,
+    payload: &[u8],
+) -> Result<(), WorkerError> {
+    let mut message = serde_json::from_slice::<RoomMessage>(payload)?;
+    message.timestamp = Some(history::now());
+
+    let members = with_backoff(|| async { Ok(cache.room_members(message.room_id).await?) }).await?;
+
+    // Sender doesn't get its own message back.
+    let members: Vec<usize> = members
+        .into_iter()
+        .filter(|member| *member != message.id)
+        .collect();
+    let servers = with_backoff(|| async { Ok(cache.users_servers(&members).await?) }).await?;
+
+    let mut members_by_server: HashMap<String, Vec<usize>> = HashMap::new();
+    for (member, servers) in members.iter().zip(servers) {
+        for server in servers {
+            members_by_server.entry(server).or_default().push(*member);
+        }
+    }
+
+    for (server, members) in members_by_server {
+        let routed = RoutedRoomMessage {
+            members,
+            message: message.clone(),
+        };
+        publish(
+            bus,
+            &mq_messages::room_send_subject(&server),
+            Bytes::from(serde_json::to_string(&routed).unwrap()),
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+/// Routes typing events to the servers of the recipients.
+///
+/// Events for offline recipients are dropped, typing events
+/// are never stored or queued.
+async fn typing_router(bus: Arc<dyn MessageBus>, cache: Arc<dyn CacheDB>, mut qsub: Subscription) {
+    while let Some(msg) = qsub.next().await {
+        // Late typing event is worthless, it is never delivered again.
+        ack(&msg).await;
+        let Ok(event) = serde_json::from_slice::<TypingEvent>(&msg.payload) else {
+            continue;
+        };
+        // Typing event is not retried either.
+        let servers = match cache.user_servers(event.recipient).await {
+            Ok(servers) => servers,
+            Err(err) => {
+                println!("Cannot route typing event: {}", err);
+                continue;
+            }
+        };
+        for server in servers {
+            if let Err(err) = bus
+                .publish(
+                    &mq_messages::typing_send_subject(&server),
+                    Bytes::from(serde_json::to_string(&event).unwrap()),
+                )
+                .await
+            {
+                println!("Cannot route typing event: {}", err);
+            }
+        }
+    }
+}
+
+/// Reads the page of the conversation, one extra message tells if there are more.
+fn history_page(history: &dyn HistoryStore, query: &HistoryQuery) -> HistoryReply {
+    if let Some(range) = query.sequence {
+        let mut messages = history
+            .sequence(query.peer, query.user_id, range, query.limit + 1)
+            .map_err(|err| JRPCError::history_unavailable().with_data(err.to_string()))?;
+        let has_more = messages.len() > query.limit;
+        messages.truncate(query.limit);
+        return Ok(HistoryPage { messages, has_more });
+    }
+
+    let cursor = Cursor {
+        before: query.before,
+        after: query.after,
+        limit: query.limit + 1,
+    };
+    let mut messages = history
+        .conversation(query.user_id, query.peer, cursor)
+        .map_err(|err| JRPCError::history_unavailable().with_data(err.to_string()))?;
+
+    let has_more = messages.len() > query.limit;
+    if has_more {
+        // Extra message is on the far side of the page.
+        if query.after.is_some() {
+            messages.pop();
+        } else {
+            messages.remove(0);
+        }
+    }
+
+    Ok(HistoryPage { messages, has_more })
+}
+
+/// Answers history queries of the servers.
+async fn history_responder(
+    bus: Arc<dyn MessageBus>,
+    history: Arc<dyn HistoryStore>,
+    mut qsub: Subscription,
+) {
+    while let Some(msg) = qsub.next().await {
+        if let Some(reply) = &msg.reply {
+            let page = match serde_json::from_slice::<HistoryQuery>(&msg.payload) {
+                Ok(query) => {
+                    let history = history.clone();
+                    tokio::task::spawn_blocking(move || history_page(history.as_ref(), &query))
+                        .await
+                        .unwrap_or_else(|_| Err(JRPCError::history_unavailable()))
+                }
+                Err(err) => Err(JRPCError::invalid_params().with_data(err.to_string())),
+            };
+            // Server waits for the reply only for a while, it is not retried.
+            if let Err(err) = bus
+                .publish(reply, Bytes::from(serde_json::to_string(&page).unwrap()))
+                .await
+            {
+                println!("Cannot reply to history query: {}", err);
+            }
+        }
+        ack(&msg).await;
+    }
+}
+
+/// Stores messages from the servers in the history and routes them
+/// to the servers of the recipients.
+async fn message_router(
+    bus: Arc<dyn MessageBus>,
+    cache: Arc<dyn CacheDB>,
+    history: Arc<dyn HistoryStore>,
+    pending_limits: PendingLimits,
+    mut qsub: Subscription,
+) {
+    while let Some(msg) = qsub.next().await {
+        let mut res = match serde_json::from_slice::<ClientMessage>(&msg.payload) {
+            Ok(res) => res,
+            Err(err) => {
+                dead_letter(
+                    bus.as_ref(),
+                    cache.as_ref(),
+                    mq_messages::PUBLISH_SUBJECT,
+                    &msg.payload,
+                    err.into(),
+                )
+                .await;
+                ack(&msg).await;
+                continue;
+            }
+        };
+
+        // Copy of the routed message, the sender gets the outcome of the original one.
+        // Message is not routed while the cache can't tell if it is a copy.
+        match claim_message(cache.as_ref(), &res).await {
+            Ok(None) => {}
+            Ok(Some(earlier)) => {
+                res.message_id = earlier.message_id;
+                res.seq = earlier.seq;
+                publish_status(bus.as_ref(), &res, earlier.status).await;
+                ack(&msg).await;
+                continue;
+            }
+            Err(err) => {
+                dead_letter(
+                    bus.as_ref(),
+                    cache.as_ref(),
+                    mq_messages::PUBLISH_SUBJECT,
+                    &msg.payload,
+                    err,
+                )
+                .await;
+                remember_status(cache.as_ref(), &res, DeliveryStatus::Failed).await;
+                publish_status(bus.as_ref(), &res, DeliveryStatus::Failed).await;
+                ack(&msg).await;
+                continue;
+            }
+        }
+
+        // Stored in the order of arrival, message that is not stored is not
+        // routed either, its dead letter is the message as it came.
+        if let Err(err) = store_message(&history, &mut res).await {
+            dead_letter(
+                bus.as_ref(),
+                cache.as_ref(),
+                mq_messages::PUBLISH_SUBJECT,
+                &msg.payload,
+                err,
+            )
+            .await;
+            remember_status(cache.as_ref(), &res, DeliveryStatus::Failed).await;
+            publish_status(bus.as_ref(), &res, DeliveryStatus::Failed).await;
+            ack(&msg).await;
+            continue;
+        }
+
+        let bus = bus.clone();
+        let cache = cache.clone();
+        tokio::spawn(async move {
+            match route_message(bus.as_ref(), cache.as_ref(), &res, pending_limits).await {
+                Ok(status) => {
+                    remember_status(cache.as_ref(), &res, status).await;
+                    publish_status(bus.as_ref(), &res, status).await;
+                }
+                Err(err) => {
+                    // Dead letter of the stored message has its id,
+                    // so the replay doesn't store it again.
+                    dead_letter(
+                        bus.as_ref(),
+                        cache.as_ref(),
+                        mq_messages::PUBLISH_SUBJECT,
+                        serde_json::to_string(&res).unwrap().as_bytes(),
+                        err,
+                    )
+                    .await;
+                    remember_status(cache.as_ref(), &res, DeliveryStatus::Failed).await;
+                    publish_status(bus.as_ref(), &res, DeliveryStatus::Failed).await;
+                }
+            }
+            ack(&msg).await;
+        });
+    }
+}
+
+/// Stores the message in the history, assigns its id and timestamp.
+///
+/// A replayed message is routed to every server again, a session that
+/// already wrote it drops the copy by its sequence number.
+async fn store_message(
+    history: &Arc<dyn HistoryStore>,
+    message: &mut ClientMessage,
+) -> Result<(), WorkerError> {
+    // Replay of the message that was stored before its routing failed.
+    if message.message_id.is_some() {
+        return Ok(());
+    }
+    let (sender, recipient, seq) = (message.id, message.recipient, message.seq);
+    let stored = with_backoff(|| {
+        let text = message.msg.clone();
+        async move {
+            Ok(history::blocking(history, move |history| {
+                history.store(sender, recipient, &text, seq)
+            })
+            .await?)
+        }
+    })
+    .await?;
+    message.message_id = Some(stored.id);
+    message.timestamp = Some(stored.timestamp);
+    Ok(())
+}
+
+/// Sends the message to the servers of the recipient, or queues it
+/// if the recipient is offline. Returns status of the message.
+async fn route_message(
+    bus: &dyn MessageBus,
+    cache: &dyn CacheDB,
+    message: &ClientMessage,
+    pending_limits: PendingLimits,
+) -> Result<DeliveryStatus, WorkerError> {
+    let servers = retireve_servers(cache, message.recipient).await?;
+    if servers.is_empty() {
+        with_backoff(|| async { Ok(cache.queue_message(message, pending_limits).await?) }).await?;
+        return Ok(DeliveryStatus::Queued);
+    }
+
+    // Every server is retried on its own, so no server gets the message twice.
+    for server in servers {
+        publish(
+            bus,
+            &mq_messages::send_subject(&server),
+            Bytes::from(serde_json::to_string(message).unwrap()),
+        )
+        .await?;
+    }
+    Ok(DeliveryStatus::Routed)
+}
+
+/// Removes servers that stopped refreshing their liveness key, returns the removed servers.
+///
+/// Users of the removed server don't point to it anymore, messages
+/// for them go to the pending queues until they reconnect.
+pub async fn reap_stale_servers(
+    cache: &dyn CacheDB,
+    bus: &dyn MessageBus,
+) -> Result<Vec<String>, WorkerError> {
+    let mut reaped = Vec::new();
+    for server in cache.stale_servers().await? {
+        if let Err(err) = cache.remove_server(&server).await {
+            println!("Cannot reap server {}: {}", server, err);
+            continue;
+        }
+        println!("Server {} is reaped", server);
+        if let Err(err) = bus
+            .remove_subjects(&mq_messages::server_subjects(&server))
+            .await
+        {
+            println!("Cannot remove subjects of server {}: {}", server, err);
+        }
+        reaped.push(server);
+    }
+    Ok(reaped)
+}
+
+async fn reaper(bus: Arc<dyn MessageBus>, cache: Arc<dyn CacheDB>) {
+    let mut interval = tokio::time::interval(REAP_INTERVAL);
+    loop {
+        interval.tick().await;
+        if let Err(err) = reap_stale_servers(cache.as_ref(), bus.as_ref()).await {
+            println!("Cannot read servers from the cache: {}", err);
+        }
+    }
+}
+
+/// Publishes the dead letter to its subject, the letter is kept if publishing fails.
+///
+/// Returns the replayed letter, `None` if there is no letter with the id.
+pub async fn replay_dead_letter(
+    cache: &dyn CacheDB,
+    bus: &dyn MessageBus,
+    id: u64,
+) -> Result<Option<DeadLetter>, WorkerError> {
+    let Some(letter) = cache.take_dead_letter(id).await? else {
+        return Ok(None);
+    };
+    if let Err(err) = bus
+        .publish(&letter.subject, Bytes::from(letter.payload.clone()))
+        .await
+    {
+        let _ = cache.add_dead_letter(letter).await;
+        return Err(err.into());
+    }
+    Ok(Some(letter))
+}
+
+/// Runs the worker on the given bus until the bus is gone.
+///
+/// With `InProcessBus` and `MemoryCache` the worker shares
+/// the process with the server, no broker is needed.
+/// Fails if the worker cannot subscribe to its subjects.
+pub async fn run(
+    bus: Arc<dyn MessageBus>,
+    cache: Arc<dyn CacheDB>,
+    history: Arc<dyn HistoryStore>,
+    pending_limits: PendingLimits,
+    queue_group: String,
+) -> Result<(), WorkerError> {
+    let history_sub = bus
+        .queue_subscribe(mq_messages::HISTORY_SUBJECT, &queue_group)
+        .await?;
+    let receipt_sub = bus
+        .queue_subscribe(mq_messages::RECEIPT_SUBJECT, &queue_group)
+        .await?;
+    let room_sub = bus
+        .queue_subscribe(mq_messages::ROOM_PUBLISH_SUBJECT, &queue_group)
+        .await?;
+    let typing_sub = bus
+        .queue_subscribe(mq_messages::TYPING_PUBLISH_SUBJECT, &queue_group)
+        .await?;
+    let message_sub = bus
+        .queue_subscribe(mq_messages::PUBLISH_SUBJECT, &queue_group)
+        .await?;
+
+    tokio::spawn(history_responder(bus.clone(), history.clone(), history_sub));
+    tokio::spawn(receipt_router(
+        bus.clone(),
+        cache.clone(),
+        history.clone(),
+        receipt_sub,
+    ));
+    tokio::spawn(room_router(bus.clone(), cache.clone(), room_sub));
+    tokio::spawn(typing_router(bus.clone(), cache.clone(), typing_sub));
+    tokio::spawn(reaper(bus.clone(), cache.clone()));
+    message_router(bus, cache, history, pending_limits, message_sub).await;
+    Ok(())
+}
@@ -0,0 +1,96 @@
+use std::{future::Future, time::Duration};
+
+use crate::error::WorkerError;
+
+/// Attempts of every step before the message goes to the dead letters.
+pub const MAX_ATTEMPTS: u32 = 5;
+
+/// Wait after the first failed attempt, it doubles after every next one.
+const INITIAL_BACKOFF: Duration = Duration::from_millis(100);
+const MAX_BACKOFF: Duration = Duration::from_secs(5);
+
+/// Runs the step until it succeeds or fails `MAX_ATTEMPTS` times,
+/// returns the error of the last attempt then.
+pub async fn with_backoff<T, F, Fut>(mut step: F) -> Result<T, WorkerError>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, WorkerError>>,
+{
+    let mut backoff = INITIAL_BACKOFF;
+    let mut attempt = 1;
+    loop {
+        match step().await {
+            Ok(value) => return Ok(value),
+            Err(err) if attempt >= MAX_ATTEMPTS => return Err(err),
+            Err(err) => {
+                println!("Attempt {} failed, retrying: {}", attempt, err);
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+                attempt += 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use hcwc_cache::CacheError;
+    use tokio::time::Instant;
+
+    use super::*;
+
+    fn failure() -> WorkerError {
+        WorkerError::Cache(CacheError::Poisoned)
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn first_success_is_not_retried() {
+        let attempts = Cell::new(0);
+        let start = Instant::now();
+        let res = with_backoff(|| async {
+            attempts.set(attempts.get() + 1);
+            Ok::<_, WorkerError>(7)
+        })
+        .await;
+        assert_eq!(res.unwrap(), 7);
+        assert_eq!(attempts.get(), 1);
+        assert_eq!(start.elapsed(), Duration::ZERO);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn retries_until_success() {
+        let attempts = Cell::new(0);
+        let start = Instant::now();
+        let res = with_backoff(|| async {
+            attempts.set(attempts.get() + 1);
+            if attempts.get() < 3 {
+                Err(failure())
+            } else {
+                Ok(())
+            }
+        })
+        .await;
+        assert!(res.is_ok());
+        assert_eq!(attempts.get(), 3);
+        assert_eq!(start.elapsed(), Duration::from_millis(100 + 200));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn gives_up_after_max_attempts() {
+        let attempts = Cell::new(0);
+        let start = Instant::now();
+        let res = with_backoff(|| async {
+            attempts.set(attempts.get() + 1);
+            Err::<(), _>(failure())
+        })
+        .await;
+        assert!(matches!(res, Err(WorkerError::Cache(_))));
+        assert_eq!(attempts.get(), MAX_ATTEMPTS);
+        assert_eq!(
+            start.elapsed(),
+            Duration::from_millis(100 + 200 + 400 + 800)
+        );
+    }
+}
@@ -0,0 +1,160 @@
+//! Every history store reads the same pages of a conversation.
+
+use std::path::PathBuf;
+
+use hcwc_protocol::{mq_messages::SequenceRange, responses::HistoryMessage};
+use rusqlite::Connection;
+use worker::history::{Cursor, HistoryStore, MemoryHistory, SqliteHistory};
+
+/// Every store, empty.
+fn stores() -> Vec<(&'static str, Box<dyn HistoryStore>)> {
+    vec![
+        ("memory", Box::new(MemoryHistory::new())),
+        ("sqlite", Box::new(SqliteHistory::open(":memory:").unwrap())),
+    ]
+}
+
+/// Conversation of users 1 and 2 with messages 1..=5, and a message of another one.
+fn fill(history: &dyn HistoryStore) {
+    for i in 1..=5 {
+        let (sender, recipient) = if i % 2 == 0 { (2, 1) } else { (1, 2) };
+        history
+            .store(sender, recipient, &format!("message {}", i), Some(i))
+            .unwrap();
+    }
+    history.store(1, 3, "other", Some(1)).unwrap();
+}
+
+fn ids(messages: &[HistoryMessage]) -> Vec<u64> {
+    messages.iter().map(|message| message.id).collect()
+}
+
+fn cursor(before: Option<u64>, after: Option<u64>, limit: usize) -> Cursor {
+    Cursor {
+        before,
+        after,
+        limit,
+    }
+}
+
+#[test]
+fn newest_page_without_cursor() {
+    for (name, history) in stores() {
+        fill(history.as_ref());
+        let page = history.conversation(1, 2, cursor(None, None, 3)).unwrap();
+        assert_eq!(ids(&page), vec![3, 4, 5], "{}", name);
+        // Both users read the same conversation.
+        let page = history.conversation(2, 1, cursor(None, None, 10)).unwrap();
+        assert_eq!(ids(&page), vec![1, 2, 3, 4, 5], "{}", name);
+    }
+}
+
+#[test]
+fn before_cursor_pages_backward() {
+    for (name, history) in stores() {
+        fill(history.as_ref());
+        let page = history
+            .conversation(1, 2, cursor(Some(4), None, 2))
+            .unwrap();
+        assert_eq!(ids(&page), vec![2, 3], "{}", name);
+        let page = history
+            .conversation(1, 2, cursor(Some(2), None, 2))
+            .unwrap();
+        assert_eq!(ids(&page), vec![1], "{}", name);
+    }
+}
+
+#[test]
+fn after_cursor_pages_forward() {
+    for (name, history) in stores() {
+        fill(history.as_ref());
+        let page = history
+            .conversation(1, 2, cursor(None, Some(1), 2))
+            .unwrap();
+        assert_eq!(ids(&page), vec![2, 3], "{}", name);
+        let page = history
+            .conversation(1, 2, cursor(None, Some(4), 2))
+            .unwrap();
+        assert_eq!(ids(&page), vec![5], "{}", name);
+        let page = history
+            .conversation(1, 2, cursor(Some(5), Some(1), 10))
+            .unwrap();
+        assert_eq!(ids(&page), vec![2, 3, 4], "{}", name);
+    }
+}
+
+#[test]
+fn sequence_is_per_direction() {
+    for (name, history) in stores() {
+        fill(history.as_ref());
+        let range = SequenceRange { from: 1, to: 5 };
+        let page = history.sequence(1, 2, range, 10).unwrap();
+        assert_eq!(ids(&page), vec![1, 3, 5], "{}", name);
+        let page = history.sequence(1, 2, range, 2).unwrap();
+        assert_eq!(ids(&page), vec![1, 3], "{}", name);
+        let page = history
+            .sequence(2, 1, SequenceRange { from: 3, to: 4 }, 10)
+            .unwrap();
+        assert_eq!(ids(&page), vec![4], "{}", name);
+    }
+}
+
+#[test]
+fn message_by_id() {
+    for (name, history) in stores() {
+        fill(history.as_ref());
+        let message = history.message(4).unwrap().unwrap();
+        assert_eq!(
+            (message.sender, message.recipient, message.seq),
+            (2, 1, Some(4)),
+            "{}",
+            name
+        );
+        assert!(history.message(42).unwrap().is_none(), "{}", name);
+    }
+}
+
+/// Database file removed when the test ends.
+struct TempDb(PathBuf);
+
+impl Drop for TempDb {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+#[test]
+fn sqlite_adds_seq_column_to_old_database() {
+    let db = TempDb(std::env::temp_dir().join(format!("hcwc_history_{}.db", std::process::id())));
+    let connection = Connection::open(&db.0).unwrap();
+    connection
+        .execute_batch(
+            "CREATE TABLE messages (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                conversation TEXT NOT NULL,
+                sender INTEGER NOT NULL,
+                recipient INTEGER NOT NULL,
+                message TEXT NOT NULL,
+                timestamp INTEGER NOT NULL
+            );
+            INSERT INTO messages (conversation, sender, recipient, message, timestamp)
+                VALUES ('1:2', 1, 2, 'old', 0);",
+        )
+        .unwrap();
+    drop(connection);
+
+    let history = SqliteHistory::open(&db.0).unwrap();
+    let old = history.message(1).unwrap().unwrap();
+    assert_eq!((old.message.as_str(), old.seq), ("old", None));
+    history.store(1, 2, "new", Some(1)).unwrap();
+    let page = history
+        .sequence(1, 2, SequenceRange { from: 1, to: 1 }, 10)
+        .unwrap();
+    assert_eq!(ids(&page), vec![2]);
+    drop(history);
+
+    // Migrated database opens again.
+    let history = SqliteHistory::open(&db.0).unwrap();
+    let page = history.conversation(1, 2, cursor(None, None, 10)).unwrap();
+    assert_eq!(ids(&page), vec![1, 2]);
+}